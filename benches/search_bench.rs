@@ -0,0 +1,105 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use dashmap::DashMap;
+use rust_high_performance_api_server::{
+    models::Task,
+    search::{self, SearchIndex},
+};
+use std::sync::Arc;
+
+const WORDS: &[&str] = &[
+    "deploy",
+    "database",
+    "migration",
+    "review",
+    "release",
+    "billing",
+    "invoice",
+    "customer",
+    "dashboard",
+    "latency",
+    "cache",
+    "refactor",
+    "login",
+    "password",
+    "report",
+    "export",
+    "import",
+    "search",
+    "index",
+    "timeout",
+    "retry",
+    "queue",
+    "worker",
+    "schedule",
+    "notification",
+    "email",
+    "upload",
+    "download",
+    "permission",
+    "audit",
+    "backup",
+    "restore",
+];
+
+/// Deterministic pseudo-random tasks with titles and short descriptions
+fn tasks(count: usize) -> Arc<DashMap<uuid::Uuid, Task>> {
+    let tasks = Arc::new(DashMap::new());
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        WORDS[(seed % WORDS.len() as u64) as usize]
+    };
+    for _ in 0..count {
+        let title = (0..4).map(|_| next()).collect::<Vec<_>>().join(" ");
+        let description = (0..20).map(|_| next()).collect::<Vec<_>>().join(" ");
+        let task = Task::new(title, Some(description));
+        tasks.insert(task.id, task);
+    }
+    tasks
+}
+
+fn bench_search(c: &mut Criterion) {
+    let store = tasks(10_000);
+    let index = SearchIndex::default();
+    index.rebuild(&store);
+
+    for (name, query) in [
+        ("search_term", "migration"),
+        ("search_two_terms", "billing invoice"),
+        ("search_prefix", "notif*"),
+        ("search_phrase", "\"database migration\""),
+        ("search_fuzzy", "permision~"),
+    ] {
+        let clauses = search::parse(query).unwrap();
+        c.bench_function(name, |b| b.iter(|| index.search(&clauses)));
+    }
+}
+
+fn bench_indexing(c: &mut Criterion) {
+    let store = tasks(10_000);
+    c.bench_function("rebuild_10k", |b| {
+        b.iter(|| SearchIndex::default().rebuild(&store))
+    });
+
+    let index = SearchIndex::default();
+    index.rebuild(&store);
+    let mut task = store.iter().next().unwrap().value().clone();
+    let mut flip = false;
+    c.bench_function("reindex_task", |b| {
+        b.iter(|| {
+            flip = !flip;
+            task.title = if flip {
+                "Review the release"
+            } else {
+                "Export the report"
+            }
+            .to_string();
+            index.reindex(task.id, Some(&task));
+        })
+    });
+}
+
+criterion_group!(benches, bench_search, bench_indexing);
+criterion_main!(benches);
@@ -0,0 +1,428 @@
+use crate::{
+    blobs::{Blob, BlobReader, BlobStore, BlobUpload, LocalBlobStore},
+    config::AttachmentsConfig,
+    errors::AppError,
+    health::Heartbeat,
+    tenancy::Tenants,
+};
+use actix_web::{
+    http::header::{
+        Charset, ContentDisposition, DispositionParam, DispositionType, ExtendedValue, HeaderMap,
+        Range, IF_RANGE, RANGE,
+    },
+    mime::{self, Mime},
+    web::{self, Bytes},
+};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use futures_util::{stream, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Media types accepted unless `attachments.allowed_types` says otherwise
+pub const DEFAULT_ALLOWED_TYPES: &str =
+    "image/*,text/plain,application/pdf,application/json,application/zip,application/gzip";
+
+/// Longest accepted file name, in bytes
+pub const MAX_FILENAME_BYTES: usize = 255;
+
+/// Size of the chunks downloads are read in
+const READ_CHUNK_BYTES: u64 = 64 * 1024;
+
+/// A file attached to a task
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    pub id: Uuid,
+    pub task_id: Uuid,
+    pub filename: String,
+    pub content_type: String,
+    pub size: u64,
+    /// Hex SHA-256 of the content, naming its blob
+    pub sha256: String,
+    /// Principal that uploaded the file
+    pub uploader_id: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Query parameters of a non-multipart upload
+#[derive(Debug, Default, Deserialize)]
+pub struct UploadQuery {
+    /// Name of the uploaded file
+    pub filename: Option<String>,
+}
+
+/// Parses a comma-separated media type allowlist
+pub fn parse_allowed_types(raw: &str) -> Result<Vec<Mime>, String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            entry
+                .parse::<Mime>()
+                .map_err(|_| format!("'{}' is not a media type", entry))
+        })
+        .collect()
+}
+
+/// The last path segment of an uploaded file name, checked for use in
+/// headers and listings
+pub fn sanitize_filename(raw: &str) -> Result<String, AppError> {
+    let name = raw.rsplit(['/', '\\']).next().unwrap_or_default().trim();
+    let invalid = |reason: &str| AppError::Validation(format!("Invalid file name: {}", reason));
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(invalid("it is empty"));
+    }
+    if name.len() > MAX_FILENAME_BYTES {
+        return Err(invalid(&format!(
+            "longer than {} bytes",
+            MAX_FILENAME_BYTES
+        )));
+    }
+    if name.chars().any(char::is_control) {
+        return Err(invalid("it contains control characters"));
+    }
+    Ok(name.to_string())
+}
+
+/// `Content-Disposition: attachment` naming the file, with an ASCII
+/// fallback `filename` and the exact name as UTF-8 `filename*`
+pub fn content_disposition(filename: &str) -> ContentDisposition {
+    let fallback: String = filename
+        .chars()
+        .map(|c| if c.is_ascii() { c } else { '_' })
+        .collect();
+    let mut parameters = vec![DispositionParam::Filename(fallback)];
+    if !filename.is_ascii() {
+        parameters.push(DispositionParam::FilenameExt(ExtendedValue {
+            charset: Charset::Ext("UTF-8".to_string()),
+            language_tag: None,
+            value: filename.as_bytes().to_vec(),
+        }));
+    }
+    ContentDisposition {
+        disposition: DispositionType::Attachment,
+        parameters,
+    }
+}
+
+/// The byte range a download asks for, as inclusive offsets.
+///
+/// `None` serves the whole blob: when no range is asked for, the validator in
+/// `If-Range` no longer matches, or several ranges are asked for at once.
+pub fn requested_range(
+    headers: &HeaderMap,
+    size: u64,
+    etag: &str,
+) -> Result<Option<(u64, u64)>, AppError> {
+    let Some(range) = headers.get(RANGE).and_then(|value| value.to_str().ok()) else {
+        return Ok(None);
+    };
+    if headers
+        .get(IF_RANGE)
+        .is_some_and(|validator| validator.as_bytes() != etag.as_bytes())
+    {
+        return Ok(None);
+    }
+    match range.parse::<Range>() {
+        Ok(Range::Bytes(specs)) if specs.len() == 1 => specs[0]
+            .to_satisfiable_range(size)
+            .map(Some)
+            .ok_or(AppError::RangeNotSatisfiable { size }),
+        _ => Ok(None),
+    }
+}
+
+/// Streams `len` bytes of a blob, reading off the async workers
+pub fn read_stream(
+    reader: Box<dyn BlobReader>,
+    len: u64,
+) -> impl Stream<Item = Result<Bytes, io::Error>> {
+    stream::try_unfold((reader, len), |(mut reader, remaining)| async move {
+        if remaining == 0 {
+            return Ok(None);
+        }
+        let (reader, chunk) = web::block(move || {
+            let mut chunk = vec![0; remaining.min(READ_CHUNK_BYTES) as usize];
+            reader.read_exact(&mut chunk)?;
+            Ok::<_, io::Error>((reader, chunk))
+        })
+        .await
+        .map_err(io::Error::other)??;
+        let remaining = remaining - chunk.len() as u64;
+        Ok(Some((Bytes::from(chunk), (reader, remaining))))
+    })
+}
+
+/// Opens a blob positioned at `offset`
+pub async fn open_at(
+    store: Arc<dyn BlobStore>,
+    digest: String,
+    offset: u64,
+) -> Result<Box<dyn BlobReader>, AppError> {
+    web::block(move || {
+        let mut reader = store.open(&digest)?;
+        reader.seek(SeekFrom::Start(offset))?;
+        Ok::<_, io::Error>(reader)
+    })
+    .await
+    .map_err(|e| AppError::Internal(e.to_string()))?
+    .map_err(AppError::from)
+}
+
+/// A blob upload that has been written but not committed yet
+pub struct StagedUpload {
+    pub filename: String,
+    pub content_type: Mime,
+    pub size: u64,
+    pub upload: Box<dyn BlobUpload>,
+}
+
+/// Writes a body to a new blob upload off the async workers
+pub async fn stage<S>(
+    store: Arc<dyn BlobStore>,
+    mut body: S,
+) -> Result<(Box<dyn BlobUpload>, u64), AppError>
+where
+    S: Stream<Item = Result<Bytes, AppError>> + Unpin,
+{
+    let blocking = |e: actix_web::error::BlockingError| AppError::Internal(e.to_string());
+    let mut upload = web::block(move || store.begin())
+        .await
+        .map_err(blocking)??;
+    let mut size = 0;
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk?;
+        size += chunk.len() as u64;
+        upload = web::block(move || upload.write_all(&chunk).map(|_| upload))
+            .await
+            .map_err(blocking)??;
+    }
+    Ok((upload, size))
+}
+
+/// Attachments of a tenant's tasks, by task, in upload order.
+///
+/// They are kept while their task is in the trash and dropped with it.
+#[derive(Default)]
+pub struct Attachments {
+    by_task: DashMap<Uuid, Vec<Attachment>>,
+}
+
+impl Attachments {
+    pub fn add(&self, attachment: Attachment) {
+        self.by_task
+            .entry(attachment.task_id)
+            .or_default()
+            .push(attachment);
+    }
+
+    pub fn list(&self, task_id: &Uuid) -> Vec<Attachment> {
+        self.by_task
+            .get(task_id)
+            .map(|attachments| attachments.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn get(&self, task_id: &Uuid, id: &Uuid) -> Option<Attachment> {
+        self.by_task
+            .get(task_id)?
+            .iter()
+            .find(|attachment| attachment.id == *id)
+            .cloned()
+    }
+
+    /// Removes one attachment once `check` accepts it
+    pub fn remove_one(
+        &self,
+        task_id: &Uuid,
+        id: &Uuid,
+        check: impl FnOnce(&Attachment) -> Result<(), AppError>,
+    ) -> Result<Option<Attachment>, AppError> {
+        let Some(mut attachments) = self.by_task.get_mut(task_id) else {
+            return Ok(None);
+        };
+        let Some(index) = attachments
+            .iter()
+            .position(|attachment| attachment.id == *id)
+        else {
+            return Ok(None);
+        };
+        check(&attachments[index])?;
+        Ok(Some(attachments.remove(index)))
+    }
+
+    /// Removes every attachment of a task, returning the bytes they held
+    pub fn remove(&self, task_id: &Uuid) -> u64 {
+        self.by_task.remove(task_id).map_or(0, |(_, attachments)| {
+            attachments.iter().map(|a| a.size).sum()
+        })
+    }
+
+    /// Digests of every blob the tenant's attachments use
+    pub fn digests(&self) -> HashSet<String> {
+        self.by_task
+            .iter()
+            .flat_map(|attachments| {
+                attachments
+                    .iter()
+                    .map(|attachment| attachment.sha256.clone())
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    pub fn references(&self, digest: &str) -> bool {
+        self.by_task.iter().any(|attachments| {
+            attachments
+                .iter()
+                .any(|attachment| attachment.sha256 == digest)
+        })
+    }
+}
+
+struct Settings {
+    store: Arc<dyn BlobStore>,
+    allowed_types: Vec<Mime>,
+}
+
+/// The blob store and upload rules shared by every tenant
+#[derive(Clone)]
+pub struct AttachmentStorage {
+    settings: Arc<RwLock<Settings>>,
+    /// Held shared while an upload commits its blob and references it, and
+    /// exclusively while garbage collection removes a blob, so a blob is never
+    /// removed between being stored and being referenced
+    collecting: Arc<RwLock<()>>,
+}
+
+impl Default for AttachmentStorage {
+    fn default() -> Self {
+        let settings = Settings {
+            store: Arc::new(LocalBlobStore::new("data/attachments")),
+            allowed_types: parse_allowed_types(DEFAULT_ALLOWED_TYPES).expect("default types parse"),
+        };
+        Self {
+            settings: Arc::new(RwLock::new(settings)),
+            collecting: Arc::new(RwLock::new(())),
+        }
+    }
+}
+
+impl AttachmentStorage {
+    /// Uses the local store and allowlist of `config`
+    pub fn configure(&self, config: &AttachmentsConfig) -> Result<(), AppError> {
+        let allowed_types =
+            parse_allowed_types(&config.allowed_types).map_err(AppError::Validation)?;
+        *self.settings.write().unwrap() = Settings {
+            store: Arc::new(LocalBlobStore::new(&config.path)),
+            allowed_types,
+        };
+        Ok(())
+    }
+
+    /// Replaces the blob store, for other storage backends
+    pub fn set_store(&self, store: Arc<dyn BlobStore>) {
+        self.settings.write().unwrap().store = store;
+    }
+
+    pub fn store(&self) -> Arc<dyn BlobStore> {
+        Arc::clone(&self.settings.read().unwrap().store)
+    }
+
+    /// The media type of an upload, if the allowlist accepts it; uploads
+    /// without one are `application/octet-stream`
+    pub fn check_type(&self, declared: Option<&Mime>) -> Result<Mime, AppError> {
+        let content_type = declared.cloned().unwrap_or(mime::APPLICATION_OCTET_STREAM);
+        let allowed = self
+            .settings
+            .read()
+            .unwrap()
+            .allowed_types
+            .iter()
+            .any(|allowed| {
+                (allowed.type_() == mime::STAR || allowed.type_() == content_type.type_())
+                    && (allowed.subtype() == mime::STAR
+                        || allowed.subtype() == content_type.subtype())
+            });
+        if !allowed {
+            return Err(AppError::UnsupportedMediaType(format!(
+                "Attachments of type {} are not accepted",
+                content_type.essence_str()
+            )));
+        }
+        Ok(content_type)
+    }
+
+    /// Commits a staged upload and hands the blob to `reference`, which
+    /// records the attachment using it
+    pub fn commit<T>(
+        &self,
+        upload: Box<dyn BlobUpload>,
+        reference: impl FnOnce(Blob) -> Result<T, AppError>,
+    ) -> Result<T, AppError> {
+        let _committing = self.collecting.read().unwrap();
+        reference(upload.commit()?)
+    }
+
+    /// Removes blobs no attachment of any tenant uses, returning how many
+    pub fn collect_garbage(&self, tenants: &Tenants) -> io::Result<usize> {
+        let store = self.store();
+        let referenced: HashSet<String> = tenants
+            .entries()
+            .iter()
+            .flat_map(|tenant| tenant.attachments.digests())
+            .collect();
+
+        let mut removed = 0;
+        for digest in store.list()? {
+            if referenced.contains(&digest) {
+                continue;
+            }
+            // Checked again with commits paused: an upload of the same
+            // content may have referenced the blob since the scan
+            let _collecting = self.collecting.write().unwrap();
+            if tenants
+                .entries()
+                .iter()
+                .any(|tenant| tenant.attachments.references(&digest))
+            {
+                continue;
+            }
+            store.delete(&digest)?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+}
+
+/// Removes unreferenced blobs at startup and then every `interval`
+pub async fn collect_blobs(
+    storage: AttachmentStorage,
+    tenants: Tenants,
+    interval: Duration,
+    heartbeat: Heartbeat,
+    shutdown: CancellationToken,
+) {
+    let mut ticker = tokio::time::interval(interval);
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = shutdown.cancelled() => return,
+        }
+        heartbeat.beat();
+        let (storage, tenants) = (storage.clone(), tenants.clone());
+        match tokio::task::spawn_blocking(move || storage.collect_garbage(&tenants)).await {
+            Ok(Ok(0)) => {}
+            Ok(Ok(removed)) => info!(removed, "Removed unreferenced attachment blobs"),
+            Ok(Err(e)) => warn!(error = %e, "Failed to collect attachment blobs"),
+            Err(e) => warn!(error = %e, "Attachment blob collection panicked"),
+        }
+    }
+}
@@ -0,0 +1,475 @@
+use crate::{
+    config::{ApiConfig, AuditConfig},
+    errors::AppError,
+    network::ClientIp,
+    policy::Principal,
+    state::AppState,
+};
+use actix_web::{dev::Payload, web, FromRequest, HttpMessage, HttpRequest};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeSet, VecDeque},
+    fs::{File, OpenOptions},
+    future::{ready, Ready},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    iter,
+    net::IpAddr,
+    path::Path,
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+};
+use tracing::error;
+use uuid::Uuid;
+
+/// Principal recorded for changes made by the server itself, such as reloads
+pub const SYSTEM_PRINCIPAL: &str = "system";
+
+/// Most records a single query returns
+const MAX_QUERY_LIMIT: usize = 1000;
+
+/// Records waiting for the audit file before further appends are refused
+const WRITE_QUEUE_CAPACITY: usize = 65_536;
+
+/// One field that differs between the before and after state
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+/// Field-level difference between two JSON documents.
+///
+/// Top-level fields of objects are compared; a missing side counts as
+/// `null`, so creations and deletions list every field.
+pub fn field_diff(before: Option<&Value>, after: Option<&Value>) -> Vec<FieldChange> {
+    let fields = |value: Option<&Value>| match value {
+        Some(Value::Object(map)) => map.clone(),
+        _ => serde_json::Map::new(),
+    };
+    let (before, after) = (fields(before), fields(after));
+
+    let names: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let old = before.get(name).cloned().unwrap_or(Value::Null);
+            let new = after.get(name).cloned().unwrap_or(Value::Null);
+            (old != new).then(|| FieldChange {
+                field: name.clone(),
+                before: old,
+                after: new,
+            })
+        })
+        .collect()
+}
+
+/// An audited operation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Position in the log, increasing with every record
+    pub seq: u64,
+    pub timestamp: DateTime<Utc>,
+    pub tenant: String,
+    pub principal: String,
+    /// Trace id assigned by the request logging middleware
+    pub request_id: Option<Uuid>,
+    pub client_ip: Option<IpAddr>,
+    /// What was done, e.g. `task.update` or `tenant.suspend`
+    pub operation: String,
+    /// Id of the task or other resource acted on
+    pub target_id: String,
+    pub changes: Vec<FieldChange>,
+}
+
+/// Filters accepted by the audit query endpoint
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    pub principal: Option<String>,
+    pub task_id: Option<Uuid>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// Newest matching records returned (default 100, at most 1000)
+    pub limit: Option<usize>,
+}
+
+struct AuditInner {
+    records: VecDeque<AuditRecord>,
+    next_seq: u64,
+    writer: Option<AuditWriter>,
+    max_age: Duration,
+    max_records: usize,
+}
+
+impl AuditInner {
+    /// Drops records beyond the retention limits, oldest first
+    fn prune(&mut self) {
+        // A retention reaching before the earliest representable time keeps everything
+        let cutoff = Utc::now()
+            .checked_sub_signed(self.max_age)
+            .unwrap_or(DateTime::<Utc>::MIN_UTC);
+        while self
+            .records
+            .front()
+            .is_some_and(|r| r.timestamp < cutoff || self.records.len() > self.max_records)
+        {
+            self.records.pop_front();
+        }
+    }
+}
+
+enum WriterMessage {
+    Line(Vec<u8>),
+    /// Answered once every line queued before it has been written
+    Sync(mpsc::Sender<()>),
+}
+
+/// Queue of lines for the thread appending them to the audit file
+struct AuditWriter {
+    queue: SyncSender<WriterMessage>,
+    /// First write failure; appends are refused from then on
+    failure: Arc<Mutex<Option<String>>>,
+}
+
+impl AuditWriter {
+    fn start(file: File) -> io::Result<Self> {
+        let (queue, received) = mpsc::sync_channel(WRITE_QUEUE_CAPACITY);
+        let failure = Arc::new(Mutex::new(None));
+        let failed = Arc::clone(&failure);
+        thread::Builder::new()
+            .name("audit-writer".to_string())
+            .spawn(move || write_lines(file, received, failed))?;
+        Ok(Self { queue, failure })
+    }
+
+    fn check(&self) -> Result<(), AppError> {
+        match &*self.failure.lock().unwrap() {
+            Some(e) => Err(AppError::Internal(format!(
+                "Failed to write audit record: {}",
+                e
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Appends queued lines to `file`, flushing once per batch of lines that
+/// arrived together, until every queue handle is dropped
+fn write_lines(file: File, received: Receiver<WriterMessage>, failure: Arc<Mutex<Option<String>>>) {
+    let mut file = BufWriter::new(file);
+    let mut failed = false;
+    while let Ok(first) = received.recv() {
+        let mut synced = Vec::new();
+        let mut written = Ok(());
+        for message in iter::once(first).chain(received.try_iter()) {
+            match message {
+                WriterMessage::Line(line) if !failed && written.is_ok() => {
+                    written = file.write_all(&line);
+                }
+                WriterMessage::Line(_) => {}
+                WriterMessage::Sync(done) => synced.push(done),
+            }
+        }
+        if !failed {
+            if let Err(e) = written.and_then(|_| file.flush()) {
+                error!(error = %e, "Failed to write audit records; refusing further changes");
+                *failure.lock().unwrap() = Some(e.to_string());
+                failed = true;
+            }
+        }
+        for done in synced {
+            let _ = done.send(());
+        }
+    }
+}
+
+/// Append-only audit log.
+///
+/// Records are kept in memory for queries and, when `audit.path` is set,
+/// queued for an NDJSON file before the operation they describe is applied.
+/// A dedicated thread appends them, so a slow disk never holds the log's
+/// lock or an executor thread; once a write fails, or the queue is full,
+/// every further append fails and so does the operation it records.
+/// Records older than the retention limits are dropped from memory as new
+/// ones arrive, and from the file when it is reopened at startup.
+#[derive(Clone)]
+pub struct AuditLog {
+    inner: Arc<Mutex<AuditInner>>,
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(AuditInner {
+                records: VecDeque::new(),
+                next_seq: 1,
+                writer: None,
+                max_age: Duration::days(90),
+                max_records: 100_000,
+            })),
+        }
+    }
+}
+
+impl AuditLog {
+    /// Applies the retention settings and opens the audit file, if any,
+    /// loading the records it still retains
+    pub fn open(&self, config: &AuditConfig) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.max_age = i64::try_from(config.retention_days)
+            .ok()
+            .and_then(Duration::try_days)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "audit.retention_days {} is out of range",
+                        config.retention_days
+                    ),
+                )
+            })?;
+        inner.max_records = config.max_records;
+
+        let Some(path) = &config.path else {
+            inner.prune();
+            return Ok(());
+        };
+        let path = Path::new(path);
+        if path.exists() {
+            for line in BufReader::new(File::open(path)?).lines() {
+                let record: AuditRecord = serde_json::from_str(&line?)?;
+                inner.next_seq = inner.next_seq.max(record.seq + 1);
+                inner.records.push_back(record);
+            }
+        }
+        inner.prune();
+
+        // Rewrite the file with only the retained records, then append to it
+        let tmp = path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        for record in &inner.records {
+            serde_json::to_writer(&mut file, record)?;
+            file.write_all(b"\n")?;
+        }
+        file.sync_all()?;
+        std::fs::rename(&tmp, path)?;
+        let file = OpenOptions::new().append(true).open(path)?;
+        inner.writer = Some(AuditWriter::start(file)?);
+        Ok(())
+    }
+
+    /// Appends a record, failing if it cannot be queued for the audit file
+    /// or an earlier record could not be written
+    pub fn append(&self, mut record: AuditRecord) -> Result<(), AppError> {
+        let mut inner = self.inner.lock().unwrap();
+        record.seq = inner.next_seq;
+
+        if let Some(writer) = &inner.writer {
+            writer.check()?;
+            let mut line = serde_json::to_vec(&record)?;
+            line.push(b'\n');
+            writer
+                .queue
+                .try_send(WriterMessage::Line(line))
+                .map_err(|e| {
+                    AppError::Internal(match e {
+                        TrySendError::Full(_) => "The audit file is falling behind".to_string(),
+                        TrySendError::Disconnected(_) => "The audit writer has stopped".to_string(),
+                    })
+                })?;
+        }
+
+        inner.next_seq += 1;
+        inner.records.push_back(record);
+        inner.prune();
+        Ok(())
+    }
+
+    /// Waits until every record appended so far is in the audit file,
+    /// failing if any could not be written
+    pub fn sync(&self) -> Result<(), AppError> {
+        let Some((queue, failure)) = self
+            .inner
+            .lock()
+            .unwrap()
+            .writer
+            .as_ref()
+            .map(|writer| (writer.queue.clone(), Arc::clone(&writer.failure)))
+        else {
+            return Ok(());
+        };
+        let (done, wait) = mpsc::channel();
+        if queue.send(WriterMessage::Sync(done)).is_err() || wait.recv().is_err() {
+            return Err(AppError::Internal(
+                "The audit writer has stopped".to_string(),
+            ));
+        }
+        AuditWriter { queue, failure }.check()
+    }
+
+    /// Records of `tenant` matching `query`, oldest first
+    pub fn query(&self, tenant: &str, query: &AuditQuery) -> Vec<AuditRecord> {
+        let limit = query.limit.unwrap_or(100).min(MAX_QUERY_LIMIT);
+        let task_id = query.task_id.map(|id| id.to_string());
+        let inner = self.inner.lock().unwrap();
+
+        let mut matches: Vec<AuditRecord> = inner
+            .records
+            .iter()
+            .rev()
+            .filter(|r| r.tenant == tenant)
+            .filter(|r| query.principal.as_ref().is_none_or(|p| *p == r.principal))
+            .filter(|r| task_id.as_ref().is_none_or(|id| *id == r.target_id))
+            .filter(|r| query.since.is_none_or(|since| r.timestamp >= since))
+            .filter(|r| query.until.is_none_or(|until| r.timestamp < until))
+            .take(limit)
+            .cloned()
+            .collect();
+        matches.reverse();
+        matches
+    }
+
+    /// Records which API keys and roles changed between two configurations.
+    ///
+    /// Keys themselves are never written; a rotated key shows as a change
+    /// between two redacted values.
+    pub fn record_api_changes(&self, old: &ApiConfig, new: &ApiConfig) -> Result<(), AppError> {
+        let describe = |api: &ApiConfig| {
+            let mut principals = serde_json::Map::new();
+            principals.insert(
+                "api_key".to_string(),
+                serde_json::json!(fingerprint(api.api_key.expose())),
+            );
+            for (id, principal) in &api.principals {
+                principals.insert(
+                    format!("principals.{}", id),
+                    serde_json::json!({
+                        "key": fingerprint(principal.key.expose()),
+                        "role": principal.role,
+                        "tenant": principal.tenant,
+                    }),
+                );
+            }
+            Value::Object(principals)
+        };
+
+        let (old, new) = (describe(old), describe(new));
+        if old == new {
+            return Ok(());
+        }
+        self.record_system(
+            crate::tenancy::DEFAULT_TENANT,
+            "config.api_keys",
+            "api",
+            Some(&old),
+            Some(&new),
+        )
+    }
+
+    /// Writes a record of an operation the server performed on its own,
+    /// such as a reload or a background purge
+    pub fn record_system<T: Serialize>(
+        &self,
+        tenant: &str,
+        operation: &str,
+        target_id: impl ToString,
+        before: Option<&T>,
+        after: Option<&T>,
+    ) -> Result<(), AppError> {
+        let before = before.map(serde_json::to_value).transpose()?;
+        let after = after.map(serde_json::to_value).transpose()?;
+        self.append(AuditRecord {
+            seq: 0,
+            timestamp: Utc::now(),
+            tenant: tenant.to_string(),
+            principal: SYSTEM_PRINCIPAL.to_string(),
+            request_id: None,
+            client_ip: None,
+            operation: operation.to_string(),
+            target_id: target_id.to_string(),
+            changes: field_diff(before.as_ref(), after.as_ref()),
+        })
+    }
+}
+
+/// Short, non-reversible tag distinguishing key values in audit diffs
+fn fingerprint(secret: &str) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, secret.as_bytes());
+    let hex: String = digest.as_ref()[..4]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("sha256:{}", hex)
+}
+
+/// Who is acting in the current request, for writing audit records
+///
+/// Mutating handlers take this extractor and write the record before
+/// applying the change, inside the same critical section, so a change is
+/// never applied without its record.
+pub struct AuditContext {
+    pub principal: Principal,
+    request_id: Option<Uuid>,
+    client_ip: Option<IpAddr>,
+    log: AuditLog,
+}
+
+impl AuditContext {
+    /// Writes a record of `operation` on `target_id`, diffing the
+    /// serialised `before` and `after` states
+    pub fn record<T: Serialize>(
+        &self,
+        operation: &str,
+        target_id: impl ToString,
+        before: Option<&T>,
+        after: Option<&T>,
+    ) -> Result<(), AppError> {
+        let before = before.map(serde_json::to_value).transpose()?;
+        let after = after.map(serde_json::to_value).transpose()?;
+        self.log.append(AuditRecord {
+            seq: 0,
+            timestamp: Utc::now(),
+            tenant: self.principal.tenant.clone(),
+            principal: self.principal.id.clone(),
+            request_id: self.request_id,
+            client_ip: self.client_ip,
+            operation: operation.to_string(),
+            target_id: target_id.to_string(),
+            changes: field_diff(before.as_ref(), after.as_ref()),
+        })
+    }
+}
+
+impl FromRequest for AuditContext {
+    type Error = AppError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let extensions = req.extensions();
+        let Some(principal) = extensions.get::<Principal>().cloned() else {
+            return ready(Err(AppError::Unauthorized(
+                "Authentication required".to_string(),
+            )));
+        };
+        let Some(state) = req.app_data::<web::Data<AppState>>() else {
+            return ready(Err(AppError::Internal(
+                "Application state is not configured".to_string(),
+            )));
+        };
+
+        ready(Ok(AuditContext {
+            principal,
+            request_id: extensions.get::<Uuid>().copied(),
+            client_ip: extensions
+                .get::<ClientIp>()
+                .map(|c| c.0)
+                .or_else(|| req.peer_addr().map(|a| a.ip())),
+            log: state.audit.clone(),
+        }))
+    }
+}
@@ -0,0 +1,180 @@
+use ring::digest::{Context, SHA256};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, Write};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// A stored blob, named by the SHA-256 of its content
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Blob {
+    /// Lowercase hex SHA-256 digest
+    pub digest: String,
+    pub size: u64,
+}
+
+/// Readable, seekable blob content
+pub trait BlobReader: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> BlobReader for T {}
+
+/// Content being written to a store; dropping it without committing
+/// discards what was written
+pub trait BlobUpload: Write + Send {
+    /// Stores the content written so far. Content the store already holds
+    /// is kept once.
+    fn commit(self: Box<Self>) -> io::Result<Blob>;
+}
+
+/// Content-addressed storage for attachment bodies.
+///
+/// Blobs are immutable and shared by every attachment with the same content;
+/// nothing is removed until `delete` is called for a digest.
+pub trait BlobStore: Send + Sync {
+    /// Starts writing a new blob
+    fn begin(&self) -> io::Result<Box<dyn BlobUpload>>;
+    /// Opens a blob for reading
+    fn open(&self, digest: &str) -> io::Result<Box<dyn BlobReader>>;
+    /// Removes a blob; removing one that does not exist is not an error
+    fn delete(&self, digest: &str) -> io::Result<()>;
+    /// Digests of every stored blob
+    fn list(&self) -> io::Result<Vec<String>>;
+}
+
+/// Whether `digest` looks like a lowercase hex SHA-256 digest
+pub fn is_digest(digest: &str) -> bool {
+    digest.len() == 64
+        && digest
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// Blobs as files under a directory, at `sha256/<first two hex digits>/<digest>`.
+///
+/// Uploads are written to `staging/` and renamed into place once complete,
+/// so readers never see a partial blob.
+pub struct LocalBlobStore {
+    root: PathBuf,
+}
+
+impl LocalBlobStore {
+    /// A store under `root`; directories are created as they are needed
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, digest: &str) -> io::Result<PathBuf> {
+        if !is_digest(digest) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("'{}' is not a SHA-256 digest", digest),
+            ));
+        }
+        Ok(self.root.join("sha256").join(&digest[..2]).join(digest))
+    }
+}
+
+impl BlobStore for LocalBlobStore {
+    fn begin(&self) -> io::Result<Box<dyn BlobUpload>> {
+        let staging = self.root.join("staging");
+        fs::create_dir_all(&staging)?;
+        let path = staging.join(Uuid::new_v4().to_string());
+        let file = File::create_new(&path)?;
+        Ok(Box::new(LocalUpload {
+            root: self.root.clone(),
+            path,
+            file: Some(file),
+            hash: Context::new(&SHA256),
+            size: 0,
+        }))
+    }
+
+    fn open(&self, digest: &str) -> io::Result<Box<dyn BlobReader>> {
+        Ok(Box::new(File::open(self.path(digest)?)?))
+    }
+
+    fn delete(&self, digest: &str) -> io::Result<()> {
+        match fs::remove_file(self.path(digest)?) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    fn list(&self) -> io::Result<Vec<String>> {
+        let mut digests = Vec::new();
+        let shards = match fs::read_dir(self.root.join("sha256")) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(digests),
+            shards => shards?,
+        };
+        for shard in shards {
+            for blob in fs::read_dir(shard?.path())? {
+                let name = blob?.file_name();
+                if let Some(digest) = name.to_str().filter(|name| is_digest(name)) {
+                    digests.push(digest.to_string());
+                }
+            }
+        }
+        digests.sort();
+        Ok(digests)
+    }
+}
+
+/// An upload to a `LocalBlobStore`, hashed as it is written
+struct LocalUpload {
+    root: PathBuf,
+    path: PathBuf,
+    /// Taken on commit; the staging file is removed on drop otherwise
+    file: Option<File>,
+    hash: Context,
+    size: u64,
+}
+
+impl Write for LocalUpload {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let file = self
+            .file
+            .as_mut()
+            .expect("uploads are written before commit");
+        let written = file.write(buf)?;
+        self.hash.update(&buf[..written]);
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.as_mut().map_or(Ok(()), File::flush)
+    }
+}
+
+impl BlobUpload for LocalUpload {
+    fn commit(mut self: Box<Self>) -> io::Result<Blob> {
+        let file = self.file.take().expect("uploads are committed once");
+        let digest = hex(self.hash.clone().finish().as_ref());
+        let target = self.root.join("sha256").join(&digest[..2]).join(&digest);
+        let stored = file.sync_all().and_then(|_| {
+            drop(file);
+            if target.exists() {
+                return Ok(());
+            }
+            fs::create_dir_all(target.parent().expect("blob paths have a parent"))?;
+            fs::rename(&self.path, &target)
+        });
+        // Left behind when the content was already stored or the rename failed
+        let _ = fs::remove_file(&self.path);
+        stored.map(|_| Blob {
+            digest,
+            size: self.size,
+        })
+    }
+}
+
+impl Drop for LocalUpload {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
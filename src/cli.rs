@@ -0,0 +1,92 @@
+use crate::config::{ConfigSources, Profile};
+use clap::Parser;
+use std::path::PathBuf;
+
+/// Command line flags; these override every other configuration layer
+#[derive(Debug, Clone, Default, Parser)]
+#[command(
+    version,
+    about = "A high-performance RESTful API server built with Actix-web"
+)]
+pub struct Cli {
+    /// Configuration file (TOML or YAML)
+    #[arg(short, long, env = "CONFIG_FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Encrypted secrets file, decrypted with `SECRETS_KEY` or `SECRETS_KEY_FILE`
+    #[arg(long, env = "SECRETS_FILE")]
+    pub secrets_file: Option<PathBuf>,
+
+    /// Deployment profile
+    #[arg(long, value_enum)]
+    pub profile: Option<CliProfile>,
+
+    /// Bind address
+    #[arg(long)]
+    pub address: Option<String>,
+
+    /// Listen port
+    #[arg(long)]
+    pub port: Option<u16>,
+
+    /// Number of worker threads
+    #[arg(long)]
+    pub workers: Option<usize>,
+
+    /// Print the effective configuration with secrets redacted, then exit
+    #[arg(long)]
+    pub print_config: bool,
+
+    /// Print a new random secrets file key, then exit
+    #[arg(long)]
+    pub generate_secrets_key: bool,
+
+    /// Encrypt a plaintext TOML secrets file to stdout with `SECRETS_KEY`, then exit
+    #[arg(long, value_name = "PATH")]
+    pub encrypt_secrets: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum CliProfile {
+    Development,
+    Production,
+}
+
+impl From<CliProfile> for Profile {
+    fn from(profile: CliProfile) -> Self {
+        match profile {
+            CliProfile::Development => Profile::Development,
+            CliProfile::Production => Profile::Production,
+        }
+    }
+}
+
+impl Cli {
+    /// Configuration sources described by these flags
+    pub fn config_sources(&self) -> ConfigSources {
+        let mut overrides = Vec::new();
+
+        if let Some(profile) = self.profile {
+            let value = match Profile::from(profile) {
+                Profile::Development => "development",
+                Profile::Production => "production",
+            };
+            overrides.push(("profile".to_string(), value.to_string()));
+        }
+        if let Some(address) = &self.address {
+            overrides.push(("server.address".to_string(), address.clone()));
+        }
+        if let Some(port) = self.port {
+            overrides.push(("server.port".to_string(), port.to_string()));
+        }
+        if let Some(workers) = self.workers {
+            overrides.push(("server.workers".to_string(), workers.to_string()));
+        }
+
+        ConfigSources {
+            file: self.config.clone(),
+            secrets_file: self.secrets_file.clone(),
+            overrides,
+        }
+    }
+}
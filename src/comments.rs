@@ -0,0 +1,177 @@
+use crate::errors::AppError;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Longest accepted comment body, in bytes
+pub const MAX_COMMENT_BYTES: usize = 64 * 1024;
+
+/// Comments returned when the query does not set `limit`
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+
+/// Most comments a single page returns
+pub const MAX_PAGE_SIZE: usize = 200;
+
+/// A comment on a task
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Comment {
+    pub id: Uuid,
+    pub task_id: Uuid,
+    /// Principal that wrote the comment
+    pub author_id: String,
+    /// Markdown source, stored and returned as written
+    pub body: String,
+    /// Earlier bodies, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub edits: Vec<CommentEdit>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+impl Comment {
+    /// Bytes the comment counts towards `max_stored_bytes`, history included
+    pub fn stored_bytes(&self) -> u64 {
+        let history: usize = self.edits.iter().map(|edit| edit.body.len()).sum();
+        (self.body.len() + history) as u64
+    }
+}
+
+/// A body a comment had before it was edited
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommentEdit {
+    pub body: String,
+    /// When this body was replaced
+    pub replaced_at: DateTime<Utc>,
+}
+
+/// Request DTO for writing or editing a comment
+#[derive(Debug, Deserialize)]
+pub struct CommentRequest {
+    pub body: String,
+}
+
+impl CommentRequest {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.body.trim().is_empty() {
+            return Err(AppError::Validation(
+                "Comment body cannot be empty".to_string(),
+            ));
+        }
+        if self.body.len() > MAX_COMMENT_BYTES {
+            return Err(AppError::Validation(format!(
+                "Comment body exceeds {} bytes",
+                MAX_COMMENT_BYTES
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Query parameters of the comment listing
+#[derive(Debug, Default, Deserialize)]
+pub struct CommentQuery {
+    /// Continue after this comment, as returned in `next`
+    pub after: Option<Uuid>,
+    pub limit: Option<usize>,
+}
+
+/// A page of comments, oldest first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommentPage {
+    pub comments: Vec<Comment>,
+    /// Cursor for the following page; absent on the last one
+    pub next: Option<Uuid>,
+}
+
+/// Comments of a tenant's tasks, by task, in the order they were written.
+///
+/// Soft-deleted comments stay in place until their task is purged.
+#[derive(Default)]
+pub struct Comments {
+    threads: DashMap<Uuid, Vec<Comment>>,
+}
+
+impl Comments {
+    pub fn add(&self, comment: Comment) {
+        self.threads
+            .entry(comment.task_id)
+            .or_default()
+            .push(comment);
+    }
+
+    /// A live comment of the task
+    pub fn get(&self, task_id: &Uuid, id: &Uuid) -> Option<Comment> {
+        self.threads
+            .get(task_id)?
+            .iter()
+            .find(|comment| comment.id == *id && comment.deleted_at.is_none())
+            .cloned()
+    }
+
+    /// Applies `change` to a live comment under the thread's lock; the
+    /// change is kept only if it succeeds
+    pub fn update(
+        &self,
+        task_id: &Uuid,
+        id: &Uuid,
+        change: impl FnOnce(&Comment) -> Result<Comment, AppError>,
+    ) -> Result<Option<Comment>, AppError> {
+        let Some(mut thread) = self.threads.get_mut(task_id) else {
+            return Ok(None);
+        };
+        let Some(comment) = thread
+            .iter_mut()
+            .find(|comment| comment.id == *id && comment.deleted_at.is_none())
+        else {
+            return Ok(None);
+        };
+        *comment = change(comment)?;
+        Ok(Some(comment.clone()))
+    }
+
+    /// A page of the task's live comments, oldest first
+    pub fn page(&self, task_id: &Uuid, query: &CommentQuery) -> Result<CommentPage, AppError> {
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+        let Some(thread) = self.threads.get(task_id) else {
+            return Ok(CommentPage {
+                comments: Vec::new(),
+                next: None,
+            });
+        };
+
+        let start = match query.after {
+            Some(after) => {
+                thread
+                    .iter()
+                    .position(|comment| comment.id == after)
+                    .ok_or_else(|| {
+                        AppError::Validation(format!("Unknown comment cursor {}", after))
+                    })?
+                    + 1
+            }
+            None => 0,
+        };
+        let mut live = thread[start..]
+            .iter()
+            .filter(|comment| comment.deleted_at.is_none());
+        let comments: Vec<Comment> = live.by_ref().take(limit).cloned().collect();
+        let next = match live.next() {
+            Some(_) => comments.last().map(|comment| comment.id),
+            None => None,
+        };
+        Ok(CommentPage { comments, next })
+    }
+
+    /// Removes every comment of a task, returning the bytes they held
+    pub fn remove(&self, task_id: &Uuid) -> u64 {
+        self.threads.remove(task_id).map_or(0, |(_, thread)| {
+            thread.iter().map(Comment::stored_bytes).sum()
+        })
+    }
+}
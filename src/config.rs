@@ -1,43 +1,734 @@
-use serde::Deserialize;
-use std::env;
+use crate::{
+    attachments, health,
+    network::CidrList,
+    policy::Role,
+    secrets::{self, Secret},
+    tenancy::{self, DEFAULT_TENANT},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 
-/// Application configuration loaded from environment variables
-#[derive(Debug, Clone, Deserialize)]
+/// API key shipped as the default; refused under the production profile
+pub const DEFAULT_API_KEY: &str = "dev-api-key-change-in-production";
+
+/// Principal id of callers presenting `api.api_key`
+pub const ADMIN_PRINCIPAL: &str = "admin";
+
+/// Longest retention accepted for audit records, trashed tasks and revisions
+pub const MAX_RETENTION_DAYS: u64 = 36_500;
+
+/// Application configuration
+///
+/// Layered from lowest to highest precedence: built-in defaults, an optional
+/// TOML or YAML file, an optional encrypted secrets file, environment
+/// variables (`SECTION__KEY`), then command line flags.
+///
+/// The `api`, `logging`, `cors`, `security_headers` and `network` sections
+/// are reloadable at runtime (see
+/// `reload::ConfigHandle`); every other section is read once at startup.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Config {
+    pub profile: Profile,
     pub server: ServerConfig,
     pub api: ApiConfig,
+    pub logging: LoggingConfig,
+    pub cors: CorsConfig,
+    pub security_headers: SecurityHeadersConfig,
+    pub network: NetworkConfig,
+    pub health: HealthConfig,
+    pub compression: CompressionConfig,
+    pub limits: LimitsConfig,
+    pub usage: UsageConfig,
+    pub audit: AuditConfig,
+    pub revisions: RevisionsConfig,
+    pub trash: TrashConfig,
+    pub reminders: RemindersConfig,
+    pub attachments: AttachmentsConfig,
+}
+
+/// Deployment profile; production enables stricter validation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    #[default]
+    Development,
+    Production,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+/// Where configuration comes from besides defaults and the environment
+#[derive(Debug, Clone, Default)]
+pub struct ConfigSources {
+    /// TOML or YAML file, format chosen by extension
+    pub file: Option<PathBuf>,
+    /// AES-256-GCM encrypted TOML file of secrets, see `secrets::encrypt`
+    pub secrets_file: Option<PathBuf>,
+    /// Highest-precedence overrides as (`section.key`, value) pairs
+    pub overrides: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ServerConfig {
     pub address: String,
     pub port: u16,
     pub workers: usize,
+    /// Seconds between failing readiness and closing listeners on shutdown
+    pub readiness_delay_secs: u64,
+    /// Seconds allowed for in-flight requests and background tasks to drain
+    pub shutdown_timeout_secs: u64,
+    /// How often the config file is checked for changes; 0 reloads on SIGHUP only
+    pub config_reload_interval_secs: u64,
+    pub tls: TlsConfig,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+/// Native TLS termination; HTTP/2 is negotiated via ALPN when enabled
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TlsConfig {
+    pub enabled: bool,
+    /// PEM certificate chain, leaf first
+    pub cert_path: String,
+    /// PEM private key (PKCS#8, PKCS#1 or SEC1)
+    pub key_path: String,
+    /// Minimum protocol version, `1.2` or `1.3`
+    pub min_version: String,
+    /// PEM bundle of CAs for client certificates; setting it enables mutual TLS
+    #[serde(default)]
+    pub client_ca_path: Option<String>,
+    /// Reject clients without a certificate when mutual TLS is enabled
+    pub client_auth_required: bool,
+    /// How often the certificate files are checked for renewal
+    pub reload_interval_secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ApiConfig {
-    pub api_key: String,
+    /// Key of the built-in `admin` principal
+    pub api_key: Secret,
+    /// Additional callers keyed by principal id, each with its own key and role
+    #[serde(default)]
+    pub principals: BTreeMap<String, PrincipalConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrincipalConfig {
+    /// May be left out when given as `key_file` or `key_env`
+    #[serde(default)]
+    pub key: Secret,
+    pub role: Role,
+    #[serde(default = "default_tenant")]
+    pub tenant: String,
+}
+
+fn default_tenant() -> String {
+    DEFAULT_TENANT.to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoggingConfig {
+    /// Log filter directive, e.g. `info` or `info,rust_high_performance_api_server=debug`
+    pub level: String,
+    /// Requests slower than this many milliseconds are logged as warnings; 0 disables
+    pub slow_request_ms: u64,
+}
+
+/// Cross-origin resource sharing policy; list fields are comma-separated
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CorsConfig {
+    /// Exact origins (`https://app.example.com`), wildcard subdomains
+    /// (`https://*.example.com`) or `*`; empty rejects all cross-origin requests
+    pub allowed_origins: String,
+    pub allowed_methods: String,
+    /// Request headers browsers may send, or `*` for any
+    pub allowed_headers: String,
+    /// Response headers scripts may read
+    pub exposed_headers: String,
+    pub allow_credentials: bool,
+    /// How long browsers may cache a preflight result
+    pub max_age_secs: u64,
+}
+
+/// Hardening headers added to every response; an empty value disables a header
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SecurityHeadersConfig {
+    pub enabled: bool,
+    /// `Strict-Transport-Security`
+    pub hsts: String,
+    /// `X-Content-Type-Options`
+    pub content_type_options: String,
+    /// `X-Frame-Options`
+    pub frame_options: String,
+    /// `Referrer-Policy`
+    pub referrer_policy: String,
+    /// `Content-Security-Policy`
+    pub content_security_policy: String,
+    /// `Cache-Control` for responses to authenticated requests
+    pub cache_control: String,
+    /// Per-scope values keyed by path prefix; the longest matching prefix wins
+    #[serde(default = "default_security_header_overrides")]
+    pub overrides: BTreeMap<String, SecurityHeadersOverride>,
+}
+
+/// Header values for one path prefix; unset fields inherit the defaults
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SecurityHeadersOverride {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hsts: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type_options: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame_options: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub referrer_policy: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_security_policy: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<String>,
+}
+
+/// Probes and metrics must never be cached; docs pages need scripts and styles
+fn default_security_header_overrides() -> BTreeMap<String, SecurityHeadersOverride> {
+    let no_store = SecurityHeadersOverride {
+        cache_control: Some("no-store".to_string()),
+        ..Default::default()
+    };
+    let docs = SecurityHeadersOverride {
+        content_security_policy: Some(
+            "default-src 'self'; script-src 'self' 'unsafe-inline'; \
+             style-src 'self' 'unsafe-inline'; img-src 'self' data:"
+                .to_string(),
+        ),
+        frame_options: Some("SAMEORIGIN".to_string()),
+        ..Default::default()
+    };
+
+    BTreeMap::from([
+        ("/health".to_string(), no_store.clone()),
+        ("/metrics".to_string(), no_store),
+        ("/docs".to_string(), docs),
+    ])
+}
+
+/// Client address resolution and IP access control; lists are comma-separated
+/// CIDRs or addresses
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetworkConfig {
+    /// Proxies whose `Forwarded` / `X-Forwarded-For` headers are believed
+    pub trusted_proxies: CidrList,
+    /// Clients allowed everywhere; empty allows all not denied
+    pub allow: CidrList,
+    /// Clients denied everywhere
+    pub deny: CidrList,
+    /// Additional rules keyed by path prefix; the longest matching prefix applies
+    #[serde(default)]
+    pub scopes: BTreeMap<String, IpAccessRule>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IpAccessRule {
+    #[serde(default)]
+    pub allow: CidrList,
+    #[serde(default)]
+    pub deny: CidrList,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthConfig {
+    /// Per-check timeout for configurable checks, in milliseconds
+    pub check_timeout_ms: u64,
+    /// Directory whose filesystem must keep free space (e.g. persistence data)
+    #[serde(default)]
+    pub disk_path: Option<String>,
+    /// Minimum free bytes on `disk_path` before readiness fails
+    pub min_free_disk_bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompressionConfig {
+    pub enabled: bool,
+    /// Comma-separated response encodings in order of preference (`br`, `zstd`, `gzip`)
+    pub encodings: String,
+    /// Responses smaller than this many bytes are sent uncompressed
+    pub min_size: usize,
+}
+
+/// Maximum request body sizes, measured after decompression
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LimitsConfig {
+    /// Limit for JSON bodies on task create/update
+    pub json_max_bytes: usize,
+    /// Limit for NDJSON bodies on task import
+    pub import_max_bytes: usize,
+    /// Limit for attachment uploads, and so for each attachment
+    pub attachment_max_bytes: usize,
+}
+
+/// Persistence of per-tenant usage counters
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageConfig {
+    /// JSON file the monthly request counters are saved to and restored from
+    #[serde(default)]
+    pub persist_path: Option<String>,
+    pub persist_interval_secs: u64,
+}
+
+/// Audit log storage and retention
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditConfig {
+    /// NDJSON file records are appended to; without one they are kept in memory only
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Records older than this many days are dropped
+    pub retention_days: u64,
+    /// Oldest records are dropped beyond this many
+    pub max_records: usize,
+}
+
+/// How much task revision history is kept; `0` means no limit
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RevisionsConfig {
+    /// Revisions kept per task
+    pub max_count: usize,
+    /// Revisions older than this many days are dropped
+    pub max_age_days: u64,
+}
+
+/// How long deleted tasks stay in the trash
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrashConfig {
+    /// Trashed tasks older than this many days are purged
+    pub retention_days: u64,
+    /// How often the purge job runs
+    pub purge_interval_secs: u64,
+}
+
+/// Reminder scheduler
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RemindersConfig {
+    /// How often due reminders are looked for, bounding how late they fire
+    pub poll_interval_secs: u64,
+}
+
+/// Attachment storage and upload rules
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttachmentsConfig {
+    /// Directory of the local blob store
+    pub path: String,
+    /// Comma-separated media types uploads may have; `image/*` accepts a
+    /// whole family and `*/*` anything
+    pub allowed_types: String,
+    /// How often blobs no longer referenced by any attachment are removed
+    pub gc_interval_secs: u64,
 }
 
 impl Config {
-    /// Load configuration from environment variables
+    /// Load configuration from defaults and environment variables only
     pub fn from_env() -> Result<Self, config::ConfigError> {
-        // Default workers: try to get from env, otherwise default to 4
-        // In production, set SERVER__WORKERS to match your CPU count
-        let default_workers = env::var("SERVER__WORKERS")
-            .ok()
-            .and_then(|v| v.parse().ok())
+        Self::load(&ConfigSources::default())
+    }
+
+    /// Load configuration from every layer
+    pub fn load(sources: &ConfigSources) -> Result<Self, config::ConfigError> {
+        let default_workers = std::thread::available_parallelism()
+            .map(|n| n.get())
             .unwrap_or(4);
 
-        let config = config::Config::builder()
-            .add_source(config::Environment::default())
+        let default_log_level = std::env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string());
+
+        let mut builder = config::Config::builder()
+            .set_default("profile", "development")?
             .set_default("server.address", "0.0.0.0")?
             .set_default("server.port", 8080)?
-            .set_default("server.workers", default_workers)?
-            .set_default("api.api_key", "dev-api-key-change-in-production")?
-            .build()?;
+            .set_default("server.workers", default_workers as u64)?
+            .set_default("server.readiness_delay_secs", 0)?
+            .set_default("server.shutdown_timeout_secs", 30)?
+            .set_default("server.config_reload_interval_secs", 10)?
+            .set_default("server.tls.enabled", false)?
+            .set_default("server.tls.cert_path", "certs/server.crt")?
+            .set_default("server.tls.key_path", "certs/server.key")?
+            .set_default("server.tls.min_version", "1.2")?
+            .set_default("server.tls.client_auth_required", true)?
+            .set_default("server.tls.reload_interval_secs", 60)?
+            .set_default("api.api_key", DEFAULT_API_KEY)?
+            .set_default("logging.level", default_log_level)?
+            .set_default("logging.slow_request_ms", 0)?
+            .set_default("cors.allowed_origins", "")?
+            .set_default("cors.allowed_methods", "GET,POST,PUT,DELETE")?
+            .set_default("cors.allowed_headers", "content-type,x-api-key")?
+            .set_default("cors.exposed_headers", "")?
+            .set_default("cors.allow_credentials", false)?
+            .set_default("cors.max_age_secs", 3600)?
+            .set_default("security_headers.enabled", true)?
+            .set_default(
+                "security_headers.hsts",
+                "max-age=31536000; includeSubDomains",
+            )?
+            .set_default("security_headers.content_type_options", "nosniff")?
+            .set_default("security_headers.frame_options", "DENY")?
+            .set_default("security_headers.referrer_policy", "no-referrer")?
+            .set_default(
+                "security_headers.content_security_policy",
+                "default-src 'none'; frame-ancestors 'none'",
+            )?
+            .set_default("security_headers.cache_control", "no-store")?
+            .set_default("network.trusted_proxies", "")?
+            .set_default("network.allow", "")?
+            .set_default("network.deny", "")?
+            .set_default("health.check_timeout_ms", health::DEFAULT_CHECK_TIMEOUT_MS)?
+            .set_default("health.min_free_disk_bytes", 100 * 1024 * 1024)?
+            .set_default("compression.enabled", true)?
+            .set_default("compression.encodings", "br,zstd,gzip")?
+            .set_default("compression.min_size", 1024)?
+            .set_default("limits.json_max_bytes", 64 * 1024)?
+            .set_default("limits.import_max_bytes", 256 * 1024 * 1024)?
+            .set_default("limits.attachment_max_bytes", 25 * 1024 * 1024)?
+            .set_default("usage.persist_interval_secs", 60)?
+            .set_default("audit.retention_days", 90)?
+            .set_default("audit.max_records", 100_000)?
+            .set_default("revisions.max_count", 100)?
+            .set_default("revisions.max_age_days", 0)?
+            .set_default("trash.retention_days", 30)?
+            .set_default("trash.purge_interval_secs", 3600)?
+            .set_default("reminders.poll_interval_secs", 5)?
+            .set_default("attachments.path", "data/attachments")?
+            .set_default(
+                "attachments.allowed_types",
+                attachments::DEFAULT_ALLOWED_TYPES,
+            )?
+            .set_default("attachments.gc_interval_secs", 3600)?;
+
+        if let Some(path) = &sources.file {
+            builder = builder.add_source(config::File::from(path.as_path()).required(true));
+        }
+
+        if let Some(path) = &sources.secrets_file {
+            let plaintext = secrets::load_encrypted_file(path)?;
+            builder =
+                builder.add_source(config::File::from_str(&plaintext, config::FileFormat::Toml));
+        }
+
+        builder = builder.add_source(config::Environment::default().separator("__"));
+
+        for (key, value) in &sources.overrides {
+            builder = builder.set_override(key.as_str(), value.as_str())?;
+        }
+
+        let layers = builder.build()?;
+        let mut config: Config = layers.clone().try_deserialize()?;
+
+        // Resolve `<key>_file` and `<key>_env` indirection for secrets
+        for key in config.secret_keys() {
+            let resolved = if let Ok(path) = layers.get_string(&format!("{}_file", key)) {
+                secrets::read_secret_file(Path::new(&path))?
+            } else if let Ok(name) = layers.get_string(&format!("{}_env", key)) {
+                let value = std::env::var(&name).map_err(|_| {
+                    config::ConfigError::Message(format!(
+                        "{}_env names {}, which is not set",
+                        key, name
+                    ))
+                })?;
+                Secret::new(value)
+            } else {
+                continue;
+            };
+            if let Some(secret) = config.secret_mut(&key) {
+                *secret = resolved;
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Configuration keys holding secrets: `api.api_key` and the key of every
+    /// principal, as `api.principals.<id>.key`.
+    ///
+    /// Each can also be given as `<key>_file` (e.g. `API__API_KEY_FILE`) naming
+    /// a file to read the value from, or as `<key>_env` naming another
+    /// environment variable holding it. Either takes precedence over the plain
+    /// value, and the file over the variable.
+    pub fn secret_keys(&self) -> Vec<String> {
+        let principals = self
+            .api
+            .principals
+            .keys()
+            .map(|id| format!("api.principals.{}.key", id));
+        std::iter::once("api.api_key".to_string())
+            .chain(principals)
+            .collect()
+    }
 
-        config.try_deserialize()
+    fn secret_mut(&mut self, key: &str) -> Option<&mut Secret> {
+        if key == "api.api_key" {
+            return Some(&mut self.api.api_key);
+        }
+        let id = key.strip_prefix("api.principals.")?.strip_suffix(".key")?;
+        self.api
+            .principals
+            .get_mut(id)
+            .map(|principal| &mut principal.key)
     }
+
+    /// Checks values that deserialise fine but cannot work at runtime.
+    ///
+    /// Every problem is reported, not just the first one.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        let mut check = |ok: bool, problem: &str| {
+            if !ok {
+                problems.push(problem.to_string());
+            }
+        };
+
+        check(
+            !self.server.address.trim().is_empty(),
+            "server.address must not be empty",
+        );
+        check(
+            self.server.port != 0,
+            "server.port must be between 1 and 65535",
+        );
+        check(self.server.workers > 0, "server.workers must be at least 1");
+        check(
+            self.server.shutdown_timeout_secs > 0,
+            "server.shutdown_timeout_secs must be at least 1",
+        );
+
+        let tls = &self.server.tls;
+        if tls.enabled {
+            check(
+                matches!(tls.min_version.as_str(), "1.2" | "1.3"),
+                "server.tls.min_version must be 1.2 or 1.3",
+            );
+            check(
+                file_exists(&tls.cert_path),
+                "server.tls.cert_path does not exist",
+            );
+            check(
+                file_exists(&tls.key_path),
+                "server.tls.key_path does not exist",
+            );
+            if let Some(ca) = &tls.client_ca_path {
+                check(file_exists(ca), "server.tls.client_ca_path does not exist");
+            }
+            check(
+                tls.reload_interval_secs > 0,
+                "server.tls.reload_interval_secs must be at least 1",
+            );
+        }
+
+        check(
+            !self.api.api_key.expose().trim().is_empty(),
+            "api.api_key must not be empty",
+        );
+        check(
+            !(self.profile == Profile::Production && self.api.api_key.matches(DEFAULT_API_KEY)),
+            "api.api_key must be changed from the development default in production",
+        );
+        for (id, principal) in &self.api.principals {
+            check(
+                id != ADMIN_PRINCIPAL,
+                &format!(
+                    "api.principals may not redefine the '{}' principal",
+                    ADMIN_PRINCIPAL
+                ),
+            );
+            check(
+                !principal.key.expose().trim().is_empty(),
+                &format!("api.principals.{}.key must not be empty", id),
+            );
+            if let Err(e) = tenancy::validate_tenant_id(&principal.tenant) {
+                check(false, &format!("api.principals.{}.tenant: {}", id, e));
+            }
+            let shared = self.api.api_key == principal.key
+                || self
+                    .api
+                    .principals
+                    .iter()
+                    .any(|(other, p)| other != id && p.key == principal.key);
+            check(!shared, &format!("api.principals.{}.key is not unique", id));
+        }
+
+        check(
+            tracing_subscriber::EnvFilter::try_new(&self.logging.level).is_ok(),
+            "logging.level is not a valid log filter",
+        );
+
+        let cors = &self.cors;
+        for origin in cors.allowed_origins.split(',').map(str::trim) {
+            check(
+                origin.is_empty() || origin == "*" || origin.contains("://"),
+                &format!(
+                    "cors.allowed_origins entry '{}' must include a scheme",
+                    origin
+                ),
+            );
+        }
+        check(
+            !(cors.allow_credentials && cors.allowed_origins.split(',').any(|o| o.trim() == "*")),
+            "cors.allow_credentials cannot be combined with the '*' origin",
+        );
+        for method in cors.allowed_methods.split(',').map(str::trim) {
+            check(
+                method.parse::<actix_web::http::Method>().is_ok(),
+                &format!("cors.allowed_methods contains invalid method '{}'", method),
+            );
+        }
+
+        for prefix in self.security_headers.overrides.keys() {
+            check(
+                prefix.starts_with('/'),
+                &format!(
+                    "security_headers.overrides key '{}' must start with '/'",
+                    prefix
+                ),
+            );
+        }
+
+        // CIDR lists are checked as they are parsed on load
+        for prefix in self.network.scopes.keys() {
+            check(
+                prefix.starts_with('/'),
+                &format!("network.scopes key '{}' must start with '/'", prefix),
+            );
+        }
+
+        check(
+            self.health.check_timeout_ms > 0,
+            "health.check_timeout_ms must be at least 1",
+        );
+
+        if self.compression.enabled {
+            for name in self.compression.encodings.split(',').map(str::trim) {
+                check(
+                    matches!(name, "br" | "zstd" | "gzip" | "deflate"),
+                    &format!(
+                        "compression.encodings contains unsupported encoding '{}'",
+                        name
+                    ),
+                );
+            }
+        }
+
+        check(
+            self.limits.json_max_bytes > 0,
+            "limits.json_max_bytes must be at least 1",
+        );
+        check(
+            self.limits.import_max_bytes > 0,
+            "limits.import_max_bytes must be at least 1",
+        );
+        check(
+            self.limits.attachment_max_bytes > 0,
+            "limits.attachment_max_bytes must be at least 1",
+        );
+        check(
+            self.usage.persist_interval_secs > 0,
+            "usage.persist_interval_secs must be at least 1",
+        );
+        check(
+            (1..=MAX_RETENTION_DAYS).contains(&self.audit.retention_days),
+            &format!(
+                "audit.retention_days must be between 1 and {}",
+                MAX_RETENTION_DAYS
+            ),
+        );
+        check(
+            self.audit.max_records > 0,
+            "audit.max_records must be at least 1",
+        );
+        check(
+            self.revisions.max_age_days <= MAX_RETENTION_DAYS,
+            &format!(
+                "revisions.max_age_days must be at most {}",
+                MAX_RETENTION_DAYS
+            ),
+        );
+        check(
+            (1..=MAX_RETENTION_DAYS).contains(&self.trash.retention_days),
+            &format!(
+                "trash.retention_days must be between 1 and {}",
+                MAX_RETENTION_DAYS
+            ),
+        );
+        check(
+            self.trash.purge_interval_secs > 0,
+            "trash.purge_interval_secs must be at least 1",
+        );
+        check(
+            self.reminders.poll_interval_secs > 0,
+            "reminders.poll_interval_secs must be at least 1",
+        );
+        check(
+            !self.attachments.path.is_empty(),
+            "attachments.path must be set",
+        );
+        if let Err(e) = attachments::parse_allowed_types(&self.attachments.allowed_types) {
+            check(false, &format!("attachments.allowed_types: {}", e));
+        }
+        check(
+            self.attachments.gc_interval_secs > 0,
+            "attachments.gc_interval_secs must be at least 1",
+        );
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// This configuration with the reloadable sections taken from `other`
+    pub fn with_reloadable_from(&self, other: &Config) -> Self {
+        let mut config = self.clone();
+        config.api = other.api.clone();
+        config.logging = other.logging.clone();
+        config.cors = other.cors.clone();
+        config.security_headers = other.security_headers.clone();
+        config.network = other.network.clone();
+        config
+    }
+
+    /// Static sections that differ in `other` and need a restart to apply
+    pub fn static_changes(&self, other: &Config) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        if self.profile != other.profile {
+            changed.push("profile");
+        }
+        if self.server != other.server {
+            changed.push("server");
+        }
+        if self.health != other.health {
+            changed.push("health");
+        }
+        if self.compression != other.compression {
+            changed.push("compression");
+        }
+        if self.limits != other.limits {
+            changed.push("limits");
+        }
+        if self.usage != other.usage {
+            changed.push("usage");
+        }
+        if self.audit != other.audit {
+            changed.push("audit");
+        }
+        if self.revisions != other.revisions {
+            changed.push("revisions");
+        }
+        if self.trash != other.trash {
+            changed.push("trash");
+        }
+        if self.reminders != other.reminders {
+            changed.push("reminders");
+        }
+        if self.attachments != other.attachments {
+            changed.push("attachments");
+        }
+        changed
+    }
+}
+
+fn file_exists(path: &str) -> bool {
+    Path::new(path).is_file()
 }
@@ -0,0 +1,235 @@
+use crate::{
+    errors::AppError,
+    models::Task,
+    tenancy::{TaskStore, TenantEntry},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::RwLock;
+use uuid::Uuid;
+
+/// Request DTO for making a task wait on another one
+#[derive(Debug, Deserialize)]
+pub struct AddDependencyRequest {
+    /// Task that has to be completed first
+    pub task_id: Uuid,
+}
+
+/// A task with the dependency state derived from the rest of the store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskView {
+    #[serde(flatten)]
+    pub task: Task,
+    /// Tasks waiting on this one
+    pub blocking: Vec<Uuid>,
+    /// Whether any task this one waits on is still open
+    pub blocked: bool,
+}
+
+/// Reverse dependency edges of live tasks: for each task, the tasks
+/// waiting on it
+#[derive(Default)]
+pub struct Dependents {
+    index: RwLock<HashMap<Uuid, HashSet<Uuid>>>,
+}
+
+impl Dependents {
+    /// Moves a task's edges from its `before` dependencies to its `after` ones
+    pub fn reindex(&self, id: Uuid, before: &[Uuid], after: &[Uuid]) {
+        if before == after {
+            return;
+        }
+        let mut index = self.index.write().unwrap();
+        for dependency in before
+            .iter()
+            .filter(|dependency| !after.contains(dependency))
+        {
+            if let Some(dependents) = index.get_mut(dependency) {
+                dependents.remove(&id);
+                if dependents.is_empty() {
+                    index.remove(dependency);
+                }
+            }
+        }
+        for dependency in after {
+            index.entry(*dependency).or_default().insert(id);
+        }
+    }
+
+    /// Indexes every task of the store from scratch
+    pub fn rebuild(&self, tasks: &TaskStore) {
+        let mut index: HashMap<Uuid, HashSet<Uuid>> = HashMap::new();
+        for task in tasks.iter() {
+            for dependency in &task.blocked_by {
+                index.entry(*dependency).or_default().insert(task.id);
+            }
+        }
+        *self.index.write().unwrap() = index;
+    }
+
+    /// Tasks waiting on `id`, in id order
+    pub fn of(&self, id: &Uuid) -> Vec<Uuid> {
+        let mut dependents: Vec<Uuid> = self
+            .index
+            .read()
+            .unwrap()
+            .get(id)
+            .map(|dependents| dependents.iter().copied().collect())
+            .unwrap_or_default();
+        dependents.sort();
+        dependents
+    }
+}
+
+/// Adds the dependency state of a task from the index and its own edges;
+/// no task entry lock may be held
+pub fn view(tenant: &TenantEntry, task: Task) -> TaskView {
+    let blocking = tenant.dependents.of(&task.id);
+    let blocked = !open_blockers(&tenant.tasks, &task).is_empty();
+    TaskView {
+        task,
+        blocking,
+        blocked,
+    }
+}
+
+/// Dependencies of `task` that are live and not completed; deleted tasks no
+/// longer block anything
+pub fn open_blockers(tasks: &TaskStore, task: &Task) -> Vec<Uuid> {
+    task.blocked_by
+        .iter()
+        .filter(|id| {
+            tasks
+                .get(id)
+                .is_some_and(|dependency| !dependency.completed)
+        })
+        .copied()
+        .collect()
+}
+
+/// Whether `id` waits on `dependency`, directly or through other tasks.
+///
+/// Trashed tasks keep their edges and can be restored, so they are followed
+/// too.
+pub fn depends_on(tenant: &TenantEntry, id: &Uuid, dependency: &Uuid) -> bool {
+    let mut seen = HashSet::new();
+    let mut stack = vec![*id];
+    while let Some(next) = stack.pop() {
+        if next == *dependency {
+            return true;
+        }
+        if !seen.insert(next) {
+            continue;
+        }
+        let edges = tenant
+            .tasks
+            .get(&next)
+            .map(|task| task.blocked_by.clone())
+            .or_else(|| tenant.trash.get(&next).map(|task| task.blocked_by.clone()));
+        stack.extend(edges.unwrap_or_default());
+    }
+    false
+}
+
+/// Query parameters of the plan endpoint
+#[derive(Debug, Default, Deserialize)]
+pub struct PlanQuery {
+    /// Comma-separated task ids; all readable tasks when omitted
+    pub ids: Option<String>,
+}
+
+impl PlanQuery {
+    pub fn ids(&self) -> Result<Option<Vec<Uuid>>, AppError> {
+        let Some(ids) = &self.ids else {
+            return Ok(None);
+        };
+        ids.split(',')
+            .filter(|id| !id.trim().is_empty())
+            .map(|id| {
+                Uuid::from_str(id.trim())
+                    .map_err(|_| AppError::Validation(format!("Invalid task id '{}'", id.trim())))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Some)
+    }
+}
+
+/// Execution order for a set of tasks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Plan {
+    /// Every task after the tasks it waits on; ties go to the oldest task
+    pub order: Vec<Uuid>,
+    /// Longest chain of dependencies, first task first
+    pub critical_path: Vec<Uuid>,
+}
+
+impl Plan {
+    /// Orders `tasks` using only the dependencies between them
+    pub fn new(tasks: &[Task]) -> Result<Self, AppError> {
+        let by_id: HashMap<Uuid, &Task> = tasks.iter().map(|task| (task.id, task)).collect();
+        let mut waiting: HashMap<Uuid, usize> = HashMap::new();
+        let mut blocking: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+        for task in by_id.values() {
+            let dependencies: HashSet<&Uuid> = task
+                .blocked_by
+                .iter()
+                .filter(|id| by_id.contains_key(id))
+                .collect();
+            waiting.insert(task.id, dependencies.len());
+            for dependency in dependencies {
+                blocking.entry(*dependency).or_default().push(task.id);
+            }
+        }
+
+        // Kahn's algorithm, tracking the longest chain ending at each task
+        let mut ready: BTreeSet<_> = by_id
+            .values()
+            .filter(|task| waiting[&task.id] == 0)
+            .map(|task| (task.created_at, task.id))
+            .collect();
+        let mut chain: HashMap<Uuid, (usize, Option<Uuid>)> = HashMap::new();
+        let mut order = Vec::with_capacity(by_id.len());
+        while let Some((_, id)) = ready.pop_first() {
+            let length = chain.entry(id).or_insert((1, None)).0;
+            order.push(id);
+            for next in blocking.get(&id).into_iter().flatten() {
+                let entry = chain.entry(*next).or_insert((1, None));
+                if length + 1 > entry.0 {
+                    *entry = (length + 1, Some(id));
+                }
+                let count = waiting.get_mut(next).expect("every task is counted");
+                *count -= 1;
+                if *count == 0 {
+                    ready.insert((by_id[next].created_at, *next));
+                }
+            }
+        }
+        if order.len() < by_id.len() {
+            return Err(AppError::Conflict(
+                "Dependencies of the selected tasks form a cycle".to_string(),
+            ));
+        }
+
+        // The first task in order with the longest chain ends the critical path
+        let mut longest = 0;
+        let mut current = None;
+        for id in &order {
+            if chain[id].0 > longest {
+                longest = chain[id].0;
+                current = Some(*id);
+            }
+        }
+        let mut critical_path = Vec::new();
+        while let Some(id) = current {
+            critical_path.push(id);
+            current = chain[&id].1;
+        }
+        critical_path.reverse();
+
+        Ok(Self {
+            order,
+            critical_path,
+        })
+    }
+}
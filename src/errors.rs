@@ -10,6 +10,9 @@ pub enum AppError {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
+    #[error("TLS error: {0}")]
+    Tls(String),
+
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
 
@@ -22,6 +25,34 @@ pub enum AppError {
     #[error("Unauthorized: {0}")]
     Unauthorized(String),
 
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    #[error("Quota '{quota}' of {limit} exceeded")]
+    QuotaExceeded {
+        quota: &'static str,
+        limit: u64,
+        used: u64,
+    },
+
+    #[error("Too many requests: {message}")]
+    TooManyRequests {
+        message: String,
+        retry_after_secs: u64,
+    },
+
+    #[error("Payload too large: {0}")]
+    PayloadTooLarge(String),
+
+    #[error("Unsupported media type: {0}")]
+    UnsupportedMediaType(String),
+
+    #[error("Requested range is outside the {size} bytes available")]
+    RangeNotSatisfiable { size: u64 },
+
     #[error("Internal server error: {0}")]
     Internal(String),
 }
@@ -29,36 +60,72 @@ pub enum AppError {
 impl ResponseError for AppError {
     fn error_response(&self) -> HttpResponse {
         match self {
-            AppError::NotFound(msg) => {
-                HttpResponse::NotFound().json(serde_json::json!({
-                    "error": "Not Found",
-                    "message": msg
+            AppError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Not Found",
+                "message": msg
+            })),
+            AppError::Validation(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Validation Error",
+                "message": msg
+            })),
+            AppError::Unauthorized(msg) => HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized",
+                "message": msg
+            })),
+            AppError::Forbidden(msg) => HttpResponse::Forbidden().json(serde_json::json!({
+                "error": "Forbidden",
+                "message": msg
+            })),
+            AppError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+                "error": "Conflict",
+                "message": msg
+            })),
+            AppError::QuotaExceeded { quota, limit, used } => {
+                HttpResponse::Forbidden().json(serde_json::json!({
+                    "error": "Quota Exceeded",
+                    "message": self.to_string(),
+                    "quota": quota,
+                    "limit": limit,
+                    "used": used
                 }))
             }
-            AppError::Validation(msg) => {
-                HttpResponse::BadRequest().json(serde_json::json!({
-                    "error": "Validation Error",
+            AppError::TooManyRequests {
+                message,
+                retry_after_secs,
+            } => HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", retry_after_secs.to_string()))
+                .json(serde_json::json!({
+                    "error": "Too Many Requests",
+                    "message": message
+                })),
+            AppError::PayloadTooLarge(msg) => {
+                HttpResponse::PayloadTooLarge().json(serde_json::json!({
+                    "error": "Payload Too Large",
                     "message": msg
                 }))
             }
-            AppError::Unauthorized(msg) => {
-                HttpResponse::Unauthorized().json(serde_json::json!({
-                    "error": "Unauthorized",
+            AppError::UnsupportedMediaType(msg) => {
+                HttpResponse::UnsupportedMediaType().json(serde_json::json!({
+                    "error": "Unsupported Media Type",
                     "message": msg
                 }))
             }
+            AppError::RangeNotSatisfiable { size } => HttpResponse::RangeNotSatisfiable()
+                .insert_header(("Content-Range", format!("bytes */{}", size)))
+                .json(serde_json::json!({
+                    "error": "Range Not Satisfiable",
+                    "message": self.to_string()
+                })),
             AppError::Internal(msg) => {
                 HttpResponse::InternalServerError().json(serde_json::json!({
                     "error": "Internal Server Error",
                     "message": msg
                 }))
             }
-            _ => {
-                HttpResponse::InternalServerError().json(serde_json::json!({
-                    "error": "Internal Server Error",
-                    "message": "An unexpected error occurred"
-                }))
-            }
+            _ => HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal Server Error",
+                "message": "An unexpected error occurred"
+            })),
         }
     }
 }
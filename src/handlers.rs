@@ -1,10 +1,35 @@
 use crate::{
+    attachments::{self, Attachment, StagedUpload, UploadQuery},
+    audit::{AuditContext, AuditQuery},
+    comments::{Comment, CommentEdit, CommentQuery, CommentRequest},
+    dependencies::{self, AddDependencyRequest, Plan, PlanQuery, TaskView},
     errors::AppError,
-    models::{CreateTaskRequest, Task, UpdateTaskRequest},
+    health::{HealthReport, Probe},
+    hierarchy::{self, MoveTaskRequest, SubtaskMode},
+    labels::{self, Label, LabelChanges, LabelRequest},
+    metrics::PROMETHEUS_CONTENT_TYPE,
+    models::{CreateTaskRequest, Reminder, Task, TaskQuery, UpdateTaskRequest},
+    policy::{self, Action, Principal, Role},
+    recurrence::{Series, SeriesView, UpdateSeriesRequest},
+    revisions::{DiffQuery, RevisionDiff},
+    search::{self, Highlight, SearchHit, SearchQuery, SearchResults},
     state::AppState,
+    tenancy::{CreateTenantRequest, CurrentTenant, TenantEntry, TenantQuota, TenantStatus},
+    transfer::{self, ImportQuery, Importer},
+    trash::{self, DeleteQuery},
+    usage::UsageReport,
 };
-use actix_web::{web, HttpResponse, Responder};
+use actix_multipart::{Multipart, MultipartError};
+use actix_web::{
+    body::SizedStream,
+    error::PayloadError,
+    http::header::{ACCEPT_RANGES, CONTENT_ENCODING, CONTENT_RANGE, CONTENT_TYPE, ETAG},
+    mime, web, HttpMessage, HttpRequest, HttpResponse, Responder,
+};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
 use std::str::FromStr;
+use tracing::warn;
 use uuid::Uuid;
 
 /// Health check endpoint
@@ -12,12 +37,63 @@ pub async fn health_check() -> impl Responder {
     HttpResponse::Ok().json(crate::models::HealthResponse::ok())
 }
 
-/// Get all tasks
-pub async fn get_tasks(state: web::Data<AppState>) -> impl Responder {
-    let tasks: Vec<Task> = state
-        .tasks
-        .iter()
-        .map(|entry| entry.value().clone())
+/// Liveness probe: the process is running and its workers make progress
+pub async fn liveness(state: web::Data<AppState>) -> impl Responder {
+    probe_response(state.health.report(Probe::Live).await)
+}
+
+/// Readiness probe: dependencies are usable and the server is not draining
+pub async fn readiness(state: web::Data<AppState>) -> impl Responder {
+    probe_response(state.health.report(Probe::Ready).await)
+}
+
+/// Startup probe: initialisation has completed
+pub async fn startup(state: web::Data<AppState>) -> impl Responder {
+    probe_response(state.health.report(Probe::Startup).await)
+}
+
+/// Prometheus metrics endpoint
+pub async fn metrics(state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok()
+        .content_type(PROMETHEUS_CONTENT_TYPE)
+        .body(state.metrics.render())
+}
+
+fn probe_response(report: HealthReport) -> HttpResponse {
+    if report.is_healthy() {
+        HttpResponse::Ok().json(report)
+    } else {
+        HttpResponse::ServiceUnavailable().json(report)
+    }
+}
+
+/// Get all tasks the caller may read, filtered and sorted by the query
+pub async fn get_tasks(
+    principal: Principal,
+    query: web::Query<TaskQuery>,
+    tenant: CurrentTenant,
+) -> impl Responder {
+    let now = Utc::now();
+    let include =
+        |task: &Task| policy::can(&principal, Action::Read, Some(task)) && query.matches(task, now);
+    // Label filters narrow the candidates through the index before any task is read
+    let mut tasks: Vec<Task> = match tenant.labels.select(&query.labels_all, &query.labels_any) {
+        Some(ids) => ids
+            .iter()
+            .filter_map(|id| tenant.tasks.get(id).map(|task| task.value().clone()))
+            .filter(|task| include(task))
+            .collect(),
+        None => tenant
+            .tasks
+            .iter()
+            .filter(|entry| include(entry.value()))
+            .map(|entry| entry.value().clone())
+            .collect(),
+    };
+    query.sort(&mut tasks);
+    let tasks: Vec<TaskView> = tasks
+        .into_iter()
+        .map(|task| present(&tenant, task))
         .collect();
 
     HttpResponse::Ok().json(tasks)
@@ -25,52 +101,103 @@ pub async fn get_tasks(state: web::Data<AppState>) -> impl Responder {
 
 /// Get a single task by ID
 pub async fn get_task(
+    principal: Principal,
     path: web::Path<String>,
-    state: web::Data<AppState>,
+    tenant: CurrentTenant,
 ) -> Result<HttpResponse, AppError> {
     let id = Uuid::from_str(&path.into_inner())
         .map_err(|_| AppError::Validation("Invalid UUID format".to_string()))?;
 
-    let task = state
+    let task = tenant
         .tasks
         .get(&id)
         .map(|entry| entry.value().clone())
         .ok_or_else(|| AppError::NotFound(format!("Task with id {} not found", id)))?;
+    policy::authorize(&principal, Action::Read, Some(&task))?;
 
-    Ok(HttpResponse::Ok().json(task))
+    Ok(HttpResponse::Ok().json(present(&tenant, task)))
 }
 
 /// Create a new task
 pub async fn create_task(
+    principal: Principal,
     req: web::Json<CreateTaskRequest>,
-    state: web::Data<AppState>,
+    tenant: CurrentTenant,
+    audit: AuditContext,
 ) -> Result<HttpResponse, AppError> {
+    policy::authorize(&principal, Action::Create, None)?;
+
     // Validate input
     if req.title.trim().is_empty() {
         return Err(AppError::Validation("Title cannot be empty".to_string()));
     }
+    validate_reminders(&req.reminders, &[])?;
+    if let Some(parent) = &req.parent_id {
+        readable_parent(&principal, &tenant, parent)?;
+    }
+    for dependency in &req.blocked_by {
+        readable_dependency(&principal, &tenant, dependency)?;
+    }
+    let labels = labels::normalize_all(&req.labels)?;
+    let _slot = tenant.reserve_task_slot()?;
 
-    let task = Task::new(req.title.clone(), req.description.clone());
-    let id = task.id;
+    let req = req.into_inner();
+    let mut task = Task::new(req.title, req.description);
+    task.due_at = req.due_at;
+    task.priority = req.priority.unwrap_or_default();
+    task.set_reminders(req.reminders);
+    task.parent_id = req.parent_id;
+    task.blocked_by = req.blocked_by;
+    task.blocked_by.sort();
+    task.blocked_by.dedup();
+    task.labels = labels;
+    task.owner_id = Some(principal.id.clone());
 
-    state.tasks.insert(id, task.clone());
+    // A recurring task is the first occurrence of a new series
+    let Some(recurrence) = req.recurrence else {
+        let task = insert_new(&tenant, &audit, task)?;
+        return Ok(HttpResponse::Created().json(present(&tenant, task)));
+    };
+    let series = Series::new(&task, recurrence)
+        .ok_or_else(|| AppError::Validation("Recurring tasks need a due_at".to_string()))?;
+    task.series_id = Some(series.id);
+    audit.record("series.create", series.id, None, Some(&series))?;
+    let series_id = series.id;
+    tenant.series.insert(series_id, series);
+    let task = insert_new(&tenant, &audit, task).inspect_err(|_| {
+        tenant.series.remove(&series_id);
+    })?;
 
-    Ok(HttpResponse::Created().json(task))
+    Ok(HttpResponse::Created().json(present(&tenant, task)))
 }
 
 /// Update an existing task
 pub async fn update_task(
+    principal: Principal,
     path: web::Path<String>,
     req: web::Json<UpdateTaskRequest>,
-    state: web::Data<AppState>,
+    tenant: CurrentTenant,
+    audit: AuditContext,
 ) -> Result<HttpResponse, AppError> {
     let id = Uuid::from_str(&path.into_inner())
         .map_err(|_| AppError::Validation("Invalid UUID format".to_string()))?;
 
-    let mut task = state
+    // Dependencies are read before the entry lock is taken; the dependency
+    // lock keeps the task's edges fixed until the completion is applied
+    let completing = req.completed == Some(true);
+    let _dependencies = completing.then(|| tenant.dependencies.lock().unwrap());
+    let blockers = if completing {
+        completion_blockers(&tenant, &id)
+    } else {
+        Vec::new()
+    };
+
+    let mut task = tenant
         .tasks
         .get_mut(&id)
         .ok_or_else(|| AppError::NotFound(format!("Task with id {} not found", id)))?;
+    policy::authorize(&principal, Action::Update, Some(task.value()))?;
+    check_unblocked(&id, &blockers)?;
 
     // Validate title if provided
     if let Some(ref title) = req.title {
@@ -78,24 +205,1483 @@ pub async fn update_task(
             return Err(AppError::Validation("Title cannot be empty".to_string()));
         }
     }
+    if let Some(reminders) = &req.reminders {
+        validate_reminders(reminders, &task.reminders)?;
+    }
+    let labels = req
+        .labels
+        .as_deref()
+        .map(labels::normalize_all)
+        .transpose()?;
+
+    let was_open = !task.completed;
+    let mut updated = task.value().clone();
+    updated.update(req.title.clone(), req.description.clone(), req.completed);
+    if let Some(due_at) = req.due_at {
+        updated.due_at = due_at;
+    }
+    if let Some(priority) = req.priority {
+        updated.priority = priority;
+    }
+    if let Some(reminders) = &req.reminders {
+        updated.set_reminders(reminders.clone());
+    }
+    if let Some(labels) = labels {
+        updated.labels = labels;
+    }
+    tenant.commit_update(&audit, "task.update", &mut task, updated)?;
+    let updated = task.value().clone();
+
+    // The entry lock is released before the next occurrence is stored
+    drop(task);
+    if was_open && updated.completed {
+        advance_series(&tenant, &audit, &updated);
+    }
 
-    task.update(req.title.clone(), req.description.clone(), req.completed);
+    Ok(HttpResponse::Ok().json(present(&tenant, updated)))
+}
+
+/// Open tasks `id` waits on, if completing it now would close it; the caller
+/// holds the dependency lock and no entry lock
+fn completion_blockers(tenant: &TenantEntry, id: &Uuid) -> Vec<Uuid> {
+    match tenant.tasks.get(id).map(|task| task.value().clone()) {
+        Some(task) if !task.completed => dependencies::open_blockers(&tenant.tasks, &task),
+        _ => Vec::new(),
+    }
+}
 
-    Ok(HttpResponse::Ok().json(task.value().clone()))
+/// Refuses to complete task `id` while `blockers` are open
+fn check_unblocked(id: &Uuid, blockers: &[Uuid]) -> Result<(), AppError> {
+    if blockers.is_empty() {
+        return Ok(());
+    }
+    let blockers: Vec<String> = blockers.iter().map(Uuid::to_string).collect();
+    Err(AppError::Conflict(format!(
+        "Task {} is blocked by open tasks: {}",
+        id,
+        blockers.join(", ")
+    )))
+}
+
+/// Make a task wait on another one
+pub async fn add_dependency(
+    principal: Principal,
+    path: web::Path<String>,
+    req: web::Json<AddDependencyRequest>,
+    tenant: CurrentTenant,
+    audit: AuditContext,
+) -> Result<HttpResponse, AppError> {
+    let id = Uuid::from_str(&path.into_inner())
+        .map_err(|_| AppError::Validation("Invalid UUID format".to_string()))?;
+    let dependency = req.task_id;
+
+    let _dependencies = tenant.dependencies.lock().unwrap();
+    readable_dependency(&principal, &tenant, &dependency)?;
+    if dependencies::depends_on(&tenant, &dependency, &id) {
+        return Err(AppError::Conflict(format!(
+            "Task {} waiting on {} would create a cycle",
+            id, dependency
+        )));
+    }
+
+    let mut task = tenant
+        .tasks
+        .get_mut(&id)
+        .ok_or_else(|| AppError::NotFound(format!("Task with id {} not found", id)))?;
+    policy::authorize(&principal, Action::Update, Some(task.value()))?;
+    if !task.blocked_by.contains(&dependency) {
+        let mut updated = task.value().clone();
+        updated.blocked_by.push(dependency);
+        updated.blocked_by.sort();
+        updated.updated_at = Utc::now();
+        tenant.commit_update(&audit, "task.depend", &mut task, updated)?;
+    }
+    let updated = task.value().clone();
+    drop(task);
+
+    Ok(HttpResponse::Ok().json(present(&tenant, updated)))
+}
+
+/// Stop a task from waiting on another one
+pub async fn remove_dependency(
+    principal: Principal,
+    path: web::Path<(String, String)>,
+    tenant: CurrentTenant,
+    audit: AuditContext,
+) -> Result<HttpResponse, AppError> {
+    let (id, dependency) = path.into_inner();
+    let parse = |id: &str| {
+        Uuid::from_str(id).map_err(|_| AppError::Validation("Invalid UUID format".to_string()))
+    };
+    let (id, dependency) = (parse(&id)?, parse(&dependency)?);
+
+    let _dependencies = tenant.dependencies.lock().unwrap();
+    let mut task = tenant
+        .tasks
+        .get_mut(&id)
+        .ok_or_else(|| AppError::NotFound(format!("Task with id {} not found", id)))?;
+    policy::authorize(&principal, Action::Update, Some(task.value()))?;
+    if !task.blocked_by.contains(&dependency) {
+        return Err(AppError::NotFound(format!(
+            "Task {} does not wait on {}",
+            id, dependency
+        )));
+    }
+    let mut updated = task.value().clone();
+    updated.blocked_by.retain(|other| *other != dependency);
+    updated.updated_at = Utc::now();
+    tenant.commit_update(&audit, "task.undepend", &mut task, updated)?;
+    let updated = task.value().clone();
+    drop(task);
+
+    Ok(HttpResponse::Ok().json(present(&tenant, updated)))
+}
+
+/// Search task titles and descriptions, best matches first
+pub async fn search_tasks(
+    principal: Principal,
+    query: web::Query<SearchQuery>,
+    tenant: CurrentTenant,
+) -> Result<HttpResponse, AppError> {
+    let clauses = search::parse(&query.q)?;
+    let limit = query
+        .limit
+        .unwrap_or(search::DEFAULT_LIMIT)
+        .clamp(1, search::MAX_LIMIT);
+
+    let matches = tenant.search.search(&clauses);
+    let readable: Vec<(Task, f64)> = matches
+        .scores
+        .iter()
+        .filter_map(|(id, score)| {
+            tenant
+                .tasks
+                .get(id)
+                .map(|task| (task.value().clone(), *score))
+        })
+        .filter(|(task, _)| policy::can(&principal, Action::Read, Some(task)))
+        .collect();
+    let total = readable.len();
+    let hits = readable
+        .into_iter()
+        .take(limit)
+        .map(|(task, score)| SearchHit {
+            highlight: Highlight::new(&task, &matches.stems),
+            task,
+            score,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(SearchResults { total, hits }))
+}
+
+/// Order a set of tasks by their dependencies, with the critical path
+pub async fn plan_tasks(
+    principal: Principal,
+    query: web::Query<PlanQuery>,
+    tenant: CurrentTenant,
+) -> Result<HttpResponse, AppError> {
+    let tasks = match query.ids()? {
+        Some(ids) => ids
+            .iter()
+            .map(|id| {
+                let task = tenant
+                    .tasks
+                    .get(id)
+                    .map(|task| task.value().clone())
+                    .ok_or_else(|| AppError::NotFound(format!("Task with id {} not found", id)))?;
+                policy::authorize(&principal, Action::Read, Some(&task))?;
+                Ok(task)
+            })
+            .collect::<Result<Vec<_>, AppError>>()?,
+        None => tenant
+            .tasks
+            .iter()
+            .filter(|entry| policy::can(&principal, Action::Read, Some(entry.value())))
+            .map(|entry| entry.value().clone())
+            .collect(),
+    };
+
+    Ok(HttpResponse::Ok().json(Plan::new(&tasks)?))
+}
+
+/// Adds the dependency state of a task; no task entry lock may be held
+fn present(tenant: &TenantEntry, task: Task) -> TaskView {
+    dependencies::view(tenant, task)
+}
+
+/// Get the recurring series of a task with its next occurrences
+pub async fn get_series(
+    principal: Principal,
+    path: web::Path<String>,
+    tenant: CurrentTenant,
+) -> Result<HttpResponse, AppError> {
+    let id = readable_task(&principal, &tenant, &path)?;
+    let series = task_series(&tenant, &id)?;
+
+    Ok(HttpResponse::Ok().json(SeriesView {
+        upcoming: series.upcoming(Utc::now(), UPCOMING_OCCURRENCES),
+        series,
+    }))
+}
+
+/// Edit an occurrence and all future ones: the series and its open
+/// occurrences due from this one on
+pub async fn update_series(
+    principal: Principal,
+    path: web::Path<String>,
+    req: web::Json<UpdateSeriesRequest>,
+    tenant: CurrentTenant,
+    audit: AuditContext,
+) -> Result<HttpResponse, AppError> {
+    let id = Uuid::from_str(&path.into_inner())
+        .map_err(|_| AppError::Validation("Invalid UUID format".to_string()))?;
+    let task = tenant
+        .tasks
+        .get(&id)
+        .map(|task| task.value().clone())
+        .ok_or_else(|| AppError::NotFound(format!("Task with id {} not found", id)))?;
+    policy::authorize(&principal, Action::Update, Some(&task))?;
+    if let Some(ref title) = req.title {
+        if title.trim().is_empty() {
+            return Err(AppError::Validation("Title cannot be empty".to_string()));
+        }
+    }
+
+    let series = {
+        let not_recurring =
+            || AppError::NotFound(format!("Task {} is not part of a recurring series", id));
+        let series_id = task.series_id.ok_or_else(not_recurring)?;
+        let mut series = tenant
+            .series
+            .get_mut(&series_id)
+            .ok_or_else(not_recurring)?;
+        let mut updated = series.clone();
+        if let Some(title) = &req.title {
+            updated.title = title.clone();
+        }
+        if let Some(description) = &req.description {
+            updated.description = description.clone();
+        }
+        if let Some(priority) = req.priority {
+            updated.priority = priority;
+        }
+        if req.rule.is_some() || req.timezone.is_some() {
+            // The new rule is counted from the edited occurrence
+            updated.rule = req.rule.clone().unwrap_or(updated.rule);
+            updated.timezone = req.timezone.unwrap_or(updated.timezone);
+            updated.starts_at = task.due_at.unwrap_or(updated.starts_at);
+        }
+        audit.record(
+            "series.update",
+            series_id,
+            Some(series.value()),
+            Some(&updated),
+        )?;
+        *series = updated.clone();
+        updated
+    };
+
+    let ids: Vec<Uuid> = tenant
+        .tasks
+        .iter()
+        .filter(|entry| {
+            entry.series_id == Some(series.id) && !entry.completed && entry.due_at >= task.due_at
+        })
+        .map(|entry| *entry.key())
+        .collect();
+    for id in ids {
+        let Some(mut occurrence) = tenant.tasks.get_mut(&id) else {
+            continue;
+        };
+        if !policy::can(&principal, Action::Update, Some(occurrence.value())) {
+            continue;
+        }
+        let mut updated = occurrence.value().clone();
+        updated.update(req.title.clone(), req.description.clone(), None);
+        if let Some(priority) = req.priority {
+            updated.priority = priority;
+        }
+        tenant.commit_update(&audit, "task.update", &mut occurrence, updated)?;
+    }
+
+    Ok(HttpResponse::Ok().json(SeriesView {
+        upcoming: series.upcoming(Utc::now(), UPCOMING_OCCURRENCES),
+        series,
+    }))
+}
+
+/// Next occurrences listed with a series
+const UPCOMING_OCCURRENCES: usize = 5;
+
+fn task_series(tenant: &TenantEntry, id: &Uuid) -> Result<Series, AppError> {
+    tenant
+        .tasks
+        .get(id)
+        .and_then(|task| task.series_id)
+        .and_then(|series_id| {
+            tenant
+                .series
+                .get(&series_id)
+                .map(|series| series.value().clone())
+        })
+        .ok_or_else(|| AppError::NotFound(format!("Task {} is not part of a recurring series", id)))
+}
+
+/// Creates the occurrence following a completed one, unless the rule is
+/// exhausted or a later occurrence is already open.
+///
+/// The completion itself has been applied, so failures here are logged
+/// rather than returned.
+fn advance_series(tenant: &TenantEntry, audit: &AuditContext, done: &Task) {
+    let (Some(series_id), Some(due_at)) = (done.series_id, done.due_at) else {
+        return;
+    };
+    let Some(next) = tenant
+        .series
+        .get(&series_id)
+        .and_then(|series| series.next_occurrence(due_at))
+    else {
+        return;
+    };
+    let pending = tenant.tasks.iter().any(|entry| {
+        entry.series_id == Some(series_id) && !entry.completed && entry.due_at >= next.due_at
+    });
+    if pending {
+        return;
+    }
+    if let Err(e) = tenant
+        .reserve_task_slot()
+        .and_then(|_slot| insert_new(tenant, audit, next))
+    {
+        warn!(tenant = %tenant.id, series = %series_id, error = %e, "Failed to create the next occurrence");
+    }
+}
+
+/// Stores a new task with its storage accounting, audit record and first
+/// revision
+fn insert_new(tenant: &TenantEntry, audit: &AuditContext, task: Task) -> Result<Task, AppError> {
+    tenant.resize(0, task.stored_bytes())?;
+    if let Err(e) = audit.record("task.create", task.id, None, Some(&task)) {
+        tenant.resize(task.stored_bytes(), 0)?;
+        return Err(e);
+    }
+    tenant.revisions.record(&task, &audit.principal.id);
+    tenant.reindex(task.id, None, Some(&task));
+    tenant.tasks.insert(task.id, task.clone());
+    Ok(task)
 }
 
 /// Delete a task
 pub async fn delete_task(
+    principal: Principal,
+    path: web::Path<String>,
+    query: web::Query<DeleteQuery>,
+    tenant: CurrentTenant,
+    audit: AuditContext,
+) -> Result<HttpResponse, AppError> {
+    let id = Uuid::from_str(&path.into_inner())
+        .map_err(|_| AppError::Validation("Invalid UUID format".to_string()))?;
+    let action = if query.hard {
+        Action::Purge
+    } else {
+        Action::Delete
+    };
+    let task = tenant
+        .tasks
+        .get(&id)
+        .map(|task| task.value().clone())
+        .ok_or_else(|| AppError::NotFound(format!("Task with id {} not found", id)))?;
+    policy::authorize(&principal, action, Some(&task))?;
+
+    // Subtasks are resolved under the hierarchy lock so a concurrent move
+    // cannot add one midway
+    let _hierarchy = tenant.hierarchy.lock().unwrap();
+    let children = hierarchy::children(&tenant.tasks, &id);
+    let descendants = match query.subtasks {
+        SubtaskMode::Forbid if !children.is_empty() => {
+            return Err(AppError::Conflict(format!("Task {} has subtasks", id)));
+        }
+        SubtaskMode::Cascade => hierarchy::descendants(&tenant.tasks, &id),
+        _ => Vec::new(),
+    };
+    // A cascade only starts once every task in it may be deleted, and
+    // orphaning once every child may be moved
+    for descendant in &descendants {
+        if let Some(task) = tenant.tasks.get(descendant) {
+            policy::authorize(&principal, action, Some(task.value()))?;
+        }
+    }
+    if query.subtasks == SubtaskMode::Orphan {
+        for child in &children {
+            if let Some(task) = tenant.tasks.get(child) {
+                policy::authorize(&principal, Action::Update, Some(task.value()))?;
+            }
+        }
+    }
+
+    delete_one(&tenant, &principal, &audit, id, query.hard)?;
+    for descendant in descendants {
+        match delete_one(&tenant, &principal, &audit, descendant, query.hard) {
+            Ok(()) | Err(AppError::NotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    if query.subtasks == SubtaskMode::Orphan {
+        for child in children {
+            let Some(mut task) = tenant.tasks.get_mut(&child) else {
+                continue;
+            };
+            let orphaned = Task {
+                parent_id: None,
+                ..task.value().clone()
+            };
+            tenant.commit_update(&audit, "task.move", &mut task, orphaned)?;
+        }
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Moves one task to the trash, or removes it permanently when `hard`
+fn delete_one(
+    tenant: &TenantEntry,
+    principal: &Principal,
+    audit: &AuditContext,
+    id: Uuid,
+    hard: bool,
+) -> Result<(), AppError> {
+    let not_found = || AppError::NotFound(format!("Task with id {} not found", id));
+    if !hard {
+        trash::trash(tenant, &id, |task, trashed| {
+            policy::authorize(principal, Action::Delete, Some(task))
+                .and_then(|_| audit.record("task.trash", id, Some(task), Some(trashed)))
+        })?
+        .ok_or_else(not_found)?;
+        return Ok(());
+    }
+
+    // Checked, audited and removed atomically so a concurrent update cannot slip in
+    let mut denied = None;
+    let (_, task) = tenant
+        .tasks
+        .remove_if(&id, |_, task| {
+            denied = policy::authorize(principal, Action::Purge, Some(task))
+                .and_then(|_| audit.record("task.delete", id, Some(task), None))
+                .err();
+            denied.is_none()
+        })
+        .ok_or_else(|| denied.take().unwrap_or_else(not_found))?;
+    tenant.reindex(id, Some(&task), None);
+    trash::release(tenant, &id, &task)
+}
+
+/// List the direct subtasks the caller may read, oldest first
+pub async fn list_children(
+    principal: Principal,
+    path: web::Path<String>,
+    tenant: CurrentTenant,
+) -> Result<HttpResponse, AppError> {
+    let id = readable_task(&principal, &tenant, &path)?;
+    let mut children: Vec<Task> = hierarchy::children(&tenant.tasks, &id)
+        .iter()
+        .filter_map(|child| tenant.tasks.get(child).map(|task| task.value().clone()))
+        .filter(|task| policy::can(&principal, Action::Read, Some(task)))
+        .collect();
+    children.sort_by_key(|task| task.created_at);
+
+    Ok(HttpResponse::Ok().json(children))
+}
+
+/// Get a task with all subtasks the caller may read, nested, with progress
+pub async fn get_subtree(
+    principal: Principal,
+    path: web::Path<String>,
+    tenant: CurrentTenant,
+) -> Result<HttpResponse, AppError> {
+    let id = readable_task(&principal, &tenant, &path)?;
+    let root = tenant
+        .tasks
+        .get(&id)
+        .map(|task| task.value().clone())
+        .ok_or_else(|| AppError::NotFound(format!("Task with id {} not found", id)))?;
+    let node = hierarchy::subtree(&tenant.tasks, root, |task| {
+        policy::can(&principal, Action::Read, Some(task))
+    });
+
+    Ok(HttpResponse::Ok().json(node))
+}
+
+/// Move a task under a new parent, or to the top level
+pub async fn move_task(
+    principal: Principal,
+    path: web::Path<String>,
+    req: web::Json<MoveTaskRequest>,
+    tenant: CurrentTenant,
+    audit: AuditContext,
+) -> Result<HttpResponse, AppError> {
+    let id = Uuid::from_str(&path.into_inner())
+        .map_err(|_| AppError::Validation("Invalid UUID format".to_string()))?;
+
+    let _hierarchy = tenant.hierarchy.lock().unwrap();
+    if let Some(parent) = req.parent_id {
+        readable_parent(&principal, &tenant, &parent)?;
+        if hierarchy::is_ancestor(&tenant.tasks, &id, &parent) {
+            return Err(AppError::Conflict(format!(
+                "Moving task {} under {} would create a cycle",
+                id, parent
+            )));
+        }
+    }
+
+    let mut task = tenant
+        .tasks
+        .get_mut(&id)
+        .ok_or_else(|| AppError::NotFound(format!("Task with id {} not found", id)))?;
+    policy::authorize(&principal, Action::Update, Some(task.value()))?;
+    let moved = Task {
+        parent_id: req.parent_id,
+        updated_at: Utc::now(),
+        ..task.value().clone()
+    };
+    tenant.commit_update(&audit, "task.move", &mut task, moved)?;
+    let moved = task.value().clone();
+    drop(task);
+
+    Ok(HttpResponse::Ok().json(present(&tenant, moved)))
+}
+
+/// Checks a prospective dependency exists and the caller may read it
+fn readable_dependency(
+    principal: &Principal,
+    tenant: &TenantEntry,
+    id: &Uuid,
+) -> Result<(), AppError> {
+    let dependency = tenant
+        .tasks
+        .get(id)
+        .ok_or_else(|| AppError::NotFound(format!("Dependency {} not found", id)))?;
+    policy::authorize(principal, Action::Read, Some(dependency.value()))
+}
+
+/// Checks a prospective parent exists and the caller may read it
+fn readable_parent(principal: &Principal, tenant: &TenantEntry, id: &Uuid) -> Result<(), AppError> {
+    let parent = tenant
+        .tasks
+        .get(id)
+        .ok_or_else(|| AppError::NotFound(format!("Parent task {} not found", id)))?;
+    policy::authorize(principal, Action::Read, Some(parent.value()))
+}
+
+/// Add and remove labels of a task without resending it
+pub async fn change_labels(
+    principal: Principal,
     path: web::Path<String>,
+    req: web::Json<LabelChanges>,
+    tenant: CurrentTenant,
+    audit: AuditContext,
+) -> Result<HttpResponse, AppError> {
+    let id = Uuid::from_str(&path.into_inner())
+        .map_err(|_| AppError::Validation("Invalid UUID format".to_string()))?;
+
+    let mut task = tenant
+        .tasks
+        .get_mut(&id)
+        .ok_or_else(|| AppError::NotFound(format!("Task with id {} not found", id)))?;
+    policy::authorize(&principal, Action::Update, Some(task.value()))?;
+    let labels = req.apply(&task.labels)?;
+    if labels != task.labels {
+        let updated = Task {
+            labels,
+            updated_at: Utc::now(),
+            ..task.value().clone()
+        };
+        tenant.commit_update(&audit, "task.label", &mut task, updated)?;
+    }
+    let updated = task.value().clone();
+    drop(task);
+
+    Ok(HttpResponse::Ok().json(present(&tenant, updated)))
+}
+
+/// List the label catalogue along with labels only found on tasks
+pub async fn list_labels(tenant: CurrentTenant) -> impl Responder {
+    HttpResponse::Ok().json(tenant.labels.summaries())
+}
+
+/// Create or update a catalogue entry
+pub async fn put_label(
+    principal: Principal,
+    path: web::Path<String>,
+    req: web::Json<LabelRequest>,
+    tenant: CurrentTenant,
+    audit: AuditContext,
+) -> Result<HttpResponse, AppError> {
+    policy::authorize(&principal, Action::ManageLabels, None)?;
+    let name = labels::normalize(&path.into_inner())?;
+    req.validate()?;
+
+    let now = Utc::now();
+    let mut created = false;
+    let mut label = tenant
+        .labels
+        .catalogue
+        .entry(name.clone())
+        .or_insert_with(|| {
+            created = true;
+            Label {
+                name: name.clone(),
+                color: None,
+                description: None,
+                created_at: now,
+                updated_at: now,
+            }
+        });
+    let before = (!created).then(|| label.clone());
+    let updated = Label {
+        color: req.color.clone(),
+        description: req.description.clone(),
+        updated_at: now,
+        ..label.clone()
+    };
+    let operation = if created {
+        "label.create"
+    } else {
+        "label.update"
+    };
+    if let Err(e) = audit.record(operation, &name, before.as_ref(), Some(&updated)) {
+        drop(label);
+        if created {
+            tenant.labels.catalogue.remove(&name);
+        }
+        return Err(e);
+    }
+    *label = updated.clone();
+
+    if created {
+        Ok(HttpResponse::Created().json(updated))
+    } else {
+        Ok(HttpResponse::Ok().json(updated))
+    }
+}
+
+/// Remove a catalogue entry; tasks keep the label
+pub async fn delete_label(
+    principal: Principal,
+    path: web::Path<String>,
+    tenant: CurrentTenant,
+    audit: AuditContext,
+) -> Result<HttpResponse, AppError> {
+    policy::authorize(&principal, Action::ManageLabels, None)?;
+    let name = labels::normalize(&path.into_inner())?;
+
+    let mut failed = None;
+    let removed = tenant.labels.catalogue.remove_if(&name, |_, label| {
+        failed = audit.record("label.delete", &name, Some(label), None).err();
+        failed.is_none()
+    });
+    if let Some(e) = failed {
+        return Err(e);
+    }
+    removed.ok_or_else(|| AppError::NotFound(format!("Label {} not found", name)))?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// List a page of a task's comments, oldest first
+pub async fn list_comments(
+    principal: Principal,
+    path: web::Path<String>,
+    query: web::Query<CommentQuery>,
+    tenant: CurrentTenant,
+) -> Result<HttpResponse, AppError> {
+    let id = readable_task(&principal, &tenant, &path)?;
+    Ok(HttpResponse::Ok().json(tenant.comments.page(&id, &query)?))
+}
+
+/// Comment on a task
+pub async fn create_comment(
+    principal: Principal,
+    path: web::Path<String>,
+    req: web::Json<CommentRequest>,
+    tenant: CurrentTenant,
+    audit: AuditContext,
+) -> Result<HttpResponse, AppError> {
+    let id = Uuid::from_str(&path.into_inner())
+        .map_err(|_| AppError::Validation("Invalid UUID format".to_string()))?;
+
+    // The task stays locked so it cannot be deleted under the new comment
+    let task = tenant
+        .tasks
+        .get(&id)
+        .ok_or_else(|| AppError::NotFound(format!("Task with id {} not found", id)))?;
+    policy::authorize(&principal, Action::Comment, Some(task.value()))?;
+    req.validate()?;
+
+    let now = Utc::now();
+    let comment = Comment {
+        id: Uuid::new_v4(),
+        task_id: id,
+        author_id: principal.id.clone(),
+        body: req.into_inner().body,
+        edits: Vec::new(),
+        created_at: now,
+        updated_at: now,
+        deleted_at: None,
+    };
+    tenant.resize(0, comment.stored_bytes())?;
+    if let Err(e) = audit.record("comment.create", comment.id, None, Some(&comment)) {
+        tenant.resize(comment.stored_bytes(), 0)?;
+        return Err(e);
+    }
+    tenant.comments.add(comment.clone());
+    drop(task);
+
+    Ok(HttpResponse::Created().json(comment))
+}
+
+/// Get one comment of a task
+pub async fn get_comment(
+    principal: Principal,
+    path: web::Path<(String, String)>,
+    tenant: CurrentTenant,
+) -> Result<HttpResponse, AppError> {
+    let (id, comment_id) = path.into_inner();
+    let id = readable_task(&principal, &tenant, &id)?;
+    let comment_id = parse_comment_id(&comment_id)?;
+    let comment = tenant
+        .comments
+        .get(&id, &comment_id)
+        .ok_or_else(|| comment_not_found(comment_id))?;
+
+    Ok(HttpResponse::Ok().json(comment))
+}
+
+/// Edit a comment, keeping its previous body in the history
+pub async fn update_comment(
+    principal: Principal,
+    path: web::Path<(String, String)>,
+    req: web::Json<CommentRequest>,
+    tenant: CurrentTenant,
+    audit: AuditContext,
+) -> Result<HttpResponse, AppError> {
+    let (id, comment_id) = path.into_inner();
+    let id = readable_task(&principal, &tenant, &id)?;
+    let comment_id = parse_comment_id(&comment_id)?;
+    req.validate()?;
+
+    let comment = tenant
+        .comments
+        .update(&id, &comment_id, |comment| {
+            authorize_comment_change(&principal, comment)?;
+            let now = Utc::now();
+            let mut edited = comment.clone();
+            edited.edits.push(CommentEdit {
+                body: std::mem::replace(&mut edited.body, req.body.clone()),
+                replaced_at: now,
+            });
+            edited.updated_at = now;
+            tenant.resize(comment.stored_bytes(), edited.stored_bytes())?;
+            if let Err(e) = audit.record("comment.update", comment_id, Some(comment), Some(&edited))
+            {
+                tenant.resize(edited.stored_bytes(), comment.stored_bytes())?;
+                return Err(e);
+            }
+            Ok(edited)
+        })?
+        .ok_or_else(|| comment_not_found(comment_id))?;
+
+    Ok(HttpResponse::Ok().json(comment))
+}
+
+/// Soft-delete a comment; it is removed for good with its task
+pub async fn delete_comment(
+    principal: Principal,
+    path: web::Path<(String, String)>,
+    tenant: CurrentTenant,
+    audit: AuditContext,
+) -> Result<HttpResponse, AppError> {
+    let (id, comment_id) = path.into_inner();
+    let id = readable_task(&principal, &tenant, &id)?;
+    let comment_id = parse_comment_id(&comment_id)?;
+
+    tenant
+        .comments
+        .update(&id, &comment_id, |comment| {
+            authorize_comment_change(&principal, comment)?;
+            let deleted = Comment {
+                deleted_at: Some(Utc::now()),
+                ..comment.clone()
+            };
+            audit.record("comment.delete", comment_id, Some(comment), Some(&deleted))?;
+            Ok(deleted)
+        })?
+        .ok_or_else(|| comment_not_found(comment_id))?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Comments are changed by their author, or by an admin
+fn authorize_comment_change(principal: &Principal, comment: &Comment) -> Result<(), AppError> {
+    if comment.author_id == principal.id || principal.role == Role::Admin {
+        return Ok(());
+    }
+    Err(AppError::Forbidden(
+        "Only the author of a comment can change it".to_string(),
+    ))
+}
+
+fn parse_comment_id(id: &str) -> Result<Uuid, AppError> {
+    Uuid::from_str(id).map_err(|_| AppError::Validation("Invalid UUID format".to_string()))
+}
+
+fn comment_not_found(id: Uuid) -> AppError {
+    AppError::NotFound(format!("Comment {} not found", id))
+}
+
+/// List a task's attachments, oldest first
+pub async fn list_attachments(
+    principal: Principal,
+    path: web::Path<String>,
+    tenant: CurrentTenant,
+) -> Result<HttpResponse, AppError> {
+    let id = readable_task(&principal, &tenant, &path)?;
+    Ok(HttpResponse::Ok().json(tenant.attachments.list(&id)))
+}
+
+/// Attach files to a task, from a `multipart/form-data` body with one part
+/// per file or from a raw body named by `?filename=`
+#[allow(clippy::too_many_arguments)]
+pub async fn upload_attachments(
+    principal: Principal,
+    path: web::Path<String>,
+    query: web::Query<UploadQuery>,
+    req: HttpRequest,
+    payload: web::Payload,
+    tenant: CurrentTenant,
+    audit: AuditContext,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let id = Uuid::from_str(&path.into_inner())
+        .map_err(|_| AppError::Validation("Invalid UUID format".to_string()))?;
+    {
+        let task = tenant
+            .tasks
+            .get(&id)
+            .ok_or_else(|| AppError::NotFound(format!("Task with id {} not found", id)))?;
+        policy::authorize(&principal, Action::Update, Some(task.value()))?;
+    }
+
+    let storage = &state.attachments;
+    let mut staged = Vec::new();
+    let content_type = req.mime_type().ok().flatten();
+    if content_type.as_ref().is_some_and(|content_type| {
+        content_type.essence_str() == mime::MULTIPART_FORM_DATA.essence_str()
+    }) {
+        let mut multipart = Multipart::new(req.headers(), payload);
+        while let Some(field) = multipart.next().await {
+            let mut field = field.map_err(multipart_error)?;
+            let Some(filename) = field
+                .content_disposition()
+                .and_then(|disposition| disposition.get_filename())
+                .map(attachments::sanitize_filename)
+                .transpose()?
+            else {
+                // Parts other than files are skipped
+                while let Some(chunk) = field.next().await {
+                    chunk.map_err(multipart_error)?;
+                }
+                continue;
+            };
+            let content_type = storage.check_type(field.content_type())?;
+            let (upload, size) = attachments::stage(
+                storage.store(),
+                field.map(|chunk| chunk.map_err(multipart_error)),
+            )
+            .await?;
+            staged.push(StagedUpload {
+                filename,
+                content_type,
+                size,
+                upload,
+            });
+        }
+        if staged.is_empty() {
+            return Err(AppError::Validation(
+                "The upload contains no files".to_string(),
+            ));
+        }
+    } else {
+        let filename = query
+            .filename
+            .as_deref()
+            .ok_or_else(|| AppError::Validation("Uploads need a filename".to_string()))?;
+        let filename = attachments::sanitize_filename(filename)?;
+        let content_type = storage.check_type(content_type.as_ref())?;
+        let (upload, size) = attachments::stage(
+            storage.store(),
+            payload.map(|chunk| chunk.map_err(payload_error)),
+        )
+        .await?;
+        staged.push(StagedUpload {
+            filename,
+            content_type,
+            size,
+            upload,
+        });
+    }
+
+    // The task stays locked so it cannot be deleted under the new attachments
+    let task = tenant
+        .tasks
+        .get(&id)
+        .ok_or_else(|| AppError::NotFound(format!("Task with id {} not found", id)))?;
+    let total: u64 = staged.iter().map(|staged| staged.size).sum();
+    tenant.resize(0, total)?;
+    let mut reserved = total;
+    let mut created = Vec::with_capacity(staged.len());
+    for staged in staged {
+        let attachment = storage.commit(staged.upload, |blob| {
+            let attachment = Attachment {
+                id: Uuid::new_v4(),
+                task_id: id,
+                filename: staged.filename,
+                content_type: staged.content_type.to_string(),
+                size: blob.size,
+                sha256: blob.digest,
+                uploader_id: principal.id.clone(),
+                created_at: Utc::now(),
+            };
+            audit.record("attachment.create", attachment.id, None, Some(&attachment))?;
+            tenant.attachments.add(attachment.clone());
+            Ok(attachment)
+        });
+        match attachment {
+            Ok(attachment) => {
+                reserved -= attachment.size;
+                created.push(attachment);
+            }
+            Err(e) => {
+                tenant.resize(reserved, 0)?;
+                return Err(e);
+            }
+        }
+    }
+    drop(task);
+
+    Ok(HttpResponse::Created().json(created))
+}
+
+/// Get the details of one attachment
+pub async fn get_attachment(
+    principal: Principal,
+    path: web::Path<(String, String)>,
+    tenant: CurrentTenant,
+) -> Result<HttpResponse, AppError> {
+    let attachment = readable_attachment(&principal, &tenant, path.into_inner())?;
+    Ok(HttpResponse::Ok().json(attachment))
+}
+
+/// Download an attachment, or the byte range named by a `Range` header
+pub async fn download_attachment(
+    principal: Principal,
+    path: web::Path<(String, String)>,
+    req: HttpRequest,
+    tenant: CurrentTenant,
     state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let attachment = readable_attachment(&principal, &tenant, path.into_inner())?;
+    let etag = format!("\"{}\"", attachment.sha256);
+    let range = attachments::requested_range(req.headers(), attachment.size, &etag)?;
+    let (start, len) = range.map_or((0, attachment.size), |(start, end)| {
+        (start, end - start + 1)
+    });
+    let reader =
+        attachments::open_at(state.attachments.store(), attachment.sha256.clone(), start).await?;
+
+    let mut response = match range {
+        Some((start, end)) => {
+            let mut response = HttpResponse::PartialContent();
+            response.insert_header((
+                CONTENT_RANGE,
+                format!("bytes {}-{}/{}", start, end, attachment.size),
+            ));
+            response
+        }
+        None => HttpResponse::Ok(),
+    };
+    Ok(response
+        .insert_header((CONTENT_TYPE, attachment.content_type.as_str()))
+        .insert_header(attachments::content_disposition(&attachment.filename))
+        .insert_header((ETAG, etag))
+        .insert_header((ACCEPT_RANGES, "bytes"))
+        // Served as stored, so lengths and ranges refer to the same bytes
+        .insert_header((CONTENT_ENCODING, "identity"))
+        .body(SizedStream::new(len, attachments::read_stream(reader, len))))
+}
+
+/// Remove an attachment; its content goes once no attachment uses it
+pub async fn delete_attachment(
+    principal: Principal,
+    path: web::Path<(String, String)>,
+    tenant: CurrentTenant,
+    audit: AuditContext,
+) -> Result<HttpResponse, AppError> {
+    let (id, attachment_id) = path.into_inner();
+    let id =
+        Uuid::from_str(&id).map_err(|_| AppError::Validation("Invalid UUID format".to_string()))?;
+    let attachment_id = Uuid::from_str(&attachment_id)
+        .map_err(|_| AppError::Validation("Invalid UUID format".to_string()))?;
+    {
+        let task = tenant
+            .tasks
+            .get(&id)
+            .ok_or_else(|| AppError::NotFound(format!("Task with id {} not found", id)))?;
+        policy::authorize(&principal, Action::Update, Some(task.value()))?;
+    }
+
+    let removed = tenant
+        .attachments
+        .remove_one(&id, &attachment_id, |attachment| {
+            audit.record("attachment.delete", attachment_id, Some(attachment), None)
+        })?
+        .ok_or_else(|| attachment_not_found(attachment_id))?;
+    tenant.resize(removed.size, 0)?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+fn readable_attachment(
+    principal: &Principal,
+    tenant: &CurrentTenant,
+    (id, attachment_id): (String, String),
+) -> Result<Attachment, AppError> {
+    let id = readable_task(principal, tenant, &id)?;
+    let attachment_id = Uuid::from_str(&attachment_id)
+        .map_err(|_| AppError::Validation("Invalid UUID format".to_string()))?;
+    tenant
+        .attachments
+        .get(&id, &attachment_id)
+        .ok_or_else(|| attachment_not_found(attachment_id))
+}
+
+fn attachment_not_found(id: Uuid) -> AppError {
+    AppError::NotFound(format!("Attachment {} not found", id))
+}
+
+fn payload_error(e: PayloadError) -> AppError {
+    match e {
+        PayloadError::Overflow => {
+            AppError::PayloadTooLarge("Attachment exceeds the configured limit".to_string())
+        }
+        e => AppError::Validation(format!("Failed to read upload: {}", e)),
+    }
+}
+
+fn multipart_error(e: MultipartError) -> AppError {
+    match e {
+        MultipartError::Payload(e) => payload_error(e),
+        e => AppError::Validation(format!("Invalid multipart upload: {}", e)),
+    }
+}
+
+/// List trashed tasks the caller may read, most recently deleted first
+pub async fn list_trash(principal: Principal, tenant: CurrentTenant) -> impl Responder {
+    let mut tasks: Vec<Task> = tenant
+        .trash
+        .iter()
+        .filter(|entry| policy::can(&principal, Action::Read, Some(entry.value())))
+        .map(|entry| entry.value().clone())
+        .collect();
+    tasks.sort_by_key(|task| std::cmp::Reverse(task.deleted_at));
+
+    HttpResponse::Ok().json(tasks)
+}
+
+/// Move a trashed task back to the live store
+pub async fn restore_task(
+    principal: Principal,
+    path: web::Path<String>,
+    tenant: CurrentTenant,
+    audit: AuditContext,
+) -> Result<HttpResponse, AppError> {
+    let id = Uuid::from_str(&path.into_inner())
+        .map_err(|_| AppError::Validation("Invalid UUID format".to_string()))?;
+
+    let task = trash::restore(&tenant, &id, &audit.principal.id, |task, restored| {
+        policy::authorize(&principal, Action::Delete, Some(task))
+            .and_then(|_| audit.record("task.untrash", id, Some(task), Some(restored)))
+    })?
+    .ok_or_else(|| AppError::NotFound(format!("Task with id {} not found in trash", id)))?;
+
+    Ok(HttpResponse::Ok().json(present(&tenant, task)))
+}
+
+/// Permanently delete one trashed task
+pub async fn purge_task(
+    principal: Principal,
+    path: web::Path<String>,
+    tenant: CurrentTenant,
+    audit: AuditContext,
 ) -> Result<HttpResponse, AppError> {
     let id = Uuid::from_str(&path.into_inner())
         .map_err(|_| AppError::Validation("Invalid UUID format".to_string()))?;
 
-    state
+    trash::purge(&tenant, &id, |task| {
+        policy::authorize(&principal, Action::Read, Some(task))
+            .and_then(|_| policy::authorize(&principal, Action::Purge, Some(task)))
+            .and_then(|_| audit.record("task.purge", id, Some(task), None))
+    })?
+    .ok_or_else(|| AppError::NotFound(format!("Task with id {} not found in trash", id)))?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Permanently delete every trashed task of the tenant
+pub async fn empty_trash(
+    principal: Principal,
+    tenant: CurrentTenant,
+    audit: AuditContext,
+) -> Result<HttpResponse, AppError> {
+    policy::authorize(&principal, Action::Purge, None)?;
+
+    let ids: Vec<Uuid> = tenant.trash.iter().map(|entry| *entry.key()).collect();
+    let mut purged = 0;
+    for id in ids {
+        let task = trash::purge(&tenant, &id, |task| {
+            audit.record("task.purge", id, Some(task), None)
+        })?;
+        purged += usize::from(task.is_some());
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "purged": purged })))
+}
+
+/// List the retained revisions of a task
+pub async fn list_revisions(
+    principal: Principal,
+    path: web::Path<String>,
+    tenant: CurrentTenant,
+) -> Result<HttpResponse, AppError> {
+    let id = readable_task(&principal, &tenant, &path)?;
+
+    Ok(HttpResponse::Ok().json(tenant.revisions.list(&id)))
+}
+
+/// Get a single revision of a task
+pub async fn get_revision(
+    principal: Principal,
+    path: web::Path<(String, u64)>,
+    tenant: CurrentTenant,
+) -> Result<HttpResponse, AppError> {
+    let (id, number) = path.into_inner();
+    let id = readable_task(&principal, &tenant, &id)?;
+    let revision = tenant
+        .revisions
+        .get(&id, number)
+        .ok_or_else(|| revision_not_found(id, number))?;
+
+    Ok(HttpResponse::Ok().json(revision))
+}
+
+/// Field-level diff between two revisions of a task
+pub async fn diff_revisions(
+    principal: Principal,
+    path: web::Path<String>,
+    query: web::Query<DiffQuery>,
+    tenant: CurrentTenant,
+) -> Result<HttpResponse, AppError> {
+    let id = readable_task(&principal, &tenant, &path)?;
+    let revision = |number| {
+        tenant
+            .revisions
+            .get(&id, number)
+            .ok_or_else(|| revision_not_found(id, number))
+    };
+
+    Ok(HttpResponse::Ok().json(RevisionDiff::between(
+        &revision(query.from)?,
+        &revision(query.to)?,
+    )))
+}
+
+/// Restore a task to the content of an earlier revision, as a new revision
+pub async fn restore_revision(
+    principal: Principal,
+    path: web::Path<(String, u64)>,
+    tenant: CurrentTenant,
+    audit: AuditContext,
+) -> Result<HttpResponse, AppError> {
+    let (id, number) = path.into_inner();
+    let id =
+        Uuid::from_str(&id).map_err(|_| AppError::Validation("Invalid UUID format".to_string()))?;
+
+    // Restoring a completed revision completes the task, which is checked
+    // like a completion sent to update_task
+    let revision = tenant.revisions.get(&id, number);
+    let completing = revision
+        .as_ref()
+        .is_some_and(|revision| revision.task.completed);
+    let _dependencies = completing.then(|| tenant.dependencies.lock().unwrap());
+    let blockers = if completing {
+        completion_blockers(&tenant, &id)
+    } else {
+        Vec::new()
+    };
+
+    let mut task = tenant
         .tasks
-        .remove(&id)
+        .get_mut(&id)
         .ok_or_else(|| AppError::NotFound(format!("Task with id {} not found", id)))?;
+    policy::authorize(&principal, Action::Update, Some(task.value()))?;
+    let old = revision.ok_or_else(|| revision_not_found(id, number))?.task;
+    check_unblocked(&id, &blockers)?;
+
+    let was_open = !task.completed;
+    let mut restored = task.value().clone();
+    restored.update(Some(old.title), Some(old.description), Some(old.completed));
+    restored.due_at = old.due_at;
+    restored.priority = old.priority;
+    restored.set_reminders(old.reminders.iter().map(|r| r.at).collect());
+    restored.labels = old.labels;
+    tenant.commit_update(&audit, "task.restore", &mut task, restored)?;
+    let restored = task.value().clone();
+
+    drop(task);
+    if was_open && restored.completed {
+        advance_series(&tenant, &audit, &restored);
+    }
+
+    Ok(HttpResponse::Ok().json(present(&tenant, restored)))
+}
+
+/// Most reminders a single task may carry
+const MAX_REMINDERS: usize = 20;
+
+/// Checks reminder times sent by a client; times not already set on the
+/// task must lie in the future
+fn validate_reminders(times: &[DateTime<Utc>], existing: &[Reminder]) -> Result<(), AppError> {
+    if times.len() > MAX_REMINDERS {
+        return Err(AppError::Validation(format!(
+            "A task can have at most {} reminders",
+            MAX_REMINDERS
+        )));
+    }
+    let now = Utc::now();
+    let new_in_past = times
+        .iter()
+        .any(|at| *at <= now && !existing.iter().any(|r| r.at == *at));
+    if new_in_past {
+        return Err(AppError::Validation(
+            "Reminder times must be in the future".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Parses a task id and checks the caller may read the task
+fn readable_task(
+    principal: &Principal,
+    tenant: &CurrentTenant,
+    id: &str,
+) -> Result<Uuid, AppError> {
+    let id =
+        Uuid::from_str(id).map_err(|_| AppError::Validation("Invalid UUID format".to_string()))?;
+    let task = tenant
+        .tasks
+        .get(&id)
+        .ok_or_else(|| AppError::NotFound(format!("Task with id {} not found", id)))?;
+    policy::authorize(principal, Action::Read, Some(task.value()))?;
+    Ok(id)
+}
+
+fn revision_not_found(id: Uuid, number: u64) -> AppError {
+    AppError::NotFound(format!("Revision {} of task {} not found", number, id))
+}
+
+/// Export the tasks the caller may read as a newline-delimited JSON stream
+pub async fn export_tasks(principal: Principal, tenant: CurrentTenant) -> impl Responder {
+    let include = move |task: &Task| policy::can(&principal, Action::Read, Some(task));
+    HttpResponse::Ok()
+        .content_type(transfer::NDJSON_CONTENT_TYPE)
+        .streaming(transfer::export_stream(tenant.tasks.clone(), include))
+}
+
+/// Import tasks from a newline-delimited JSON stream
+pub async fn import_tasks(
+    principal: Principal,
+    mut payload: web::Payload,
+    query: web::Query<ImportQuery>,
+    tenant: CurrentTenant,
+    audit: AuditContext,
+) -> Result<HttpResponse, AppError> {
+    policy::authorize(&principal, Action::Import, None)?;
+
+    let mut importer = Importer::new(tenant.entry(), audit, query.mode);
+
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| match e {
+            PayloadError::Overflow => {
+                AppError::PayloadTooLarge("Import body exceeds the configured limit".to_string())
+            }
+            e => AppError::Validation(format!("Failed to read request body: {}", e)),
+        })?;
+        importer.feed(&chunk);
+        if importer.is_aborted() {
+            break;
+        }
+    }
+
+    let report = importer.finish();
+    if report.aborted {
+        return Ok(HttpResponse::Conflict().json(report));
+    }
+
+    Ok(HttpResponse::Ok().json(report))
+}
+
+/// List all tenants
+pub async fn list_tenants(
+    principal: Principal,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    policy::authorize(&principal, Action::ManageTenants, None)?;
+    Ok(HttpResponse::Ok().json(state.tenants.list()))
+}
+
+/// Create a tenant
+pub async fn create_tenant(
+    principal: Principal,
+    req: web::Json<CreateTenantRequest>,
+    state: web::Data<AppState>,
+    audit: AuditContext,
+) -> Result<HttpResponse, AppError> {
+    policy::authorize(&principal, Action::ManageTenants, None)?;
+    let req = req.into_inner();
+    let tenant = state.tenants.create(&req.id, req.quota, |tenant| {
+        audit.record("tenant.create", &tenant.id, None, Some(tenant))
+    })?;
+
+    Ok(HttpResponse::Created().json(tenant))
+}
+
+/// Get a single tenant
+pub async fn get_tenant(
+    principal: Principal,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    policy::authorize(&principal, Action::ManageTenants, None)?;
+    let id = path.into_inner();
+    let tenant = state
+        .tenants
+        .get(&id)
+        .ok_or_else(|| AppError::NotFound(format!("Tenant '{}' not found", id)))?;
+
+    Ok(HttpResponse::Ok().json(tenant.snapshot()))
+}
+
+/// Replace a tenant's quota
+pub async fn update_tenant_quota(
+    principal: Principal,
+    path: web::Path<String>,
+    req: web::Json<TenantQuota>,
+    state: web::Data<AppState>,
+    audit: AuditContext,
+) -> Result<HttpResponse, AppError> {
+    policy::authorize(&principal, Action::ManageTenants, None)?;
+    let tenant = state
+        .tenants
+        .set_quota(&path, req.into_inner(), |before, after| {
+            audit.record("tenant.quota", &before.id, Some(before), Some(after))
+        })?;
+
+    Ok(HttpResponse::Ok().json(tenant))
+}
+
+/// Suspend a tenant, rejecting its requests while keeping its data
+pub async fn suspend_tenant(
+    principal: Principal,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+    audit: AuditContext,
+) -> Result<HttpResponse, AppError> {
+    policy::authorize(&principal, Action::ManageTenants, None)?;
+    let tenant = state
+        .tenants
+        .set_status(&path, TenantStatus::Suspended, |before, after| {
+            audit.record("tenant.suspend", &before.id, Some(before), Some(after))
+        })?;
+
+    Ok(HttpResponse::Ok().json(tenant))
+}
+
+/// Resume a suspended tenant
+pub async fn resume_tenant(
+    principal: Principal,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+    audit: AuditContext,
+) -> Result<HttpResponse, AppError> {
+    policy::authorize(&principal, Action::ManageTenants, None)?;
+    let tenant = state
+        .tenants
+        .set_status(&path, TenantStatus::Active, |before, after| {
+            audit.record("tenant.resume", &before.id, Some(before), Some(after))
+        })?;
+
+    Ok(HttpResponse::Ok().json(tenant))
+}
+
+/// Delete a tenant and all of its tasks
+pub async fn delete_tenant(
+    principal: Principal,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+    audit: AuditContext,
+) -> Result<HttpResponse, AppError> {
+    policy::authorize(&principal, Action::ManageTenants, None)?;
+    state.tenants.delete(&path, |tenant| {
+        audit.record("tenant.delete", &tenant.id, Some(tenant), None)
+    })?;
 
     Ok(HttpResponse::NoContent().finish())
 }
+
+/// Usage of the caller's tenant this month
+pub async fn get_usage(principal: Principal, tenant: CurrentTenant) -> impl Responder {
+    let mut report = UsageReport::for_tenant(&tenant);
+    // Only admins see the request counts of other principals
+    if principal.role != Role::Admin {
+        report.principals.retain(|id, _| *id == principal.id);
+    }
+
+    HttpResponse::Ok().json(report)
+}
+
+/// Usage of every tenant this month
+pub async fn list_usage(
+    principal: Principal,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    policy::authorize(&principal, Action::ManageTenants, None)?;
+    let reports: Vec<UsageReport> = state
+        .tenants
+        .entries()
+        .iter()
+        .map(|tenant| UsageReport::for_tenant(tenant))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(reports))
+}
+
+/// Query the audit log of the caller's tenant
+pub async fn get_audit_log(
+    principal: Principal,
+    query: web::Query<AuditQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    policy::authorize(&principal, Action::ReadAudit, None)?;
+
+    Ok(HttpResponse::Ok().json(state.audit.query(&principal.tenant, &query)))
+}
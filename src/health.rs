@@ -0,0 +1,333 @@
+use chrono::{DateTime, Utc};
+use futures_util::future::{join_all, BoxFuture};
+use serde::{Deserialize, Serialize};
+use std::{
+    future::Future,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicI64, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+/// Kubernetes-style probe a check contributes to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Probe {
+    Live,
+    Ready,
+    Startup,
+}
+
+/// Outcome of a single check or of a whole probe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// Result returned by a check function
+#[derive(Debug, Clone)]
+pub struct CheckOutcome {
+    pub status: CheckStatus,
+    pub message: Option<String>,
+}
+
+impl CheckOutcome {
+    pub fn pass() -> Self {
+        Self {
+            status: CheckStatus::Pass,
+            message: None,
+        }
+    }
+
+    pub fn pass_with(message: impl Into<String>) -> Self {
+        Self {
+            status: CheckStatus::Pass,
+            message: Some(message.into()),
+        }
+    }
+
+    pub fn warn(message: impl Into<String>) -> Self {
+        Self {
+            status: CheckStatus::Warn,
+            message: Some(message.into()),
+        }
+    }
+
+    pub fn fail(message: impl Into<String>) -> Self {
+        Self {
+            status: CheckStatus::Fail,
+            message: Some(message.into()),
+        }
+    }
+}
+
+type CheckFn = Arc<dyn Fn() -> BoxFuture<'static, CheckOutcome> + Send + Sync>;
+
+struct RegisteredCheck {
+    name: String,
+    probes: Vec<Probe>,
+    critical: bool,
+    timeout: Duration,
+    check: CheckFn,
+}
+
+/// Per-check entry of a probe report
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckReport {
+    pub name: String,
+    pub status: CheckStatus,
+    pub critical: bool,
+    pub duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Detailed response body of the probe endpoints
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthReport {
+    pub probe: Probe,
+    pub status: CheckStatus,
+    pub timestamp: DateTime<Utc>,
+    pub version: String,
+    pub checks: Vec<CheckReport>,
+}
+
+impl HealthReport {
+    /// Whether the probe should be answered with a success status code
+    pub fn is_healthy(&self) -> bool {
+        self.status != CheckStatus::Fail
+    }
+}
+
+/// Timeout of health checks when `health.check_timeout_ms` is not set
+pub const DEFAULT_CHECK_TIMEOUT_MS: u64 = 1000;
+
+/// Registry of named health checks shared by the probe endpoints
+///
+/// A critical check failing fails the probe (503); a non-critical failure
+/// only degrades it to `warn`. Checks exceeding their timeout count as
+/// failed.
+#[derive(Clone)]
+pub struct HealthRegistry {
+    checks: Arc<RwLock<Vec<Arc<RegisteredCheck>>>>,
+    started: Arc<AtomicBool>,
+    default_timeout: Duration,
+}
+
+impl HealthRegistry {
+    /// A registry whose checks time out after `default_timeout` unless
+    /// registered with their own
+    pub fn new(default_timeout: Duration) -> Self {
+        Self {
+            checks: Arc::new(RwLock::new(Vec::new())),
+            started: Arc::new(AtomicBool::new(false)),
+            default_timeout,
+        }
+    }
+
+    /// Registers a check with the registry's default timeout
+    pub fn register<F, Fut>(&self, name: &str, probes: &[Probe], critical: bool, check: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = CheckOutcome> + Send + 'static,
+    {
+        self.register_with_timeout(name, probes, critical, self.default_timeout, check);
+    }
+
+    /// Registers a check, replacing any existing check of the same name
+    pub fn register_with_timeout<F, Fut>(
+        &self,
+        name: &str,
+        probes: &[Probe],
+        critical: bool,
+        timeout: Duration,
+        check: F,
+    ) where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = CheckOutcome> + Send + 'static,
+    {
+        let entry = Arc::new(RegisteredCheck {
+            name: name.to_string(),
+            probes: probes.to_vec(),
+            critical,
+            timeout,
+            check: Arc::new(move || Box::pin(check())),
+        });
+
+        let mut checks = self.checks.write().unwrap();
+        checks.retain(|c| c.name != name);
+        checks.push(entry);
+    }
+
+    /// Registers a liveness check fed by a background worker's heartbeat.
+    ///
+    /// The worker must call [`Heartbeat::beat`] at least once every
+    /// `max_silence`; otherwise the check fails and liveness goes red.
+    pub fn worker(&self, name: &str, max_silence: Duration) -> Heartbeat {
+        let heartbeat = Heartbeat::new();
+        let beat = heartbeat.clone();
+        self.register(
+            &format!("worker:{}", name),
+            &[Probe::Live],
+            true,
+            move || {
+                let silence = beat.silence();
+                async move {
+                    if silence <= max_silence {
+                        CheckOutcome::pass()
+                    } else {
+                        CheckOutcome::fail(format!("No heartbeat for {}s", silence.as_secs()))
+                    }
+                }
+            },
+        );
+        heartbeat
+    }
+
+    /// Marks initialisation complete so the startup probe can succeed
+    pub fn mark_started(&self) {
+        self.started.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.started.load(Ordering::SeqCst)
+    }
+
+    /// Runs every check registered for `probe` concurrently
+    pub async fn report(&self, probe: Probe) -> HealthReport {
+        let checks: Vec<_> = self
+            .checks
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|c| c.probes.contains(&probe))
+            .cloned()
+            .collect();
+
+        let mut reports = join_all(checks.iter().map(|c| run_check(c))).await;
+
+        if probe == Probe::Startup && !self.is_started() {
+            reports.push(CheckReport {
+                name: "startup".to_string(),
+                status: CheckStatus::Fail,
+                critical: true,
+                duration_ms: 0,
+                message: Some("Initialisation has not completed".to_string()),
+            });
+        }
+
+        let status = reports
+            .iter()
+            .map(|r| match (r.status, r.critical) {
+                (CheckStatus::Fail, false) => CheckStatus::Warn,
+                (status, _) => status,
+            })
+            .max()
+            .unwrap_or(CheckStatus::Pass);
+
+        HealthReport {
+            probe,
+            status,
+            timestamp: Utc::now(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            checks: reports,
+        }
+    }
+}
+
+async fn run_check(check: &RegisteredCheck) -> CheckReport {
+    let start = Instant::now();
+    let outcome = match tokio::time::timeout(check.timeout, (check.check)()).await {
+        Ok(outcome) => outcome,
+        Err(_) => CheckOutcome::fail(format!("Timed out after {}ms", check.timeout.as_millis())),
+    };
+
+    CheckReport {
+        name: check.name.clone(),
+        status: outcome.status,
+        critical: check.critical,
+        duration_ms: start.elapsed().as_millis() as u64,
+        message: outcome.message,
+    }
+}
+
+/// Liveness signal emitted by a background worker
+#[derive(Clone)]
+pub struct Heartbeat {
+    last: Arc<AtomicI64>,
+}
+
+impl Heartbeat {
+    fn new() -> Self {
+        Self {
+            last: Arc::new(AtomicI64::new(Utc::now().timestamp_millis())),
+        }
+    }
+
+    /// Records that the worker is still making progress
+    pub fn beat(&self) {
+        self.last
+            .store(Utc::now().timestamp_millis(), Ordering::Relaxed);
+    }
+
+    /// Time since the last heartbeat
+    pub fn silence(&self) -> Duration {
+        let elapsed = Utc::now().timestamp_millis() - self.last.load(Ordering::Relaxed);
+        Duration::from_millis(elapsed.max(0) as u64)
+    }
+}
+
+/// Check that the filesystem holding `path` has at least `min_free` bytes
+pub fn disk_space_check(
+    path: PathBuf,
+    min_free: u64,
+) -> impl Fn() -> BoxFuture<'static, CheckOutcome> + Send + Sync {
+    move || {
+        let path = path.clone();
+        Box::pin(async move {
+            let result = tokio::task::spawn_blocking(move || free_disk_bytes(&path)).await;
+            match result {
+                Ok(Ok(free)) if free >= min_free => {
+                    CheckOutcome::pass_with(format!("{} bytes free", free))
+                }
+                Ok(Ok(free)) => CheckOutcome::fail(format!(
+                    "{} bytes free, below the minimum of {}",
+                    free, min_free
+                )),
+                Ok(Err(e)) => CheckOutcome::fail(format!("Cannot stat filesystem: {}", e)),
+                Err(e) => CheckOutcome::fail(e.to_string()),
+            }
+        })
+    }
+}
+
+#[cfg(unix)]
+fn free_disk_bytes(path: &std::path::Path) -> std::io::Result<u64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is a valid NUL-terminated string and `stat` is a
+    // properly sized, writable statvfs struct.
+    let rc = unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) };
+    if rc != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn free_disk_bytes(_path: &std::path::Path) -> std::io::Result<u64> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "disk space checks are only supported on Unix",
+    ))
+}
@@ -0,0 +1,134 @@
+use crate::{models::Task, tenancy::TaskStore};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use uuid::Uuid;
+
+/// What happens to the subtasks of a deleted task
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubtaskMode {
+    /// Subtasks move to the top level
+    #[default]
+    Orphan,
+    /// Subtasks are deleted along with their parent, at every depth
+    Cascade,
+    /// Tasks with subtasks cannot be deleted
+    Forbid,
+}
+
+/// Request DTO for moving a task under a new parent
+#[derive(Debug, Deserialize)]
+pub struct MoveTaskRequest {
+    /// New parent; `null` moves the task to the top level
+    pub parent_id: Option<Uuid>,
+}
+
+/// A task with its subtasks, as returned by the subtree endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskNode {
+    #[serde(flatten)]
+    pub task: Task,
+    /// Percentage of completed descendants; only set on tasks with subtasks
+    pub progress: Option<u8>,
+    pub children: Vec<TaskNode>,
+}
+
+/// Direct subtasks of a task
+pub fn children(tasks: &TaskStore, id: &Uuid) -> Vec<Uuid> {
+    tasks
+        .iter()
+        .filter(|entry| entry.parent_id == Some(*id))
+        .map(|entry| *entry.key())
+        .collect()
+}
+
+/// Subtasks of a task at every depth, parents before their children
+pub fn descendants(tasks: &TaskStore, id: &Uuid) -> Vec<Uuid> {
+    let mut by_parent: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+    for entry in tasks.iter() {
+        if let Some(parent) = entry.parent_id {
+            by_parent.entry(parent).or_default().push(*entry.key());
+        }
+    }
+
+    // Each task is visited once, so a bad parent link cannot stall the walk
+    let mut seen = HashSet::from([*id]);
+    let mut queue = VecDeque::from([*id]);
+    let mut found = Vec::new();
+    while let Some(next) = queue.pop_front() {
+        for child in by_parent.remove(&next).unwrap_or_default() {
+            if seen.insert(child) {
+                found.push(child);
+                queue.push_back(child);
+            }
+        }
+    }
+    found
+}
+
+/// Whether `ancestor` is `id` or one of its ancestors
+pub fn is_ancestor(tasks: &TaskStore, ancestor: &Uuid, id: &Uuid) -> bool {
+    let mut seen = HashSet::new();
+    let mut current = Some(*id);
+    while let Some(next) = current {
+        if next == *ancestor {
+            return true;
+        }
+        if !seen.insert(next) {
+            return false;
+        }
+        current = tasks.get(&next).and_then(|task| task.parent_id);
+    }
+    false
+}
+
+/// The subtree rooted at `root`, keeping only tasks `include` accepts
+pub fn subtree(tasks: &TaskStore, root: Task, include: impl Fn(&Task) -> bool) -> TaskNode {
+    let mut by_parent: HashMap<Uuid, Vec<Task>> = HashMap::new();
+    for entry in tasks.iter() {
+        if let Some(parent) = entry.parent_id {
+            if include(entry.value()) {
+                by_parent
+                    .entry(parent)
+                    .or_default()
+                    .push(entry.value().clone());
+            }
+        }
+    }
+    for children in by_parent.values_mut() {
+        children.sort_by_key(|task| task.created_at);
+    }
+
+    let mut seen = HashSet::new();
+    build(root, &mut by_parent, &mut seen).0
+}
+
+/// Builds a node, returning it with its number of descendants and how many
+/// of them are completed
+fn build(
+    task: Task,
+    by_parent: &mut HashMap<Uuid, Vec<Task>>,
+    seen: &mut HashSet<Uuid>,
+) -> (TaskNode, usize, usize) {
+    seen.insert(task.id);
+    let (mut total, mut completed) = (0, 0);
+    let mut nodes = Vec::new();
+    for child in by_parent.remove(&task.id).unwrap_or_default() {
+        if seen.contains(&child.id) {
+            continue;
+        }
+        let done = child.completed;
+        let (node, child_total, child_completed) = build(child, by_parent, seen);
+        total += 1 + child_total;
+        completed += usize::from(done) + child_completed;
+        nodes.push(node);
+    }
+
+    let progress = (total > 0).then(|| (completed * 100 / total) as u8);
+    let node = TaskNode {
+        task,
+        progress,
+        children: nodes,
+    };
+    (node, total, completed)
+}
@@ -0,0 +1,238 @@
+use crate::{errors::AppError, tenancy::TaskStore};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+use uuid::Uuid;
+
+/// Longest accepted label, in characters
+pub const MAX_LABEL_CHARS: usize = 64;
+
+/// Most labels a single task can carry
+pub const MAX_LABELS: usize = 50;
+
+/// Normalises a label: trimmed, lowercase, with inner whitespace as `-`.
+///
+/// Letters, digits and `-_:/.` are accepted, so names like `team/backend`
+/// or `component:api` work as namespaces.
+pub fn normalize(raw: &str) -> Result<String, AppError> {
+    let label = raw
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join("-")
+        .to_lowercase();
+    let invalid =
+        |reason: &str| AppError::Validation(format!("Invalid label '{}': {}", raw, reason));
+    if label.is_empty() {
+        return Err(invalid("it is empty"));
+    }
+    if label.chars().count() > MAX_LABEL_CHARS {
+        return Err(invalid(&format!(
+            "longer than {} characters",
+            MAX_LABEL_CHARS
+        )));
+    }
+    if let Some(c) = label
+        .chars()
+        .find(|c| !c.is_alphanumeric() && !"-_:/.".contains(*c))
+    {
+        return Err(invalid(&format!("'{}' is not allowed", c)));
+    }
+    Ok(label)
+}
+
+/// Normalises a task's labels into a sorted set
+pub fn normalize_all(raw: &[String]) -> Result<Vec<String>, AppError> {
+    let mut labels = raw
+        .iter()
+        .map(|label| normalize(label))
+        .collect::<Result<Vec<_>, _>>()?;
+    labels.sort();
+    labels.dedup();
+    if labels.len() > MAX_LABELS {
+        return Err(AppError::Validation(format!(
+            "A task can have at most {} labels",
+            MAX_LABELS
+        )));
+    }
+    Ok(labels)
+}
+
+/// Deserialises a comma-separated query value into normalised labels
+pub fn comma_separated<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw = String::deserialize(deserializer)?;
+    raw.split(',')
+        .filter(|label| !label.trim().is_empty())
+        .map(|label| normalize(label).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+/// A catalogue entry describing a label
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Label {
+    pub name: String,
+    /// Display colour as `#rrggbb`
+    pub color: Option<String>,
+    pub description: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Request DTO for creating or updating a catalogue entry
+#[derive(Debug, Deserialize)]
+pub struct LabelRequest {
+    pub color: Option<String>,
+    pub description: Option<String>,
+}
+
+impl LabelRequest {
+    pub fn validate(&self) -> Result<(), AppError> {
+        let valid = self.color.as_ref().is_none_or(|color| {
+            color.len() == 7
+                && color.starts_with('#')
+                && color[1..].chars().all(|c| c.is_ascii_hexdigit())
+        });
+        if !valid {
+            return Err(AppError::Validation(
+                "Label colours must look like #1f6feb".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Request DTO for changing a task's labels without resending the task
+#[derive(Debug, Default, Deserialize)]
+pub struct LabelChanges {
+    #[serde(default)]
+    pub add: Vec<String>,
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
+impl LabelChanges {
+    /// The labels of a task with the changes applied; removals win
+    pub fn apply(&self, labels: &[String]) -> Result<Vec<String>, AppError> {
+        let remove = normalize_all(&self.remove)?;
+        let mut changed = labels.to_vec();
+        changed.extend(self.add.iter().cloned());
+        let mut changed = normalize_all(&changed)?;
+        changed.retain(|label| !remove.contains(label));
+        Ok(changed)
+    }
+}
+
+/// A catalogue entry, or a label only found on tasks, as listed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LabelSummary {
+    pub name: String,
+    pub color: Option<String>,
+    pub description: Option<String>,
+    /// Whether the label has a catalogue entry
+    pub catalogued: bool,
+}
+
+/// A tenant's label catalogue and inverted index from label to tasks
+#[derive(Default)]
+pub struct Labels {
+    pub catalogue: DashMap<String, Label>,
+    index: RwLock<HashMap<String, HashSet<Uuid>>>,
+}
+
+impl Labels {
+    /// Moves a task in the index from its `before` labels to its `after` ones
+    pub fn reindex(&self, id: Uuid, before: &[String], after: &[String]) {
+        if before == after {
+            return;
+        }
+        let mut index = self.index.write().unwrap();
+        for label in before.iter().filter(|label| !after.contains(label)) {
+            if let Some(tasks) = index.get_mut(label) {
+                tasks.remove(&id);
+                if tasks.is_empty() {
+                    index.remove(label);
+                }
+            }
+        }
+        for label in after {
+            index.entry(label.clone()).or_default().insert(id);
+        }
+    }
+
+    /// Indexes every task of the store from scratch
+    pub fn rebuild(&self, tasks: &TaskStore) {
+        let mut index: HashMap<String, HashSet<Uuid>> = HashMap::new();
+        for task in tasks.iter() {
+            for label in &task.labels {
+                index.entry(label.clone()).or_default().insert(task.id);
+            }
+        }
+        *self.index.write().unwrap() = index;
+    }
+
+    /// Tasks carrying every label in `all` and, unless it is empty, one in
+    /// `any`; `None` when neither list constrains the selection
+    pub fn select(&self, all: &[String], any: &[String]) -> Option<HashSet<Uuid>> {
+        if all.is_empty() && any.is_empty() {
+            return None;
+        }
+        let index = self.index.read().unwrap();
+        let tasks = |label: &String| index.get(label).cloned().unwrap_or_default();
+
+        // Starting from the rarest label keeps the intersections small
+        let mut all: Vec<&String> = all.iter().collect();
+        all.sort_by_key(|label| index.get(*label).map_or(0, HashSet::len));
+        let mut selected = match all.split_first() {
+            Some((first, rest)) => {
+                let mut selected = tasks(first);
+                for label in rest {
+                    let Some(with) = index.get(*label) else {
+                        return Some(HashSet::new());
+                    };
+                    selected.retain(|id| with.contains(id));
+                }
+                Some(selected)
+            }
+            None => None,
+        };
+        if !any.is_empty() {
+            let with_any: HashSet<Uuid> = any.iter().flat_map(tasks).collect();
+            selected = Some(match selected {
+                Some(selected) => selected.intersection(&with_any).copied().collect(),
+                None => with_any,
+            });
+        }
+        selected
+    }
+
+    /// Catalogue entries and labels in use, by name
+    pub fn summaries(&self) -> Vec<LabelSummary> {
+        let mut summaries: Vec<LabelSummary> = self
+            .catalogue
+            .iter()
+            .map(|label| LabelSummary {
+                name: label.name.clone(),
+                color: label.color.clone(),
+                description: label.description.clone(),
+                catalogued: true,
+            })
+            .collect();
+        let in_use: Vec<String> = self.index.read().unwrap().keys().cloned().collect();
+        for name in in_use {
+            if !self.catalogue.contains_key(&name) {
+                summaries.push(LabelSummary {
+                    name,
+                    color: None,
+                    description: None,
+                    catalogued: false,
+                });
+            }
+        }
+        summaries.sort_by(|a, b| a.name.cmp(&b.name));
+        summaries
+    }
+}
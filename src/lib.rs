@@ -1,44 +1,208 @@
+pub mod attachments;
+pub mod audit;
+pub mod blobs;
+pub mod cli;
+pub mod comments;
 pub mod config;
+pub mod dependencies;
 pub mod errors;
 pub mod handlers;
+pub mod health;
+pub mod hierarchy;
+pub mod labels;
+pub mod metrics;
 pub mod middleware;
 pub mod models;
+pub mod network;
+pub mod policy;
+pub mod recurrence;
+pub mod reload;
+pub mod reminders;
+pub mod revisions;
 pub mod routes;
+pub mod search;
+pub mod secrets;
+pub mod shutdown;
 pub mod state;
+pub mod tenancy;
+pub mod tls;
+pub mod transfer;
+pub mod trash;
+pub mod usage;
 
 use actix_web::{web, App, HttpServer};
-use config::Config;
 use errors::AppError;
-use middleware::{auth::ApiKeyAuth, logging::RequestLogging};
+use health::Probe;
+use middleware::{
+    auth::ApiKeyAuth, compression::ResponseCompression, cors::Cors, ip_filter::IpFilter,
+    logging::RequestLogging, security_headers::SecurityHeaders,
+};
+use reload::ConfigHandle;
 use routes::configure_routes;
 use state::AppState;
+use std::{path::PathBuf, sync::Mutex, time::Duration};
+use tracing::warn;
 
 /// Creates and configures the Actix-web application
-pub async fn create_app(config: Config) -> Result<actix_web::dev::Server, AppError> {
-    // Initialize application state
-    let app_state = AppState::new();
+///
+/// Signal handling is left to the caller (see `shutdown::run_until_signal`)
+/// so that readiness can be failed before the listeners close. Only the
+/// reloadable sections of `config_handle` are re-read after startup.
+pub async fn create_app(
+    config_handle: ConfigHandle,
+    app_state: AppState,
+) -> Result<actix_web::dev::Server, AppError> {
+    let config = config_handle.current();
+    let server_config = config.server.clone();
+    let shutdown = app_state.shutdown.clone();
+
+    if let Some(path) = &config.health.disk_path {
+        app_state.health.register(
+            "disk",
+            &[Probe::Ready, Probe::Startup],
+            true,
+            health::disk_space_check(path.into(), config.health.min_free_disk_bytes),
+        );
+    }
+    let health = app_state.health.clone();
+
+    // Retained audit records are loaded, and API key changes are audited
+    app_state.audit.open(&config.audit)?;
+    let audit = app_state.audit.clone();
+    let previous_api = Mutex::new(config.api.clone());
+    config_handle.on_reload(move |config| {
+        let mut previous = previous_api.lock().unwrap();
+        if let Err(e) = audit.record_api_changes(&previous, &config.api) {
+            warn!(error = %e, "Failed to audit API key changes");
+        }
+        *previous = config.api.clone();
+    });
+
+    // Tenants referenced by configured principals exist before they are used
+    app_state
+        .tenants
+        .set_revision_retention(config.revisions.clone());
+    app_state.tenants.provision(&config);
+    let tenants = app_state.tenants.clone();
+    config_handle.on_reload(move |config| tenants.provision(config));
+    app_state.tenants.rebuild_indexes();
+
+    // Monthly request counters survive restarts when a persist path is set
+    if let Some(path) = &config.usage.persist_path {
+        let path = PathBuf::from(path);
+        if let Err(e) = usage::restore(&app_state.tenants, &path) {
+            warn!(path = %path.display(), error = %e, "Failed to restore usage counters");
+        }
+        let interval = Duration::from_secs(config.usage.persist_interval_secs);
+        shutdown.spawn(usage::persist_usage(
+            app_state.tenants.clone(),
+            path,
+            interval,
+            health.worker("usage-persister", interval * 3),
+            shutdown.token(),
+        ));
+    }
+
+    // Trashed tasks are purged once they outlive the retention period
+    let purge_interval = Duration::from_secs(config.trash.purge_interval_secs);
+    shutdown.spawn(trash::purge_trash(
+        app_state.tenants.clone(),
+        config.trash.retention_days,
+        purge_interval,
+        app_state.audit.clone(),
+        health.worker("trash-purger", purge_interval * 3),
+        shutdown.token(),
+    ));
+
+    // Attachment blobs no longer referenced by any task are removed
+    app_state.attachments.configure(&config.attachments)?;
+    let gc_interval = Duration::from_secs(config.attachments.gc_interval_secs);
+    shutdown.spawn(attachments::collect_blobs(
+        app_state.attachments.clone(),
+        app_state.tenants.clone(),
+        gc_interval,
+        health.worker("blob-collector", gc_interval * 3),
+        shutdown.token(),
+    ));
+
+    // Reminders fire from a background scheduler
+    let reminder_interval = Duration::from_secs(config.reminders.poll_interval_secs);
+    shutdown.spawn(reminders::run_reminders(
+        app_state.tenants.clone(),
+        reminder_interval,
+        app_state.metrics.clone(),
+        health.worker(
+            "reminder-scheduler",
+            reminder_interval.max(Duration::from_secs(60)) * 3,
+        ),
+        shutdown.token(),
+    ));
+
+    // Reload the configuration on SIGHUP and config file changes
+    config_handle.register_health_check(&health);
+    config_handle.register_metrics(&app_state.metrics);
+    let reload_interval = Duration::from_secs(server_config.config_reload_interval_secs);
+    shutdown.spawn(reload::watch_config(
+        config_handle.clone(),
+        reload_interval,
+        health.worker(
+            "config-reloader",
+            reload_interval.max(Duration::from_secs(60)) * 3,
+        ),
+        shutdown.token(),
+    ));
 
     // Build the HTTP server
     let server = HttpServer::new(move || {
         App::new()
             // Attach application state
             .app_data(web::Data::new(app_state.clone()))
-            // Configure CORS
-            .wrap(actix_cors::Cors::default()
-                .allow_any_origin()
-                .allow_any_method()
-                .allow_any_header()
-                .max_age(3600))
+            .app_data(web::Data::new(config_handle.clone()))
             // Request logging middleware with trace IDs
-            .wrap(RequestLogging::default())
+            .wrap(RequestLogging::new(config_handle.clone()))
             // API key authentication middleware (for protected routes)
-            .wrap(ApiKeyAuth::new(&config.api.api_key))
+            .wrap(ApiKeyAuth::new(config_handle.clone()))
+            // CORS from the live configuration; outside auth so preflights pass
+            .wrap(Cors::new(config_handle.clone()))
+            // Real client address behind proxies, and IP allow/deny rules
+            .wrap(IpFilter::new(
+                config_handle.clone(),
+                app_state.metrics.clone(),
+            ))
+            // Hardening headers on every response, including rejections
+            .wrap(SecurityHeaders::new(config_handle.clone()))
+            // Response compression negotiated via Accept-Encoding
+            .wrap(ResponseCompression::new(&config.compression))
             // Configure routes
-            .configure(configure_routes)
-    })
-    .bind((config.server.address.as_str(), config.server.port))?
-    .workers(config.server.workers)
-    .run();
+            .configure(|cfg| configure_routes(cfg, &config.limits))
+    });
+
+    let addr = (server_config.address.as_str(), server_config.port);
+    let server = if server_config.tls.enabled {
+        // Native TLS termination with certificates reloaded on renewal
+        let (rustls_config, resolver) = tls::server_config(&server_config.tls)?;
+        let interval = Duration::from_secs(server_config.tls.reload_interval_secs.max(1));
+        shutdown.spawn(tls::watch_certificates(
+            resolver,
+            interval,
+            health.worker("tls-reloader", interval * 3),
+            shutdown.token(),
+        ));
+
+        server
+            .on_connect(tls::on_connect)
+            .bind_rustls_0_23(addr, rustls_config)?
+    } else {
+        server.bind(addr)?
+    };
+
+    // Workers get a second beyond the drain deadline so that the deadline,
+    // not actix, decides whether shutdown was clean
+    let server = server
+        .workers(server_config.workers)
+        .disable_signals()
+        .shutdown_timeout(server_config.shutdown_timeout_secs + 1)
+        .run();
 
     Ok(server)
 }
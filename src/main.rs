@@ -1,28 +1,73 @@
+use clap::Parser;
 use rust_high_performance_api_server::{
+    cli::Cli,
     config::Config,
     create_app,
     errors::AppError,
+    reload::ConfigHandle,
+    secrets,
+    shutdown::{self, DrainSettings},
+    state::AppState,
 };
-use tracing::{info, error};
+use std::{process::ExitCode, time::Duration};
+use tracing::{error, info};
 use tracing_subscriber::EnvFilter;
 
 #[actix_web::main]
-async fn main() -> Result<(), AppError> {
-    // Initialize tracing subscriber with environment-based filtering
-    tracing_subscriber::fmt()
+async fn main() -> Result<ExitCode, AppError> {
+    let cli = Cli::parse();
+
+    // Initialize tracing subscriber with environment-based filtering; the
+    // filter is replaced by `logging.level` once configuration is loaded
+    let subscriber = tracing_subscriber::fmt()
         .with_env_filter(
-            EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| EnvFilter::new("info")),
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
         )
         .with_target(false)
         .with_thread_ids(true)
         .with_file(true)
         .with_line_number(true)
-        .init();
+        .with_filter_reloading();
+    let log_filter = subscriber.reload_handle();
+    subscriber.init();
 
-    // Load configuration
+    // Load configuration: defaults, file, environment, then CLI flags
     dotenvy::dotenv().ok(); // Load .env if present, ignore if not
-    let config = Config::from_env()?;
+    if cli.generate_secrets_key {
+        println!("{}", secrets::generate_key()?);
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Some(path) = &cli.encrypt_secrets {
+        let plaintext = std::fs::read_to_string(path)?;
+        print!(
+            "{}",
+            secrets::encrypt(&plaintext, &secrets::key_from_env()?)?
+        );
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let sources = cli.config_sources();
+    let config = Config::load(&sources)?;
+
+    let validation = config.validate();
+
+    if cli.print_config {
+        let rendered =
+            toml::to_string_pretty(&config).map_err(|e| AppError::Internal(e.to_string()))?;
+        println!("{}", rendered);
+    }
+
+    // Refuse to start with a configuration that cannot work
+    if let Err(problems) = validation {
+        for problem in &problems {
+            error!(problem = %problem, "Invalid configuration");
+        }
+        return Ok(ExitCode::FAILURE);
+    }
+
+    if cli.print_config {
+        return Ok(ExitCode::SUCCESS);
+    }
 
     info!(
         address = %config.server.address,
@@ -30,12 +75,42 @@ async fn main() -> Result<(), AppError> {
         "Starting Rust High-Performance API Server"
     );
 
+    // Apply the configured log level now and after every reload
+    let set_log_level = move |config: &Config| {
+        if let Ok(filter) = EnvFilter::try_new(&config.logging.level) {
+            let _ = log_filter.reload(filter);
+        }
+    };
+    set_log_level(&config);
+
+    let settings = DrainSettings {
+        readiness_delay: Duration::from_secs(config.server.readiness_delay_secs),
+        deadline: Duration::from_secs(config.server.shutdown_timeout_secs),
+    };
+
     // Create and run the application
-    let app = create_app(config).await?;
+    let app_state =
+        AppState::with_check_timeout(Duration::from_millis(config.health.check_timeout_ms));
+    let shutdown = app_state.shutdown.clone();
+    let health = app_state.health.clone();
+    let audit = app_state.audit.clone();
+    let config_handle = ConfigHandle::new(config, sources);
+    config_handle.on_reload(set_log_level);
+    let app = create_app(config_handle, app_state).await?;
+    health.mark_started();
 
     info!("Server is ready to accept connections");
-    
-    app.await?;
 
-    Ok(())
+    // Exit non-zero when draining did not complete, so orchestrators can tell
+    let drained = shutdown::run_until_signal(app, shutdown, settings).await;
+    if let Err(e) = audit.sync() {
+        error!(error = %e, "Audit records were lost at shutdown");
+    }
+    if drained {
+        info!("Shutdown complete");
+        Ok(ExitCode::SUCCESS)
+    } else {
+        error!("Shutdown deadline exceeded; exiting with unfinished work");
+        Ok(ExitCode::FAILURE)
+    }
 }
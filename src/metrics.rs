@@ -0,0 +1,127 @@
+use dashmap::DashMap;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+/// Content type of the Prometheus text exposition format
+pub const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// In-process counters and gauges exposed on `/metrics` in Prometheus text
+/// format
+///
+/// Cloning is cheap; every clone updates the same metrics.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    counters: Arc<DashMap<String, Counter>>,
+}
+
+struct Counter {
+    help: &'static str,
+    /// Prometheus metric type, `counter` or `gauge`
+    kind: &'static str,
+    series: DashMap<String, AtomicU64>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds one to the counter `name` with the given label values
+    pub fn increment(&self, name: &str, help: &'static str, labels: &[(&str, &str)]) {
+        self.series(name, help, "counter", labels, |value| {
+            value.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+
+    /// Sets the gauge `name` with the given label values to `value`
+    pub fn set(&self, name: &str, help: &'static str, labels: &[(&str, &str)], value: u64) {
+        self.series(name, help, "gauge", labels, |current| {
+            current.store(value, Ordering::Relaxed);
+        });
+    }
+
+    fn series(
+        &self,
+        name: &str,
+        help: &'static str,
+        kind: &'static str,
+        labels: &[(&str, &str)],
+        update: impl FnOnce(&AtomicU64),
+    ) {
+        let counter = self
+            .counters
+            .entry(name.to_string())
+            .or_insert_with(|| Counter {
+                help,
+                kind,
+                series: DashMap::new(),
+            });
+        let value = counter
+            .series
+            .entry(format_labels(labels))
+            .or_insert_with(|| AtomicU64::new(0));
+        update(&value);
+    }
+
+    /// Current value of one counter or gauge series, zero if never set
+    pub fn counter(&self, name: &str, labels: &[(&str, &str)]) -> u64 {
+        self.counters
+            .get(name)
+            .and_then(|c| {
+                c.series
+                    .get(&format_labels(labels))
+                    .map(|v| v.load(Ordering::Relaxed))
+            })
+            .unwrap_or(0)
+    }
+
+    /// Renders every metric, sorted by name and labels for stable output
+    pub fn render(&self) -> String {
+        type Rendered = (&'static str, &'static str, BTreeMap<String, u64>);
+        let counters: BTreeMap<String, Rendered> = self
+            .counters
+            .iter()
+            .map(|c| {
+                let series = c
+                    .series
+                    .iter()
+                    .map(|s| (s.key().clone(), s.value().load(Ordering::Relaxed)))
+                    .collect();
+                (c.key().clone(), (c.help, c.kind, series))
+            })
+            .collect();
+
+        let mut out = String::new();
+        for (name, (help, kind, series)) in counters {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            for (labels, value) in series {
+                let _ = writeln!(out, "{}{} {}", name, labels, value);
+            }
+        }
+        out
+    }
+}
+
+fn format_labels(labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let pairs: Vec<String> = labels
+        .iter()
+        .map(|(k, v)| {
+            let v = v
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", k, v)
+        })
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
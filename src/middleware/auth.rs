@@ -1,4 +1,12 @@
+use crate::{
+    config::{Config, ADMIN_PRINCIPAL},
+    network::ClientIp,
+    policy::{Principal, Role},
+    reload::ConfigHandle,
+    tls::ClientCertificate,
+};
 use actix_web::{
+    body::EitherBody,
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
     Error, HttpMessage,
 };
@@ -10,15 +18,17 @@ use std::{
 use tracing::warn;
 
 /// API Key authentication middleware
+///
+/// Keys are read from the live configuration on every request, so a rotated
+/// key applies as soon as the configuration is reloaded. The caller's
+/// [`Principal`] is inserted into request extensions.
 pub struct ApiKeyAuth {
-    api_key: String,
+    config: ConfigHandle,
 }
 
 impl ApiKeyAuth {
-    pub fn new(api_key: &str) -> Self {
-        Self {
-            api_key: api_key.to_string(),
-        }
+    pub fn new(config: ConfigHandle) -> Self {
+        Self { config }
     }
 }
 
@@ -28,7 +38,7 @@ where
     S::Future: 'static,
     B: 'static,
 {
-    type Response = ServiceResponse<B>;
+    type Response = ServiceResponse<EitherBody<B>>;
     type Error = Error;
     type InitError = ();
     type Transform = ApiKeyAuthMiddleware<S>;
@@ -37,14 +47,14 @@ where
     fn new_transform(&self, service: S) -> Self::Future {
         ready(Ok(ApiKeyAuthMiddleware {
             service: Rc::new(service),
-            api_key: self.api_key.clone(),
+            config: self.config.clone(),
         }))
     }
 }
 
 pub struct ApiKeyAuthMiddleware<S> {
     service: Rc<S>,
-    api_key: String,
+    config: ConfigHandle,
 }
 
 impl<S, B> Service<ServiceRequest> for ApiKeyAuthMiddleware<S>
@@ -53,17 +63,30 @@ where
     S::Future: 'static,
     B: 'static,
 {
-    type Response = ServiceResponse<B>;
+    type Response = ServiceResponse<EitherBody<B>>;
     type Error = Error;
     type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
 
     forward_ready!(service);
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        // Skip authentication for health check endpoint
-        if req.path() == "/health" {
+        // Expose the verified mutual TLS client certificate, if any
+        let client_cert = req.conn_data::<ClientCertificate>().cloned();
+        let subject = client_cert.as_ref().map(|c| c.subject.clone());
+        if let Some(cert) = client_cert {
+            req.extensions_mut().insert(cert);
+        }
+
+        // Skip authentication for health check and probe endpoints
+        let path = req.match_info().as_str();
+        if path == "/health" || path.starts_with("/health/") {
             let service = Rc::clone(&self.service);
-            return Box::pin(async move { service.call(req).await });
+            return Box::pin(async move {
+                service
+                    .call(req)
+                    .await
+                    .map(ServiceResponse::map_into_left_body)
+            });
         }
 
         // Extract API key from header
@@ -73,25 +96,47 @@ where
             .and_then(|h| h.to_str().ok())
             .map(|s| s.to_string());
 
-        match api_key_header {
-            Some(key) if key == self.api_key => {
+        let config = self.config.current();
+        match api_key_header.and_then(|key| identify(&config, &key)) {
+            Some(principal) => {
+                req.extensions_mut().insert(principal);
                 let service = Rc::clone(&self.service);
-                Box::pin(async move { service.call(req).await })
+                Box::pin(async move {
+                    service
+                        .call(req)
+                        .await
+                        .map(ServiceResponse::map_into_left_body)
+                })
             }
-            _ => {
+            None => {
+                let client_ip = req.extensions().get::<ClientIp>().map(|c| c.0);
                 warn!(
                     path = %req.path(),
+                    client_ip = ?client_ip,
+                    client_cert = subject.as_deref().unwrap_or("-"),
                     "Unauthorized request - missing or invalid API key"
                 );
                 Box::pin(async move {
-                    let resp = actix_web::HttpResponse::Unauthorized()
-                        .json(serde_json::json!({
-                            "error": "Unauthorized",
-                            "message": "Missing or invalid API key. Provide X-API-Key header."
-                        }));
-                    Ok(ServiceResponse::new(req.into_parts().0, resp))
+                    let resp = actix_web::HttpResponse::Unauthorized().json(serde_json::json!({
+                        "error": "Unauthorized",
+                        "message": "Missing or invalid API key. Provide X-API-Key header."
+                    }));
+                    Ok(req.into_response(resp).map_into_right_body())
                 })
             }
         }
     }
 }
+
+/// Finds the principal whose key matches `key`
+fn identify(config: &Config, key: &str) -> Option<Principal> {
+    if config.api.api_key.matches(key) {
+        return Some(Principal::new(ADMIN_PRINCIPAL, Role::Admin));
+    }
+    config
+        .api
+        .principals
+        .iter()
+        .find(|(_, p)| p.key.matches(key))
+        .map(|(id, p)| Principal::new(id.clone(), p.role).in_tenant(p.tenant.clone()))
+}
@@ -0,0 +1,284 @@
+use actix_http::BoxedPayloadStream;
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    error::PayloadError,
+    http::header::{ContentEncoding, CONTENT_ENCODING, CONTENT_LENGTH},
+    web::Bytes,
+    Error, HttpMessage,
+};
+use futures_util::{
+    future::LocalBoxFuture,
+    stream::{self, StreamExt},
+};
+use std::{
+    future::{ready, Ready},
+    io::{self, Write},
+    rc::Rc,
+};
+
+use crate::errors::AppError;
+
+/// Request body limit middleware with bounded decompression
+///
+/// Bodies sent with `Content-Encoding: gzip`, `deflate`, `br` or `zstd` are
+/// decoded here rather than by the extractors, so that the limit applies to
+/// the decompressed size and a small compressed body cannot expand into an
+/// arbitrarily large buffer. Exceeding the limit surfaces as
+/// `PayloadError::Overflow`, which handlers report as 413.
+#[derive(Clone)]
+pub struct BodyLimit {
+    max_bytes: usize,
+}
+
+impl BodyLimit {
+    pub fn new(max_bytes: usize) -> Self {
+        Self { max_bytes }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for BodyLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = BodyLimitMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(BodyLimitMiddleware {
+            service: Rc::new(service),
+            max_bytes: self.max_bytes,
+        }))
+    }
+}
+
+pub struct BodyLimitMiddleware<S> {
+    service: Rc<S>,
+    max_bytes: usize,
+}
+
+impl<S, B> Service<ServiceRequest> for BodyLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let max_bytes = self.max_bytes;
+
+        let encoding = match req.headers().get(CONTENT_ENCODING) {
+            None => ContentEncoding::Identity,
+            Some(value) => match value
+                .to_str()
+                .ok()
+                .map(|v| v.trim().parse::<ContentEncoding>())
+            {
+                Some(Ok(encoding)) => encoding,
+                _ => {
+                    let err = AppError::UnsupportedMediaType(format!(
+                        "Unsupported Content-Encoding: {}",
+                        String::from_utf8_lossy(value.as_bytes())
+                    ));
+                    return Box::pin(
+                        async move { Ok(req.error_response(err).map_into_right_body()) },
+                    );
+                }
+            },
+        };
+
+        // Reject early when the declared length alone is over the limit
+        let declared = req
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|s| s.parse::<usize>().ok());
+        if encoding == ContentEncoding::Identity && declared.is_some_and(|len| len > max_bytes) {
+            let err = AppError::PayloadTooLarge(format!(
+                "Request body exceeds the limit of {} bytes",
+                max_bytes
+            ));
+            return Box::pin(async move { Ok(req.error_response(err).map_into_right_body()) });
+        }
+
+        let decoder = match ContentDecoder::new(encoding, max_bytes) {
+            Ok(decoder) => decoder,
+            Err(e) => {
+                let err = AppError::Internal(format!("Failed to initialise decoder: {}", e));
+                return Box::pin(async move { Ok(req.error_response(err).map_into_right_body()) });
+            }
+        };
+
+        if encoding != ContentEncoding::Identity {
+            // The body is handed on decoded; stop extractors from decoding it again
+            req.headers_mut().remove(CONTENT_ENCODING);
+            req.headers_mut().remove(CONTENT_LENGTH);
+        }
+
+        let payload = limited_payload(req.take_payload(), decoder);
+        req.set_payload(payload);
+
+        let service = Rc::clone(&self.service);
+        Box::pin(async move {
+            service
+                .call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        })
+    }
+}
+
+/// Wraps a payload so that it is decoded and counted against the limit
+fn limited_payload(inner: Payload, decoder: ContentDecoder) -> Payload {
+    let stream = stream::unfold(Some((inner, decoder)), |state| async move {
+        let (mut inner, mut decoder) = state?;
+
+        loop {
+            match inner.next().await {
+                Some(Ok(chunk)) => match decoder.feed(chunk) {
+                    Ok(out) if out.is_empty() => continue,
+                    Ok(out) => return Some((Ok(out), Some((inner, decoder)))),
+                    Err(e) => return Some((Err(e), None)),
+                },
+                Some(Err(e)) => return Some((Err(e), None)),
+                None => {
+                    return match decoder.finish() {
+                        Ok(out) if out.is_empty() => None,
+                        Ok(out) => Some((Ok(out), None)),
+                        Err(e) => Some((Err(e), None)),
+                    };
+                }
+            }
+        }
+    });
+
+    // Fused: some consumers, like multipart parsing, poll again after the end
+    let stream: BoxedPayloadStream = stream.fuse().boxed_local();
+    Payload::from(stream)
+}
+
+/// Output sink that refuses to grow past the body limit
+struct LimitedWriter {
+    buf: Vec<u8>,
+    written: usize,
+    limit: usize,
+    overflowed: bool,
+}
+
+impl LimitedWriter {
+    fn new(limit: usize) -> Self {
+        Self {
+            buf: Vec::new(),
+            written: 0,
+            limit,
+            overflowed: false,
+        }
+    }
+
+    fn take(&mut self) -> Bytes {
+        Bytes::from(std::mem::take(&mut self.buf))
+    }
+}
+
+impl Write for LimitedWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if self.written + data.len() > self.limit {
+            self.overflowed = true;
+            return Err(io::Error::other("decoded body exceeds limit"));
+        }
+        self.written += data.len();
+        self.buf.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+enum ContentDecoder {
+    Identity(LimitedWriter),
+    Gzip(Box<flate2::write::GzDecoder<LimitedWriter>>),
+    Deflate(Box<flate2::write::ZlibDecoder<LimitedWriter>>),
+    Brotli(Box<brotli::DecompressorWriter<LimitedWriter>>),
+    Zstd(Box<zstd::stream::write::Decoder<'static, LimitedWriter>>),
+}
+
+impl ContentDecoder {
+    fn new(encoding: ContentEncoding, limit: usize) -> io::Result<Self> {
+        let writer = LimitedWriter::new(limit);
+        Ok(match encoding {
+            ContentEncoding::Gzip => Self::Gzip(Box::new(flate2::write::GzDecoder::new(writer))),
+            ContentEncoding::Deflate => {
+                Self::Deflate(Box::new(flate2::write::ZlibDecoder::new(writer)))
+            }
+            ContentEncoding::Brotli => {
+                Self::Brotli(Box::new(brotli::DecompressorWriter::new(writer, 8 * 1024)))
+            }
+            ContentEncoding::Zstd => {
+                Self::Zstd(Box::new(zstd::stream::write::Decoder::new(writer)?))
+            }
+            _ => Self::Identity(writer),
+        })
+    }
+
+    fn writer(&mut self) -> &mut LimitedWriter {
+        match self {
+            Self::Identity(w) => w,
+            Self::Gzip(d) => d.get_mut(),
+            Self::Deflate(d) => d.get_mut(),
+            Self::Brotli(d) => d.get_mut(),
+            Self::Zstd(d) => d.get_mut(),
+        }
+    }
+
+    /// Decodes a chunk and returns whatever output it produced
+    fn feed(&mut self, chunk: Bytes) -> Result<Bytes, PayloadError> {
+        let res = match self {
+            Self::Identity(w) => {
+                // Nothing to decode; count the chunk and pass it through untouched
+                if w.written + chunk.len() > w.limit {
+                    return Err(PayloadError::Overflow);
+                }
+                w.written += chunk.len();
+                return Ok(chunk);
+            }
+            Self::Gzip(d) => d.write_all(&chunk).and_then(|_| d.flush()),
+            Self::Deflate(d) => d.write_all(&chunk).and_then(|_| d.flush()),
+            Self::Brotli(d) => d.write_all(&chunk).and_then(|_| d.flush()),
+            Self::Zstd(d) => d.write_all(&chunk).and_then(|_| d.flush()),
+        };
+        self.output(res)
+    }
+
+    /// Flushes any buffered output at end of stream
+    fn finish(&mut self) -> Result<Bytes, PayloadError> {
+        let res = match self {
+            Self::Identity(_) => Ok(()),
+            Self::Gzip(d) => d.try_finish(),
+            Self::Deflate(d) => d.try_finish(),
+            Self::Brotli(d) => d.close().map_err(io::Error::other),
+            Self::Zstd(d) => d.flush(),
+        };
+        self.output(res)
+    }
+
+    fn output(&mut self, res: io::Result<()>) -> Result<Bytes, PayloadError> {
+        let writer = self.writer();
+        if writer.overflowed {
+            return Err(PayloadError::Overflow);
+        }
+        res.map_err(|e| PayloadError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        Ok(writer.take())
+    }
+}
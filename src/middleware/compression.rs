@@ -0,0 +1,162 @@
+use actix_http::encoding::Encoder;
+use actix_web::{
+    body::{BodySize, MessageBody},
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{ContentEncoding, ACCEPT_ENCODING},
+    Error,
+};
+use futures_util::future::LocalBoxFuture;
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+};
+use tracing::warn;
+
+use crate::config::CompressionConfig;
+
+/// Response compression middleware negotiated via `Accept-Encoding`
+///
+/// Unlike `actix_web::middleware::Compress`, the set of encodings and their
+/// preference order come from configuration, and responses with a known size
+/// below `min_size` are sent as-is.
+#[derive(Clone)]
+pub struct ResponseCompression {
+    encodings: Rc<Vec<ContentEncoding>>,
+    min_size: usize,
+}
+
+impl ResponseCompression {
+    pub fn new(config: &CompressionConfig) -> Self {
+        let encodings = if config.enabled {
+            parse_encodings(&config.encodings)
+        } else {
+            Vec::new()
+        };
+
+        Self {
+            encodings: Rc::new(encodings),
+            min_size: config.min_size,
+        }
+    }
+}
+
+/// Parses a comma-separated encoding list, ignoring unknown and identity entries
+fn parse_encodings(list: &str) -> Vec<ContentEncoding> {
+    let mut encodings = Vec::new();
+    for name in list.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        match name.parse::<ContentEncoding>() {
+            Ok(ContentEncoding::Identity) => {}
+            Ok(encoding) if !encodings.contains(&encoding) => encodings.push(encoding),
+            Ok(_) => {}
+            Err(_) => warn!(encoding = %name, "Ignoring unsupported response encoding"),
+        }
+    }
+    encodings
+}
+
+/// Picks the best configured encoding accepted by the client.
+///
+/// Higher q-values win; ties are broken by the configured preference order.
+/// A `*` entry applies to every configured encoding not listed explicitly.
+fn negotiate(accept: &str, supported: &[ContentEncoding]) -> ContentEncoding {
+    let mut wildcard = None;
+    let mut explicit: Vec<(&str, f32)> = Vec::new();
+
+    for item in accept.split(',') {
+        let mut parts = item.split(';');
+        let name = parts.next().unwrap_or("").trim();
+        if name.is_empty() {
+            continue;
+        }
+        let q = parts
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        if name == "*" {
+            wildcard = Some(q);
+        } else {
+            explicit.push((name, q));
+        }
+    }
+
+    let mut best = (ContentEncoding::Identity, 0.0_f32);
+    for encoding in supported {
+        let q = explicit
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(encoding.as_str()))
+            .map(|(_, q)| *q)
+            .or(wildcard)
+            .unwrap_or(0.0);
+
+        if q > best.1 {
+            best = (*encoding, q);
+        }
+    }
+
+    best.0
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ResponseCompression
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<Encoder<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ResponseCompressionMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ResponseCompressionMiddleware {
+            service: Rc::new(service),
+            encodings: Rc::clone(&self.encodings),
+            min_size: self.min_size,
+        }))
+    }
+}
+
+pub struct ResponseCompressionMiddleware<S> {
+    service: Rc<S>,
+    encodings: Rc<Vec<ContentEncoding>>,
+    min_size: usize,
+}
+
+impl<S, B> Service<ServiceRequest> for ResponseCompressionMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<Encoder<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let encoding = req
+            .headers()
+            .get(ACCEPT_ENCODING)
+            .and_then(|h| h.to_str().ok())
+            .map(|accept| negotiate(accept, &self.encodings))
+            .unwrap_or(ContentEncoding::Identity);
+
+        let min_size = self.min_size;
+        let service = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            let res = service.call(req).await?;
+
+            Ok(res.map_body(move |head, body| {
+                let encoding = match body.size() {
+                    BodySize::Sized(len) if len < min_size as u64 => ContentEncoding::Identity,
+                    _ => encoding,
+                };
+                Encoder::response(encoding, head, body)
+            }))
+        })
+    }
+}
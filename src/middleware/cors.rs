@@ -0,0 +1,254 @@
+use crate::{config::CorsConfig, reload::ConfigHandle};
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{
+        header::{self, HeaderMap, HeaderValue},
+        Method,
+    },
+    Error, HttpResponse,
+};
+use futures_util::future::LocalBoxFuture;
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+};
+use tracing::warn;
+
+/// CORS middleware driven by the `cors` configuration section
+///
+/// The policy is read from the live configuration on every request, so
+/// origin changes apply as soon as the configuration is reloaded. Must wrap
+/// outside `ApiKeyAuth`, since browsers send preflights without credentials.
+#[derive(Clone)]
+pub struct Cors {
+    config: ConfigHandle,
+}
+
+impl Cors {
+    pub fn new(config: ConfigHandle) -> Self {
+        Self { config }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Cors
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = CorsMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CorsMiddleware {
+            service: Rc::new(service),
+            config: self.config.clone(),
+        }))
+    }
+}
+
+pub struct CorsMiddleware<S> {
+    service: Rc<S>,
+    config: ConfigHandle,
+}
+
+impl<S, B> Service<ServiceRequest> for CorsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        // Same-origin and non-browser requests carry no Origin header
+        let Some(origin) = req
+            .headers()
+            .get(header::ORIGIN)
+            .and_then(|h| h.to_str().ok())
+            .map(str::to_string)
+        else {
+            return Box::pin(async move {
+                service
+                    .call(req)
+                    .await
+                    .map(ServiceResponse::map_into_left_body)
+            });
+        };
+
+        let config = self.config.current();
+        let policy = &config.cors;
+        let allowed = origin_allowed(policy, &origin);
+
+        let requested_method = req
+            .headers()
+            .get(header::ACCESS_CONTROL_REQUEST_METHOD)
+            .and_then(|h| h.to_str().ok());
+        if req.method() == Method::OPTIONS && requested_method.is_some() {
+            let result = check_preflight(policy, allowed, requested_method, req.headers());
+            let resp = match result {
+                Ok(()) => {
+                    let mut resp = HttpResponse::NoContent().finish();
+                    let headers = resp.headers_mut();
+                    headers.insert(header::VARY, HeaderValue::from_static("Origin"));
+                    add_origin_headers(headers, policy, &origin);
+                    insert_list(
+                        headers,
+                        header::ACCESS_CONTROL_ALLOW_METHODS,
+                        &policy.allowed_methods,
+                    );
+                    insert_list(
+                        headers,
+                        header::ACCESS_CONTROL_ALLOW_HEADERS,
+                        &allow_headers(policy, req.headers()),
+                    );
+                    if let Ok(value) = HeaderValue::from_str(&policy.max_age_secs.to_string()) {
+                        headers.insert(header::ACCESS_CONTROL_MAX_AGE, value);
+                    }
+                    resp
+                }
+                Err(reason) => {
+                    warn!(
+                        origin = %origin,
+                        path = %req.path(),
+                        reason,
+                        "Rejected CORS preflight"
+                    );
+                    HttpResponse::Forbidden().json(serde_json::json!({
+                        "error": "Forbidden",
+                        "message": format!("CORS preflight rejected: {}", reason)
+                    }))
+                }
+            };
+            return Box::pin(async move { Ok(req.into_response(resp).map_into_right_body()) });
+        }
+
+        let policy = policy.clone();
+        Box::pin(async move {
+            let mut res = service.call(req).await?;
+            let headers = res.headers_mut();
+            headers.append(header::VARY, HeaderValue::from_static("Origin"));
+            if allowed {
+                add_origin_headers(headers, &policy, &origin);
+                insert_list(
+                    headers,
+                    header::ACCESS_CONTROL_EXPOSE_HEADERS,
+                    &policy.exposed_headers,
+                );
+            }
+            Ok(res.map_into_left_body())
+        })
+    }
+}
+
+/// Whether `origin` matches an allowed origin pattern.
+///
+/// Patterns are `*`, an exact origin such as `https://app.example.com`, or a
+/// wildcard subdomain such as `https://*.example.com`, which matches any
+/// subdomain depth but not `https://example.com` itself.
+pub fn origin_matches(pattern: &str, origin: &str) -> bool {
+    if pattern == "*" {
+        return true;
+    }
+    match pattern.split_once("://*.") {
+        Some((scheme, domain)) => origin
+            .strip_prefix(scheme)
+            .and_then(|rest| rest.strip_prefix("://"))
+            .and_then(|host| host.strip_suffix(domain))
+            .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+        None => pattern.eq_ignore_ascii_case(origin),
+    }
+}
+
+fn origin_allowed(policy: &CorsConfig, origin: &str) -> bool {
+    split_list(&policy.allowed_origins).any(|pattern| origin_matches(pattern, origin))
+}
+
+fn check_preflight(
+    policy: &CorsConfig,
+    origin_allowed: bool,
+    method: Option<&str>,
+    headers: &HeaderMap,
+) -> Result<(), &'static str> {
+    if !origin_allowed {
+        return Err("origin not allowed");
+    }
+
+    let method = method.unwrap_or_default();
+    if !split_list(&policy.allowed_methods).any(|m| m.eq_ignore_ascii_case(method)) {
+        return Err("method not allowed");
+    }
+
+    let any_header = split_list(&policy.allowed_headers).any(|h| h == "*");
+    let all_allowed = requested_headers(headers).all(|requested| {
+        any_header
+            || split_list(&policy.allowed_headers).any(|h| h.eq_ignore_ascii_case(&requested))
+    });
+    if !all_allowed {
+        return Err("header not allowed");
+    }
+
+    Ok(())
+}
+
+/// Headers to list in `Access-Control-Allow-Headers`; `*` echoes the request
+fn allow_headers(policy: &CorsConfig, headers: &HeaderMap) -> String {
+    if split_list(&policy.allowed_headers).any(|h| h == "*") {
+        requested_headers(headers).collect::<Vec<_>>().join(",")
+    } else {
+        policy.allowed_headers.clone()
+    }
+}
+
+fn add_origin_headers(headers: &mut HeaderMap, policy: &CorsConfig, origin: &str) {
+    // A literal `*` cannot be combined with credentials, so echo the origin then
+    let wildcard = split_list(&policy.allowed_origins).any(|p| p == "*");
+    let value = if wildcard && !policy.allow_credentials {
+        HeaderValue::from_static("*")
+    } else {
+        match HeaderValue::from_str(origin) {
+            Ok(value) => value,
+            Err(_) => return,
+        }
+    };
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, value);
+    if policy.allow_credentials {
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+            HeaderValue::from_static("true"),
+        );
+    }
+}
+
+fn insert_list(headers: &mut HeaderMap, name: header::HeaderName, list: &str) {
+    let value = split_list(list).collect::<Vec<_>>().join(",");
+    if value.is_empty() {
+        return;
+    }
+    if let Ok(value) = HeaderValue::from_str(&value) {
+        headers.insert(name, value);
+    }
+}
+
+fn requested_headers(headers: &HeaderMap) -> impl Iterator<Item = String> + '_ {
+    headers
+        .get_all(header::ACCESS_CONTROL_REQUEST_HEADERS)
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(','))
+        .map(|h| h.trim().to_ascii_lowercase())
+        .filter(|h| !h.is_empty())
+}
+
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',').map(str::trim).filter(|s| !s.is_empty())
+}
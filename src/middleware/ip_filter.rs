@@ -0,0 +1,135 @@
+use crate::{
+    metrics::Metrics,
+    network::{self, ClientIp},
+    reload::ConfigHandle,
+};
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    Error, HttpMessage, HttpResponse,
+};
+use futures_util::future::LocalBoxFuture;
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+};
+use tracing::warn;
+
+/// Counter of requests rejected by IP access rules
+pub const DENIED_METRIC: &str = "ip_filter_denied_total";
+
+/// Resolves the real client address and enforces IP allow/deny rules
+///
+/// The resolved address is stored as a [`ClientIp`] request extension for
+/// logging and handlers. A request must pass both the top-level lists and
+/// the longest matching scope rule in `network.scopes`; otherwise it is
+/// answered with 403 and counted in `ip_filter_denied_total`.
+#[derive(Clone)]
+pub struct IpFilter {
+    config: ConfigHandle,
+    metrics: Metrics,
+}
+
+impl IpFilter {
+    pub fn new(config: ConfigHandle, metrics: Metrics) -> Self {
+        Self { config, metrics }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for IpFilter
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = IpFilterMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(IpFilterMiddleware {
+            service: Rc::new(service),
+            config: self.config.clone(),
+            metrics: self.metrics.clone(),
+        }))
+    }
+}
+
+pub struct IpFilterMiddleware<S> {
+    service: Rc<S>,
+    config: ConfigHandle,
+    metrics: Metrics,
+}
+
+impl<S, B> Service<ServiceRequest> for IpFilterMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        // Without a peer address (e.g. a Unix socket) there is nothing to check
+        let Some(peer) = req.peer_addr().map(|addr| addr.ip()) else {
+            return Box::pin(async move {
+                service
+                    .call(req)
+                    .await
+                    .map(ServiceResponse::map_into_left_body)
+            });
+        };
+
+        let config = self.config.current();
+        let policy = &config.network;
+        let client = network::resolve_client_ip(peer, req.headers(), policy.trusted_proxies.nets());
+        req.extensions_mut().insert(ClientIp(client));
+
+        // Scopes match the percent-decoded path the router resolves, so an
+        // encoded path cannot reach a route while missing its scope
+        let denied_by = if !network::global_allows(policy, client) {
+            Some("global")
+        } else {
+            match network::scope_rule(policy, req.match_info().as_str()) {
+                Some((prefix, rule)) if !network::rule_allows(rule, client) => Some(prefix),
+                _ => None,
+            }
+        };
+
+        if let Some(scope) = denied_by {
+            warn!(
+                client_ip = %client,
+                path = %req.path(),
+                scope,
+                "Request denied by IP access rules"
+            );
+            self.metrics.increment(
+                DENIED_METRIC,
+                "Requests rejected by IP allow/deny rules",
+                &[("scope", scope)],
+            );
+            return Box::pin(async move {
+                let resp = HttpResponse::Forbidden().json(serde_json::json!({
+                    "error": "Forbidden",
+                    "message": "Access from this address is not allowed"
+                }));
+                Ok(req.into_response(resp).map_into_right_body())
+            });
+        }
+
+        Box::pin(async move {
+            service
+                .call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        })
+    }
+}
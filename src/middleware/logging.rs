@@ -1,3 +1,4 @@
+use crate::{network::ClientIp, reload::ConfigHandle};
 use actix_web::{
     dev::{Service, ServiceRequest, ServiceResponse, Transform},
     Error, HttpMessage,
@@ -8,12 +9,23 @@ use std::{
     rc::Rc,
     time::Instant,
 };
-use tracing::{info, Span};
+use tracing::{info, warn, Span};
 use uuid::Uuid;
 
 /// Request logging middleware with trace IDs
-#[derive(Clone, Default)]
-pub struct RequestLogging;
+///
+/// Requests slower than `logging.slow_request_ms` in the live configuration
+/// are logged as warnings.
+#[derive(Clone)]
+pub struct RequestLogging {
+    config: ConfigHandle,
+}
+
+impl RequestLogging {
+    pub fn new(config: ConfigHandle) -> Self {
+        Self { config }
+    }
+}
 
 impl<S, B> Transform<S, ServiceRequest> for RequestLogging
 where
@@ -30,12 +42,14 @@ where
     fn new_transform(&self, service: S) -> Self::Future {
         ready(Ok(RequestLoggingMiddleware {
             service: Rc::new(service),
+            config: self.config.clone(),
         }))
     }
 }
 
 pub struct RequestLoggingMiddleware<S> {
     service: Rc<S>,
+    config: ConfigHandle,
 }
 
 impl<S, B> Service<ServiceRequest> for RequestLoggingMiddleware<S>
@@ -53,7 +67,7 @@ where
     fn call(&self, req: ServiceRequest) -> Self::Future {
         let start = Instant::now();
         let trace_id = Uuid::new_v4();
-        
+
         // Attach trace ID to request extensions
         req.extensions_mut().insert(trace_id);
 
@@ -61,23 +75,46 @@ where
         let span = Span::current();
         span.record("trace_id", trace_id.to_string());
 
+        // Resolved by `IpFilter` when it runs; otherwise the direct peer
+        let client_ip = req
+            .extensions()
+            .get::<ClientIp>()
+            .map(|c| c.0)
+            .or_else(|| req.peer_addr().map(|a| a.ip()))
+            .map(|ip| ip.to_string())
+            .unwrap_or_else(|| "-".to_string());
+
         let method = req.method().clone();
         let path = req.path().to_string();
         let service = Rc::clone(&self.service);
+        let slow_request_ms = self.config.current().logging.slow_request_ms;
 
         Box::pin(async move {
             let res = service.call(req).await?;
             let elapsed = start.elapsed();
             let status = res.status();
 
-            info!(
-                method = %method,
-                path = %path,
-                status = %status.as_u16(),
-                duration_ms = elapsed.as_millis(),
-                trace_id = %trace_id,
-                "Request completed"
-            );
+            if slow_request_ms > 0 && elapsed.as_millis() >= u128::from(slow_request_ms) {
+                warn!(
+                    method = %method,
+                    path = %path,
+                    client_ip = %client_ip,
+                    status = %status.as_u16(),
+                    duration_ms = elapsed.as_millis(),
+                    trace_id = %trace_id,
+                    "Slow request completed"
+                );
+            } else {
+                info!(
+                    method = %method,
+                    path = %path,
+                    client_ip = %client_ip,
+                    status = %status.as_u16(),
+                    duration_ms = elapsed.as_millis(),
+                    trace_id = %trace_id,
+                    "Request completed"
+                );
+            }
 
             Ok(res)
         })
@@ -1,2 +1,7 @@
 pub mod auth;
+pub mod body_limit;
+pub mod compression;
+pub mod cors;
+pub mod ip_filter;
 pub mod logging;
+pub mod security_headers;
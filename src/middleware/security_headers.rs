@@ -0,0 +1,176 @@
+use crate::{
+    config::{SecurityHeadersConfig, SecurityHeadersOverride},
+    policy::Principal,
+    reload::ConfigHandle,
+};
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{self, HeaderMap, HeaderName, HeaderValue},
+    Error, HttpMessage,
+};
+use futures_util::future::LocalBoxFuture;
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+};
+
+/// Headers that identify the server software and are always stripped
+const IDENTIFYING_HEADERS: &[&str] = &["server", "x-powered-by", "x-aspnet-version"];
+
+/// Adds hardening headers to every response
+///
+/// Values come from the `security_headers` section of the live configuration,
+/// with the longest matching path prefix in `overrides` taking precedence.
+/// `Cache-Control` is added to authenticated responses, and to every response
+/// in a scope whose override sets it. Headers already set by a handler are
+/// left alone.
+#[derive(Clone)]
+pub struct SecurityHeaders {
+    config: ConfigHandle,
+}
+
+impl SecurityHeaders {
+    pub fn new(config: ConfigHandle) -> Self {
+        Self { config }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for SecurityHeaders
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = SecurityHeadersMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(SecurityHeadersMiddleware {
+            service: Rc::new(service),
+            config: self.config.clone(),
+        }))
+    }
+}
+
+pub struct SecurityHeadersMiddleware<S> {
+    service: Rc<S>,
+    config: ConfigHandle,
+}
+
+impl<S, B> Service<ServiceRequest> for SecurityHeadersMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let config = self.config.current();
+        // The decoded path the router resolves, as `IpFilter` matches scopes
+        let policy = resolve(&config.security_headers, req.match_info().as_str());
+        let service = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            let mut res = service.call(req).await?;
+            let Some((policy, scoped_cache_control)) = policy else {
+                return Ok(res);
+            };
+
+            let authenticated = res.request().extensions().contains::<Principal>();
+            let headers = res.headers_mut();
+            for name in IDENTIFYING_HEADERS {
+                headers.remove(*name);
+            }
+
+            set_default(headers, header::STRICT_TRANSPORT_SECURITY, &policy.hsts);
+            set_default(
+                headers,
+                header::X_CONTENT_TYPE_OPTIONS,
+                &policy.content_type_options,
+            );
+            set_default(headers, header::X_FRAME_OPTIONS, &policy.frame_options);
+            set_default(headers, header::REFERRER_POLICY, &policy.referrer_policy);
+            set_default(
+                headers,
+                header::CONTENT_SECURITY_POLICY,
+                &policy.content_security_policy,
+            );
+            if authenticated || scoped_cache_control {
+                set_default(headers, header::CACHE_CONTROL, &policy.cache_control);
+            }
+
+            Ok(res)
+        })
+    }
+}
+
+/// Effective header values for `path` and whether its scope sets
+/// `Cache-Control`, or `None` when the middleware is off
+fn resolve(config: &SecurityHeadersConfig, path: &str) -> Option<(SecurityHeadersOverride, bool)> {
+    if !config.enabled {
+        return None;
+    }
+
+    let base = SecurityHeadersOverride {
+        hsts: Some(config.hsts.clone()),
+        content_type_options: Some(config.content_type_options.clone()),
+        frame_options: Some(config.frame_options.clone()),
+        referrer_policy: Some(config.referrer_policy.clone()),
+        content_security_policy: Some(config.content_security_policy.clone()),
+        cache_control: Some(config.cache_control.clone()),
+    };
+
+    let scope = config
+        .overrides
+        .iter()
+        .filter(|(prefix, _)| path_in_scope(path, prefix))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, scope)| scope);
+
+    Some(match scope {
+        Some(scope) => (
+            SecurityHeadersOverride {
+                hsts: scope.hsts.clone().or(base.hsts),
+                content_type_options: scope
+                    .content_type_options
+                    .clone()
+                    .or(base.content_type_options),
+                frame_options: scope.frame_options.clone().or(base.frame_options),
+                referrer_policy: scope.referrer_policy.clone().or(base.referrer_policy),
+                content_security_policy: scope
+                    .content_security_policy
+                    .clone()
+                    .or(base.content_security_policy),
+                cache_control: scope.cache_control.clone().or(base.cache_control),
+            },
+            scope.cache_control.is_some(),
+        ),
+        None => (base, false),
+    })
+}
+
+fn path_in_scope(path: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    path == prefix || path.starts_with(prefix) && path[prefix.len()..].starts_with('/')
+}
+
+/// Sets a header unless the handler already did; empty values disable it
+fn set_default(headers: &mut HeaderMap, name: HeaderName, value: &Option<String>) {
+    let Some(value) = value.as_deref().filter(|v| !v.is_empty()) else {
+        return;
+    };
+    if headers.contains_key(&name) {
+        return;
+    }
+    if let Ok(value) = HeaderValue::from_str(value) {
+        headers.insert(name, value);
+    }
+}
@@ -1,7 +1,29 @@
+use crate::{labels, recurrence::RecurrenceRequest};
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::cmp::Ordering;
 use uuid::Uuid;
 
+/// How urgent a task is, ordered from `low` to `urgent`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    Urgent,
+}
+
+/// A time at which the reminder scheduler notifies about a task
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Reminder {
+    pub at: DateTime<Utc>,
+    /// When the reminder fired; unset while it is pending
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fired_at: Option<DateTime<Utc>>,
+}
+
 /// Task model representing a todo item
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Task {
@@ -9,8 +31,33 @@ pub struct Task {
     pub title: String,
     pub description: Option<String>,
     pub completed: bool,
+    #[serde(default)]
+    pub due_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub priority: Priority,
+    /// Reminders in time order
+    #[serde(default)]
+    pub reminders: Vec<Reminder>,
+    /// Recurring series this task is an occurrence of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub series_id: Option<Uuid>,
+    /// Task this one is a subtask of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<Uuid>,
+    /// Tasks that have to be completed before this one
+    #[serde(default)]
+    pub blocked_by: Vec<Uuid>,
+    /// Normalised labels in name order
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Principal that created the task; `None` for tasks only admins manage
+    #[serde(default)]
+    pub owner_id: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// When the task was moved to the trash; set only on trashed tasks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
 impl Task {
@@ -22,13 +69,32 @@ impl Task {
             title,
             description,
             completed: false,
+            due_at: None,
+            priority: Priority::default(),
+            reminders: Vec::new(),
+            series_id: None,
+            parent_id: None,
+            blocked_by: Vec::new(),
+            labels: Vec::new(),
+            owner_id: None,
             created_at: now,
             updated_at: now,
+            deleted_at: None,
         }
     }
 
+    /// Bytes of text content counted against a tenant's storage quota
+    pub fn stored_bytes(&self) -> u64 {
+        (self.title.len() + self.description.as_deref().map_or(0, str::len)) as u64
+    }
+
     /// Update task fields
-    pub fn update(&mut self, title: Option<String>, description: Option<Option<String>>, completed: Option<bool>) {
+    pub fn update(
+        &mut self,
+        title: Option<String>,
+        description: Option<Option<String>>,
+        completed: Option<bool>,
+    ) {
         if let Some(t) = title {
             self.title = t;
         }
@@ -40,14 +106,54 @@ impl Task {
         }
         self.updated_at = Utc::now();
     }
+
+    /// Replaces the reminder times, keeping the fired state of times that
+    /// were already set
+    pub fn set_reminders(&mut self, mut times: Vec<DateTime<Utc>>) {
+        times.sort();
+        times.dedup();
+        self.reminders = times
+            .into_iter()
+            .map(|at| Reminder {
+                at,
+                fired_at: self
+                    .reminders
+                    .iter()
+                    .find(|r| r.at == at)
+                    .and_then(|r| r.fired_at),
+            })
+            .collect();
+    }
+
+    /// Whether the task is still open after its due date
+    pub fn is_overdue(&self, now: DateTime<Utc>) -> bool {
+        !self.completed && self.due_at.is_some_and(|due| due < now)
+    }
 }
 
 /// Request DTO for creating a task
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct CreateTaskRequest {
     pub title: String,
     #[serde(default)]
     pub description: Option<String>,
+    #[serde(default)]
+    pub due_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    #[serde(default)]
+    pub reminders: Vec<DateTime<Utc>>,
+    /// Makes the task the first occurrence of a series; needs `due_at`
+    #[serde(default)]
+    pub recurrence: Option<RecurrenceRequest>,
+    /// Creates the task as a subtask of an existing one
+    #[serde(default)]
+    pub parent_id: Option<Uuid>,
+    /// Tasks that have to be completed before this one
+    #[serde(default)]
+    pub blocked_by: Vec<Uuid>,
+    #[serde(default)]
+    pub labels: Vec<String>,
 }
 
 /// Request DTO for updating a task
@@ -59,6 +165,120 @@ pub struct UpdateTaskRequest {
     pub description: Option<Option<String>>,
     #[serde(default)]
     pub completed: Option<bool>,
+    /// `null` clears the due date
+    #[serde(default, deserialize_with = "present")]
+    pub due_at: Option<Option<DateTime<Utc>>>,
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    /// Replaces every reminder time
+    #[serde(default)]
+    pub reminders: Option<Vec<DateTime<Utc>>>,
+    /// Replaces every label
+    #[serde(default)]
+    pub labels: Option<Vec<String>>,
+}
+
+/// Tells a field sent as `null` (`Some(None)`) apart from an absent one (`None`)
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
+
+/// Field task listings are sorted by
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskSort {
+    DueAt,
+    Priority,
+    CreatedAt,
+    UpdatedAt,
+    Title,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Filters and ordering accepted by the task listing
+#[derive(Debug, Default, Deserialize)]
+pub struct TaskQuery {
+    pub completed: Option<bool>,
+    pub priority: Option<Priority>,
+    /// Only open tasks whose due date has passed
+    #[serde(default)]
+    pub overdue: bool,
+    pub due_before: Option<DateTime<Utc>>,
+    pub due_after: Option<DateTime<Utc>>,
+    pub sort: Option<TaskSort>,
+    #[serde(default)]
+    pub order: SortOrder,
+    /// Only tasks with all of these comma-separated labels
+    #[serde(default, deserialize_with = "labels::comma_separated")]
+    pub labels_all: Vec<String>,
+    /// Only tasks with at least one of these labels
+    #[serde(default, deserialize_with = "labels::comma_separated")]
+    pub labels_any: Vec<String>,
+    /// Only tasks with none of these labels
+    #[serde(default, deserialize_with = "labels::comma_separated")]
+    pub labels_none: Vec<String>,
+}
+
+impl TaskQuery {
+    pub fn matches(&self, task: &Task, now: DateTime<Utc>) -> bool {
+        self.completed
+            .is_none_or(|completed| task.completed == completed)
+            && self
+                .priority
+                .is_none_or(|priority| task.priority == priority)
+            && (!self.overdue || task.is_overdue(now))
+            && self
+                .due_before
+                .is_none_or(|before| task.due_at.is_some_and(|due| due < before))
+            && self
+                .due_after
+                .is_none_or(|after| task.due_at.is_some_and(|due| due >= after))
+            && self
+                .labels_all
+                .iter()
+                .all(|label| task.labels.contains(label))
+            && (self.labels_any.is_empty()
+                || self
+                    .labels_any
+                    .iter()
+                    .any(|label| task.labels.contains(label)))
+            && !self
+                .labels_none
+                .iter()
+                .any(|label| task.labels.contains(label))
+    }
+
+    /// Sorts `tasks` as requested; tasks without a due date sort last by `due_at`
+    pub fn sort(&self, tasks: &mut [Task]) {
+        let Some(sort) = self.sort else {
+            return;
+        };
+        let order = |ordering: Ordering| match self.order {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        };
+        tasks.sort_by(|a, b| match sort {
+            TaskSort::DueAt => match (a.due_at, b.due_at) {
+                (Some(a), Some(b)) => order(a.cmp(&b)),
+                (a, b) => a.is_none().cmp(&b.is_none()),
+            },
+            TaskSort::Priority => order(a.priority.cmp(&b.priority)),
+            TaskSort::CreatedAt => order(a.created_at.cmp(&b.created_at)),
+            TaskSort::UpdatedAt => order(a.updated_at.cmp(&b.updated_at)),
+            TaskSort::Title => order(a.title.cmp(&b.title)),
+        });
+    }
 }
 
 /// Health check response
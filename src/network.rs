@@ -0,0 +1,187 @@
+use crate::config::{IpAccessRule, NetworkConfig};
+use actix_web::http::header::{HeaderMap, FORWARDED};
+use ipnet::IpNet;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+
+/// Real client address, after resolving trusted proxy headers
+///
+/// Inserted into request extensions by `IpFilter`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+/// Parses a comma-separated list of CIDRs; bare addresses are single hosts
+pub fn parse_cidrs(list: &str) -> Result<Vec<IpNet>, String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|entry| {
+            entry
+                .parse::<IpNet>()
+                .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| format!("'{}' is not an IP address or CIDR", entry))
+        })
+        .collect()
+}
+
+/// A comma-separated list of CIDRs, parsed once when the configuration is
+/// loaded so requests only match against it.
+///
+/// An invalid entry fails the load, leaving the previous configuration in
+/// place on reload, rather than being dropped from the list.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CidrList {
+    source: String,
+    nets: Vec<IpNet>,
+}
+
+impl CidrList {
+    pub fn nets(&self) -> &[IpNet] {
+        &self.nets
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nets.is_empty()
+    }
+
+    /// Whether any entry contains `ip`
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        self.nets.iter().any(|net| net.contains(ip))
+    }
+}
+
+impl FromStr for CidrList {
+    type Err = String;
+
+    fn from_str(list: &str) -> Result<Self, Self::Err> {
+        Ok(Self {
+            source: list.to_string(),
+            nets: parse_cidrs(list)?,
+        })
+    }
+}
+
+impl fmt::Display for CidrList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Serialize for CidrList {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for CidrList {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Determines the client address from the peer and forwarding headers.
+///
+/// Forwarding headers are only believed when the peer is a trusted proxy.
+/// The chain is walked from the nearest hop outwards, skipping trusted
+/// proxies, and the first untrusted address is the client. `Forwarded` is
+/// preferred over `X-Forwarded-For` when both are present.
+pub fn resolve_client_ip(peer: IpAddr, headers: &HeaderMap, trusted: &[IpNet]) -> IpAddr {
+    let is_trusted = |ip: &IpAddr| trusted.iter().any(|net| net.contains(ip));
+    if !is_trusted(&peer) {
+        return peer;
+    }
+
+    let chain = forwarded_chain(headers);
+    let mut client = peer;
+    for hop in chain.iter().rev() {
+        match hop {
+            Some(ip) => {
+                client = *ip;
+                if !is_trusted(ip) {
+                    break;
+                }
+            }
+            // An obfuscated or malformed hop ends what can be trusted
+            None => break,
+        }
+    }
+    client
+}
+
+/// Addresses listed in forwarding headers, leftmost (original client) first
+fn forwarded_chain(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let forwarded: Vec<_> = headers
+        .get_all(FORWARDED)
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(','))
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (key, value) = pair.trim().split_once('=')?;
+                key.eq_ignore_ascii_case("for")
+                    .then(|| parse_node(value.trim().trim_matches('"')))
+            })
+        })
+        .collect();
+    if !forwarded.is_empty() {
+        return forwarded;
+    }
+
+    headers
+        .get_all("x-forwarded-for")
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(','))
+        .map(|hop| parse_node(hop.trim()))
+        .collect()
+}
+
+/// Parses `1.2.3.4`, `1.2.3.4:80`, `2001:db8::1` or `[2001:db8::1]:80`
+fn parse_node(node: &str) -> Option<IpAddr> {
+    node.parse::<IpAddr>()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|a| a.ip()))
+        .or_else(|| {
+            node.strip_prefix('[')
+                .and_then(|n| n.strip_suffix(']'))
+                .and_then(|n| n.parse().ok())
+        })
+}
+
+/// Whether `ip` passes an allow/deny rule; deny entries win over allow
+/// entries, and an empty allow list allows everything not denied
+pub fn rule_allows(rule: &IpAccessRule, ip: IpAddr) -> bool {
+    lists_allow(&rule.allow, &rule.deny, ip)
+}
+
+/// Whether `ip` passes the top-level allow/deny lists
+pub fn global_allows(config: &NetworkConfig, ip: IpAddr) -> bool {
+    lists_allow(&config.allow, &config.deny, ip)
+}
+
+fn lists_allow(allow: &CidrList, deny: &CidrList, ip: IpAddr) -> bool {
+    if deny.contains(&ip) {
+        return false;
+    }
+    allow.is_empty() || allow.contains(&ip)
+}
+
+/// The scope rule with the longest path prefix matching `path`
+pub fn scope_rule<'a>(
+    config: &'a NetworkConfig,
+    path: &str,
+) -> Option<(&'a str, &'a IpAccessRule)> {
+    config
+        .scopes
+        .iter()
+        .filter(|(prefix, _)| {
+            let prefix = prefix.trim_end_matches('/');
+            path == prefix || path.starts_with(prefix) && path[prefix.len()..].starts_with('/')
+        })
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(prefix, rule)| (prefix.as_str(), rule))
+}
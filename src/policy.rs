@@ -0,0 +1,243 @@
+use crate::{errors::AppError, models::Task, tenancy::DEFAULT_TENANT};
+use actix_web::{dev::Payload, FromRequest, HttpMessage, HttpRequest};
+use serde::{Deserialize, Serialize};
+use std::future::{ready, Ready};
+
+/// What a principal may do with tasks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Full access to every task
+    Admin,
+    /// Creates tasks and reads, edits and deletes the ones it owns
+    Member,
+    /// Reads every task, changes nothing
+    Viewer,
+}
+
+/// Authenticated caller, inserted into request extensions by `ApiKeyAuth`
+///
+/// Handlers take it as an extractor; requests that did not pass
+/// authentication are rejected with 401.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+    pub id: String,
+    pub role: Role,
+    /// Tenant whose tasks the principal works with
+    pub tenant: String,
+}
+
+impl Principal {
+    /// A principal of the default tenant
+    pub fn new(id: impl Into<String>, role: Role) -> Self {
+        Self {
+            id: id.into(),
+            role,
+            tenant: DEFAULT_TENANT.to_string(),
+        }
+    }
+
+    pub fn in_tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenant = tenant.into();
+        self
+    }
+
+    fn owns(&self, task: &Task) -> bool {
+        task.owner_id.as_deref() == Some(self.id.as_str())
+    }
+}
+
+impl FromRequest for Principal {
+    type Error = AppError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<Principal>()
+                .cloned()
+                .ok_or_else(|| AppError::Unauthorized("Authentication required".to_string())),
+        )
+    }
+}
+
+/// Operation being authorised
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Read,
+    Create,
+    Update,
+    Delete,
+    /// Bulk import, which can overwrite any task and set any owner
+    Import,
+    /// Create, suspend and delete tenants
+    ManageTenants,
+    /// Query the tenant's audit log
+    ReadAudit,
+    /// Permanently delete tasks, bypassing or emptying the trash
+    Purge,
+    /// Edit the tenant's label catalogue
+    ManageLabels,
+    /// Write comments on a task
+    Comment,
+}
+
+/// Whether `principal` may perform `action`, on `task` where one applies.
+///
+/// Pure policy with no HTTP involved, so it can be tested directly. Tasks
+/// are already confined to the principal's tenant by storage, so only
+/// tenant management looks at the tenant here.
+pub fn can(principal: &Principal, action: Action, task: Option<&Task>) -> bool {
+    let owns = task.is_some_and(|task| principal.owns(task));
+    match (principal.role, action) {
+        (Role::Admin, Action::ManageTenants) => principal.tenant == DEFAULT_TENANT,
+        (Role::Admin, _) => true,
+        (Role::Member, Action::Create) => true,
+        (Role::Member, Action::Read | Action::Update | Action::Delete | Action::Comment) => owns,
+        (
+            Role::Member,
+            Action::Import
+            | Action::ManageTenants
+            | Action::ReadAudit
+            | Action::Purge
+            | Action::ManageLabels,
+        ) => false,
+        (Role::Viewer, Action::Read) => true,
+        (Role::Viewer, _) => false,
+    }
+}
+
+/// Like [`can`], as an error suitable for returning from a handler.
+///
+/// Tasks the principal cannot read are reported as not found, so their
+/// existence is not revealed; visible tasks it cannot change are forbidden.
+pub fn authorize(
+    principal: &Principal,
+    action: Action,
+    task: Option<&Task>,
+) -> Result<(), AppError> {
+    if can(principal, action, task) {
+        return Ok(());
+    }
+
+    match task {
+        Some(task) if !can(principal, Action::Read, Some(task)) => Err(AppError::NotFound(
+            format!("Task with id {} not found", task.id),
+        )),
+        _ => Err(AppError::Forbidden(format!(
+            "Role '{}' may not perform this operation",
+            role_name(principal.role)
+        ))),
+    }
+}
+
+fn role_name(role: Role) -> &'static str {
+    match role {
+        Role::Admin => "admin",
+        Role::Member => "member",
+        Role::Viewer => "viewer",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn owned_by(owner: Option<&str>) -> Task {
+        let mut task = Task::new("Task".to_string(), None);
+        task.owner_id = owner.map(str::to_string);
+        task
+    }
+
+    const TASK_ACTIONS: [Action; 4] = [
+        Action::Read,
+        Action::Update,
+        Action::Delete,
+        Action::Comment,
+    ];
+
+    const TENANT_ACTIONS: [Action; 6] = [
+        Action::Create,
+        Action::Import,
+        Action::ManageTenants,
+        Action::ReadAudit,
+        Action::Purge,
+        Action::ManageLabels,
+    ];
+
+    #[test]
+    fn admins_may_do_anything_in_their_tenant() {
+        let admin = Principal::new("root", Role::Admin);
+        let task = owned_by(Some("someone"));
+        for action in TASK_ACTIONS {
+            assert!(can(&admin, action, Some(&task)), "{:?}", action);
+        }
+        for action in TENANT_ACTIONS {
+            assert!(can(&admin, action, None), "{:?}", action);
+        }
+    }
+
+    #[test]
+    fn only_default_tenant_admins_manage_tenants() {
+        let admin = Principal::new("root", Role::Admin).in_tenant("acme");
+        assert!(!can(&admin, Action::ManageTenants, None));
+        assert!(can(&admin, Action::Purge, None));
+    }
+
+    #[test]
+    fn members_only_see_and_change_their_own_tasks() {
+        let member = Principal::new("alice", Role::Member);
+        let own = owned_by(Some("alice"));
+        for action in TASK_ACTIONS {
+            assert!(can(&member, action, Some(&own)), "{:?}", action);
+            assert!(
+                !can(&member, action, Some(&owned_by(Some("bob")))),
+                "{:?}",
+                action
+            );
+            assert!(!can(&member, action, Some(&owned_by(None))), "{:?}", action);
+        }
+        for action in TENANT_ACTIONS {
+            assert_eq!(
+                can(&member, action, None),
+                action == Action::Create,
+                "{:?}",
+                action
+            );
+        }
+    }
+
+    #[test]
+    fn viewers_read_everything_and_change_nothing() {
+        let viewer = Principal::new("audit", Role::Viewer);
+        let task = owned_by(Some("bob"));
+        assert!(can(&viewer, Action::Read, Some(&task)));
+        for action in [Action::Update, Action::Delete, Action::Comment] {
+            assert!(!can(&viewer, action, Some(&task)), "{:?}", action);
+        }
+        for action in TENANT_ACTIONS {
+            assert!(!can(&viewer, action, None), "{:?}", action);
+        }
+    }
+
+    #[test]
+    fn authorize_hides_unreadable_tasks() {
+        let member = Principal::new("alice", Role::Member);
+        let viewer = Principal::new("audit", Role::Viewer);
+        let other = owned_by(Some("bob"));
+
+        assert!(authorize(&member, Action::Update, Some(&owned_by(Some("alice")))).is_ok());
+        assert!(matches!(
+            authorize(&member, Action::Update, Some(&other)),
+            Err(AppError::NotFound(_))
+        ));
+        assert!(matches!(
+            authorize(&viewer, Action::Update, Some(&other)),
+            Err(AppError::Forbidden(_))
+        ));
+        assert!(matches!(
+            authorize(&member, Action::Import, None),
+            Err(AppError::Forbidden(_))
+        ));
+    }
+}
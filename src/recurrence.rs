@@ -0,0 +1,524 @@
+use crate::models::{Priority, Task};
+use chrono::{
+    DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, Offset,
+    TimeZone, Utc, Weekday,
+};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+use uuid::Uuid;
+
+/// Periods searched for occurrences before a rule is considered exhausted,
+/// so rules that can never match (e.g. `BYMONTH=2;BYMONTHDAY=30`) end
+const MAX_PERIODS: u32 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// A weekday in `BYDAY`, optionally with an ordinal such as `-1FR` (the
+/// last Friday of the month)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByDay {
+    pub ordinal: Option<i8>,
+    pub weekday: Weekday,
+}
+
+/// An RFC 5545 recurrence rule.
+///
+/// Supports `FREQ` (`DAILY`, `WEEKLY`, `MONTHLY`, `YEARLY`), `INTERVAL`,
+/// `COUNT`, `UNTIL` (UTC or a date), `BYDAY`, `BYMONTHDAY` and `BYMONTH`;
+/// other parts are rejected. Occurrences keep the local time of day of the
+/// first one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct RRule {
+    pub freq: Frequency,
+    pub interval: u32,
+    pub count: Option<u32>,
+    pub until: Option<DateTime<Utc>>,
+    pub by_day: Vec<ByDay>,
+    pub by_month_day: Vec<i8>,
+    pub by_month: Vec<u32>,
+}
+
+impl FromStr for RRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let s = s.strip_prefix("RRULE:").unwrap_or(s);
+        let mut freq = None;
+        let mut rule = RRule {
+            freq: Frequency::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+            by_month: Vec::new(),
+        };
+
+        for part in s.split(';').filter(|p| !p.is_empty()) {
+            let (name, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid RRULE part '{}'", part))?;
+            let list = || value.split(',').map(str::trim);
+            match name.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    freq = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        other => return Err(format!("Unsupported FREQ '{}'", other)),
+                    })
+                }
+                "INTERVAL" => {
+                    rule.interval = value
+                        .parse()
+                        .ok()
+                        .filter(|n| *n > 0)
+                        .ok_or("INTERVAL must be a positive integer")?
+                }
+                "COUNT" => {
+                    rule.count = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|n| *n > 0)
+                            .ok_or("COUNT must be a positive integer")?,
+                    )
+                }
+                "UNTIL" => rule.until = Some(parse_until(value)?),
+                "BYDAY" => rule.by_day = list().map(parse_by_day).collect::<Result<_, _>>()?,
+                "BYMONTHDAY" => {
+                    rule.by_month_day = list()
+                        .map(|d| {
+                            d.parse::<i8>()
+                                .ok()
+                                .filter(|d| *d != 0 && (-31..=31).contains(d))
+                                .ok_or_else(|| format!("Invalid BYMONTHDAY '{}'", d))
+                        })
+                        .collect::<Result<_, _>>()?
+                }
+                "BYMONTH" => {
+                    rule.by_month = list()
+                        .map(|m| {
+                            m.parse::<u32>()
+                                .ok()
+                                .filter(|m| (1..=12).contains(m))
+                                .ok_or_else(|| format!("Invalid BYMONTH '{}'", m))
+                        })
+                        .collect::<Result<_, _>>()?
+                }
+                other => return Err(format!("Unsupported RRULE part '{}'", other)),
+            }
+        }
+
+        rule.freq = freq.ok_or("RRULE must specify FREQ")?;
+        if rule.count.is_some() && rule.until.is_some() {
+            return Err("COUNT and UNTIL cannot both be set".to_string());
+        }
+        let ordinals = rule.by_day.iter().any(|d| d.ordinal.is_some());
+        if ordinals
+            && !(rule.freq == Frequency::Monthly
+                || rule.freq == Frequency::Yearly && !rule.by_month.is_empty())
+        {
+            return Err(
+                "BYDAY ordinals need FREQ=MONTHLY, or FREQ=YEARLY with BYMONTH".to_string(),
+            );
+        }
+        if rule.freq == Frequency::Weekly && !rule.by_month_day.is_empty() {
+            return Err("BYMONTHDAY cannot be used with FREQ=WEEKLY".to_string());
+        }
+        Ok(rule)
+    }
+}
+
+impl TryFrom<String> for RRule {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<RRule> for String {
+    fn from(rule: RRule) -> Self {
+        rule.to_string()
+    }
+}
+
+impl fmt::Display for RRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let freq = match self.freq {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+            Frequency::Yearly => "YEARLY",
+        };
+        write!(f, "FREQ={}", freq)?;
+        if self.interval != 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={}", count)?;
+        }
+        if let Some(until) = self.until {
+            write!(f, ";UNTIL={}", until.format("%Y%m%dT%H%M%SZ"))?;
+        }
+        let join = |items: Vec<String>| items.join(",");
+        if !self.by_day.is_empty() {
+            let days = self
+                .by_day
+                .iter()
+                .map(|d| {
+                    let ordinal = d.ordinal.map(|n| n.to_string()).unwrap_or_default();
+                    format!("{}{}", ordinal, weekday_code(d.weekday))
+                })
+                .collect();
+            write!(f, ";BYDAY={}", join(days))?;
+        }
+        if !self.by_month_day.is_empty() {
+            write!(
+                f,
+                ";BYMONTHDAY={}",
+                join(self.by_month_day.iter().map(i8::to_string).collect())
+            )?;
+        }
+        if !self.by_month.is_empty() {
+            write!(
+                f,
+                ";BYMONTH={}",
+                join(self.by_month.iter().map(u32::to_string).collect())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_until(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(at) = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%SZ") {
+        return Ok(at.and_utc());
+    }
+    // A date bound includes every occurrence on that day
+    NaiveDate::parse_from_str(value, "%Y%m%d")
+        .map(|date| {
+            date.and_time(NaiveTime::MIN).and_utc() + Duration::days(1) - Duration::seconds(1)
+        })
+        .map_err(|_| format!("UNTIL must be a UTC date-time or a date, got '{}'", value))
+}
+
+fn parse_by_day(value: &str) -> Result<ByDay, String> {
+    let invalid = || format!("Invalid BYDAY '{}'", value);
+    let split = value.len().checked_sub(2).ok_or_else(invalid)?;
+    let (ordinal, code) = value.split_at(split);
+    let weekday = match code.to_ascii_uppercase().as_str() {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return Err(invalid()),
+    };
+    let ordinal = match ordinal {
+        "" => None,
+        n => Some(
+            n.parse::<i8>()
+                .ok()
+                .filter(|n| *n != 0 && (-5..=5).contains(n))
+                .ok_or_else(invalid)?,
+        ),
+    };
+    Ok(ByDay { ordinal, weekday })
+}
+
+fn weekday_code(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    let (next_year, next_month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    NaiveDate::from_ymd_opt(next_year, next_month, 1)
+        .and_then(|first| first.pred_opt())
+        .map_or(28, |last| last.day())
+}
+
+/// Converts a local wall-clock time to UTC.
+///
+/// As in RFC 5545, a time skipped by a DST transition is shifted forward by
+/// the length of the gap, and a repeated time uses its first occurrence.
+fn resolve(tz: &Tz, local: NaiveDateTime) -> DateTime<Utc> {
+    match tz.from_local_datetime(&local) {
+        LocalResult::Single(at) | LocalResult::Ambiguous(at, _) => at.with_timezone(&Utc),
+        LocalResult::None => {
+            let before = tz
+                .from_local_datetime(&(local - Duration::days(1)))
+                .earliest()
+                .map(|at| at.offset().fix())
+                .unwrap_or_else(|| tz.offset_from_utc_datetime(&local).fix());
+            (local - Duration::seconds(before.local_minus_utc() as i64)).and_utc()
+        }
+    }
+}
+
+impl RRule {
+    /// Occurrences in UTC of a series whose first occurrence is `starts_at`
+    /// in `tz`, in order
+    pub fn occurrences(
+        &self,
+        starts_at: DateTime<Utc>,
+        tz: Tz,
+    ) -> impl Iterator<Item = DateTime<Utc>> + '_ {
+        let local = starts_at.with_timezone(&tz).naive_local();
+        let (first, time) = (local.date(), local.time());
+        (0..MAX_PERIODS)
+            .flat_map(move |k| self.period_dates(first, k))
+            .filter(move |date| *date >= first)
+            .map(move |date| resolve(&tz, date.and_time(time)))
+            .take_while(move |at| self.until.is_none_or(|until| *at <= until))
+            .take(self.count.map_or(usize::MAX, |count| count as usize))
+    }
+
+    /// First occurrence strictly after `after`
+    pub fn next_after(
+        &self,
+        starts_at: DateTime<Utc>,
+        tz: Tz,
+        after: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        self.occurrences(starts_at, tz).find(|at| *at > after)
+    }
+
+    /// Local dates of the `k`th period after the one containing `first`
+    fn period_dates(&self, first: NaiveDate, k: u32) -> Vec<NaiveDate> {
+        let step = k as i64 * self.interval as i64;
+        let in_months =
+            |date: &NaiveDate| self.by_month.is_empty() || self.by_month.contains(&date.month());
+        match self.freq {
+            Frequency::Daily => {
+                let date = first + Duration::days(step);
+                let day_ok = self.by_day.is_empty()
+                    || self.by_day.iter().any(|d| d.weekday == date.weekday());
+                let month_day_ok = self.by_month_day.is_empty()
+                    || self
+                        .month_days(date.year(), date.month(), date.day())
+                        .contains(&date.day());
+                if in_months(&date) && day_ok && month_day_ok {
+                    vec![date]
+                } else {
+                    Vec::new()
+                }
+            }
+            Frequency::Weekly => {
+                let monday = first - Duration::days(first.weekday().num_days_from_monday() as i64)
+                    + Duration::weeks(step);
+                let mut dates: Vec<NaiveDate> = if self.by_day.is_empty() {
+                    vec![monday + Duration::days(first.weekday().num_days_from_monday() as i64)]
+                } else {
+                    self.by_day
+                        .iter()
+                        .map(|d| monday + Duration::days(d.weekday.num_days_from_monday() as i64))
+                        .collect()
+                };
+                dates.retain(in_months);
+                dates.sort();
+                dates.dedup();
+                dates
+            }
+            Frequency::Monthly => {
+                let months = first.year() as i64 * 12 + first.month0() as i64 + step;
+                let (year, month) = ((months / 12) as i32, (months % 12) as u32 + 1);
+                if !self.by_month.is_empty() && !self.by_month.contains(&month) {
+                    return Vec::new();
+                }
+                self.dates_in_month(year, month, first.day())
+            }
+            Frequency::Yearly => {
+                let year = first.year() + step as i32;
+                let months = if self.by_month.is_empty() {
+                    vec![first.month()]
+                } else {
+                    let mut months = self.by_month.clone();
+                    months.sort();
+                    months
+                };
+                months
+                    .into_iter()
+                    .flat_map(|month| self.dates_in_month(year, month, first.day()))
+                    .collect()
+            }
+        }
+    }
+
+    fn dates_in_month(&self, year: i32, month: u32, default_day: u32) -> Vec<NaiveDate> {
+        self.month_days(year, month, default_day)
+            .into_iter()
+            .filter_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+            .collect()
+    }
+
+    /// Days of a month matching `BYMONTHDAY` and `BYDAY`, or `default_day`
+    /// when neither is set (months without that day are skipped)
+    fn month_days(&self, year: i32, month: u32, default_day: u32) -> Vec<u32> {
+        let len = days_in_month(year, month);
+        let mut days: Vec<u32> = if !self.by_month_day.is_empty() {
+            self.by_month_day
+                .iter()
+                .filter_map(|&d| {
+                    let day = if d > 0 {
+                        d as i32
+                    } else {
+                        len as i32 + 1 + d as i32
+                    };
+                    (1..=len as i32).contains(&day).then_some(day as u32)
+                })
+                .collect()
+        } else if self.by_day.is_empty() {
+            (default_day <= len)
+                .then_some(default_day)
+                .into_iter()
+                .collect()
+        } else {
+            (1..=len).collect()
+        };
+
+        if !self.by_day.is_empty() {
+            days.retain(|&day| {
+                let Some(date) = NaiveDate::from_ymd_opt(year, month, day) else {
+                    return false;
+                };
+                self.by_day.iter().any(|d| {
+                    d.weekday == date.weekday()
+                        && match d.ordinal {
+                            None => true,
+                            Some(n) if n > 0 => ((day - 1) / 7 + 1) as i8 == n,
+                            Some(n) => ((len - day) / 7 + 1) as i8 == -n,
+                        }
+                })
+            });
+        }
+        days.sort();
+        days.dedup();
+        days
+    }
+}
+
+/// Recurrence of a task as given when creating it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecurrenceRequest {
+    pub rule: RRule,
+    /// IANA time zone occurrences are computed in, e.g. `Europe/Berlin`
+    pub timezone: Tz,
+}
+
+/// A recurring task; each occurrence is a task linked by `series_id`.
+///
+/// Completing an occurrence creates the next one from the series, so edits
+/// to the series reach every occurrence created afterwards while edits to
+/// a single occurrence stay with it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Series {
+    pub id: Uuid,
+    pub rule: RRule,
+    pub timezone: Tz,
+    /// First occurrence the rule is counted from
+    pub starts_at: DateTime<Utc>,
+    pub title: String,
+    pub description: Option<String>,
+    pub priority: Priority,
+    /// Reminder times as seconds relative to each occurrence's due date
+    pub reminder_offsets_secs: Vec<i64>,
+    pub owner_id: Option<String>,
+}
+
+impl Series {
+    /// Starts a series from its first occurrence, which must have a due date
+    pub fn new(first: &Task, recurrence: RecurrenceRequest) -> Option<Self> {
+        let due_at = first.due_at?;
+        Some(Self {
+            id: Uuid::new_v4(),
+            rule: recurrence.rule,
+            timezone: recurrence.timezone,
+            starts_at: due_at,
+            title: first.title.clone(),
+            description: first.description.clone(),
+            priority: first.priority,
+            reminder_offsets_secs: first
+                .reminders
+                .iter()
+                .map(|r| (r.at - due_at).num_seconds())
+                .collect(),
+            owner_id: first.owner_id.clone(),
+        })
+    }
+
+    /// Next occurrences after `after`, at most `limit`
+    pub fn upcoming(&self, after: DateTime<Utc>, limit: usize) -> Vec<DateTime<Utc>> {
+        self.rule
+            .occurrences(self.starts_at, self.timezone)
+            .filter(|at| *at > after)
+            .take(limit)
+            .collect()
+    }
+
+    /// The occurrence following one due at `after`, if the rule has one
+    pub fn next_occurrence(&self, after: DateTime<Utc>) -> Option<Task> {
+        let due_at = self.rule.next_after(self.starts_at, self.timezone, after)?;
+        let mut task = Task::new(self.title.clone(), self.description.clone());
+        task.due_at = Some(due_at);
+        task.priority = self.priority;
+        task.set_reminders(
+            self.reminder_offsets_secs
+                .iter()
+                .map(|offset| due_at + Duration::seconds(*offset))
+                .collect(),
+        );
+        task.owner_id = self.owner_id.clone();
+        task.series_id = Some(self.id);
+        Some(task)
+    }
+}
+
+/// Request DTO for editing an occurrence and all future ones
+#[derive(Debug, Default, Deserialize)]
+pub struct UpdateSeriesRequest {
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub description: Option<Option<String>>,
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    /// New rule, counted from the edited occurrence
+    #[serde(default)]
+    pub rule: Option<RRule>,
+    #[serde(default)]
+    pub timezone: Option<Tz>,
+}
+
+/// A series with its next occurrences, as returned by the API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeriesView {
+    #[serde(flatten)]
+    pub series: Series,
+    pub upcoming: Vec<DateTime<Utc>>,
+}
@@ -0,0 +1,282 @@
+use crate::{
+    config::{Config, ConfigSources},
+    health::{CheckOutcome, HealthRegistry, Heartbeat, Probe},
+    metrics::Metrics,
+};
+use std::{
+    fs,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+type ReloadListener = Box<dyn Fn(&Config) + Send + Sync>;
+
+/// Counter of reload attempts that kept the previous configuration
+pub const RELOAD_FAILURES_METRIC: &str = "config_reload_failures_total";
+
+/// Gauge of the Unix time of the last successful reload
+pub const LAST_RELOAD_SUCCESS_METRIC: &str = "config_last_reload_success_timestamp_seconds";
+
+/// Gauge of the Unix time of the last failed reload
+pub const LAST_RELOAD_FAILURE_METRIC: &str = "config_last_reload_failure_timestamp_seconds";
+
+/// Shared handle to the live configuration
+///
+/// Middleware reads [`ConfigHandle::current`] per request, so a successful
+/// reload takes effect on the next request without a restart. Cloning is
+/// cheap; every clone observes the same configuration.
+#[derive(Clone)]
+pub struct ConfigHandle {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    current: RwLock<Arc<Config>>,
+    sources: ConfigSources,
+    listeners: RwLock<Vec<ReloadListener>>,
+    reloads: AtomicU64,
+    failures: AtomicU64,
+    last_error: RwLock<Option<String>>,
+    metrics: RwLock<Option<Metrics>>,
+}
+
+/// Counters describing reload attempts so far
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReloadStats {
+    pub reloads: u64,
+    pub failures: u64,
+    /// Problems reported by the most recent reload, if it failed
+    pub last_error: Option<String>,
+}
+
+impl ConfigHandle {
+    /// Wraps an already loaded and validated configuration
+    pub fn new(config: Config, sources: ConfigSources) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                current: RwLock::new(Arc::new(config)),
+                sources,
+                listeners: RwLock::new(Vec::new()),
+                reloads: AtomicU64::new(0),
+                failures: AtomicU64::new(0),
+                last_error: RwLock::new(None),
+                metrics: RwLock::new(None),
+            }),
+        }
+    }
+
+    /// Returns the configuration currently in effect
+    pub fn current(&self) -> Arc<Config> {
+        Arc::clone(&self.inner.current.read().unwrap())
+    }
+
+    /// Calls `listener` with the new configuration after every successful reload
+    pub fn on_reload(&self, listener: impl Fn(&Config) + Send + Sync + 'static) {
+        self.inner
+            .listeners
+            .write()
+            .unwrap()
+            .push(Box::new(listener));
+    }
+
+    /// Reloads from the original sources and swaps in the reloadable sections.
+    ///
+    /// The new configuration is validated first; on failure the current one
+    /// stays in place and the problems are returned. Changes to static
+    /// sections are logged and ignored until the next restart. Returns
+    /// `Ok(true)` when the effective configuration changed.
+    pub fn reload(&self) -> Result<bool, Vec<String>> {
+        let result = Config::load(&self.inner.sources)
+            .map_err(|e| vec![e.to_string()])
+            .and_then(|loaded| loaded.validate().map(|_| loaded));
+
+        let loaded = match result {
+            Ok(loaded) => loaded,
+            Err(problems) => {
+                self.inner.failures.fetch_add(1, Ordering::Relaxed);
+                *self.inner.last_error.write().unwrap() = Some(problems.join("; "));
+                self.record_outcome(false);
+                return Err(problems);
+            }
+        };
+
+        self.inner.reloads.fetch_add(1, Ordering::Relaxed);
+        *self.inner.last_error.write().unwrap() = None;
+        self.record_outcome(true);
+
+        let current = self.current();
+        let restart_required = current.static_changes(&loaded);
+        if !restart_required.is_empty() {
+            warn!(
+                sections = %restart_required.join(","),
+                "Configuration changes in static sections require a restart"
+            );
+        }
+
+        let next = current.with_reloadable_from(&loaded);
+        if next == *current {
+            return Ok(false);
+        }
+
+        let next = Arc::new(next);
+        *self.inner.current.write().unwrap() = Arc::clone(&next);
+        for listener in self.inner.listeners.read().unwrap().iter() {
+            listener(&next);
+        }
+        Ok(true)
+    }
+
+    pub fn stats(&self) -> ReloadStats {
+        ReloadStats {
+            reloads: self.inner.reloads.load(Ordering::Relaxed),
+            failures: self.inner.failures.load(Ordering::Relaxed),
+            last_error: self.inner.last_error.read().unwrap().clone(),
+        }
+    }
+
+    /// Registers a non-critical readiness check that warns while the most
+    /// recent reload attempt has failed
+    pub fn register_health_check(&self, health: &HealthRegistry) {
+        let handle = self.clone();
+        health.register("config", &[Probe::Ready], false, move || {
+            let stats = handle.stats();
+            async move {
+                let summary = format!("{} reloads, {} failed", stats.reloads, stats.failures);
+                match stats.last_error {
+                    Some(error) => {
+                        CheckOutcome::warn(format!("{}; last reload failed: {}", summary, error))
+                    }
+                    None => CheckOutcome::pass_with(summary),
+                }
+            }
+        });
+    }
+
+    /// Exports reload failures and the time of the last success and failure
+    /// to `metrics`
+    pub fn register_metrics(&self, metrics: &Metrics) {
+        *self.inner.metrics.write().unwrap() = Some(metrics.clone());
+    }
+
+    fn record_outcome(&self, succeeded: bool) {
+        let Some(metrics) = self.inner.metrics.read().unwrap().clone() else {
+            return;
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        if succeeded {
+            metrics.set(
+                LAST_RELOAD_SUCCESS_METRIC,
+                "Unix time of the last successful configuration reload",
+                &[],
+                now,
+            );
+        } else {
+            metrics.increment(
+                RELOAD_FAILURES_METRIC,
+                "Configuration reloads that failed and kept the previous configuration",
+                &[],
+            );
+            metrics.set(
+                LAST_RELOAD_FAILURE_METRIC,
+                "Unix time of the last failed configuration reload",
+                &[],
+                now,
+            );
+        }
+    }
+
+    fn file_modified(&self) -> Option<SystemTime> {
+        let path = self.inner.sources.file.as_ref()?;
+        fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+}
+
+/// Reloads the configuration on SIGHUP and when the config file changes.
+///
+/// The file is polled every `interval` (if non-zero), matching how TLS
+/// certificates are watched.
+pub async fn watch_config(
+    handle: ConfigHandle,
+    interval: Duration,
+    heartbeat: Heartbeat,
+    shutdown: CancellationToken,
+) {
+    let mut hangup = hangup_signal();
+    let poll = !interval.is_zero() && handle.inner.sources.file.is_some();
+    let mut ticker = tokio::time::interval(if poll {
+        interval
+    } else {
+        Duration::from_secs(60)
+    });
+    ticker.tick().await;
+    let mut modified = handle.file_modified();
+
+    loop {
+        let trigger = tokio::select! {
+            _ = ticker.tick() => {
+                heartbeat.beat();
+                let now = handle.file_modified();
+                if !poll || now == modified {
+                    continue;
+                }
+                modified = now;
+                "file change"
+            }
+            _ = recv_hangup(&mut hangup) => "SIGHUP",
+            _ = shutdown.cancelled() => return,
+        };
+
+        match handle.reload() {
+            Ok(true) => info!(trigger, "Reloaded configuration"),
+            Ok(false) => info!(trigger, "Configuration reloaded without changes"),
+            Err(problems) => warn!(
+                trigger,
+                problems = %problems.join("; "),
+                "Configuration reload failed, keeping the previous configuration"
+            ),
+        }
+    }
+}
+
+#[cfg(unix)]
+type Hangup = Option<tokio::signal::unix::Signal>;
+#[cfg(not(unix))]
+type Hangup = ();
+
+#[cfg(unix)]
+fn hangup_signal() -> Hangup {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    match signal(SignalKind::hangup()) {
+        Ok(signal) => Some(signal),
+        Err(e) => {
+            warn!(error = %e, "Cannot install SIGHUP handler; reload on file change only");
+            None
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn hangup_signal() -> Hangup {}
+
+#[cfg(unix)]
+async fn recv_hangup(hangup: &mut Hangup) {
+    match hangup {
+        Some(signal) => {
+            signal.recv().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(not(unix))]
+async fn recv_hangup(_hangup: &mut Hangup) {
+    std::future::pending().await
+}
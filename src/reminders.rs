@@ -0,0 +1,102 @@
+use crate::{
+    health::Heartbeat,
+    metrics::Metrics,
+    models::Reminder,
+    tenancy::{TenantEntry, Tenants},
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+use uuid::Uuid;
+
+/// Counter of reminders fired, by tenant
+pub const REMINDERS_FIRED_METRIC: &str = "task_reminders_fired_total";
+
+/// A reminder that came due
+#[derive(Debug, Clone, Serialize)]
+pub struct ReminderEvent {
+    pub tenant: String,
+    pub task_id: Uuid,
+    pub title: String,
+    pub due_at: Option<DateTime<Utc>>,
+    /// Time the reminder was set for
+    pub remind_at: DateTime<Utc>,
+}
+
+/// Fires the reminders of a tenant's open tasks that are due at `now`,
+/// marking them fired on the task.
+///
+/// Only the fired state on the task decides what is pending, so reminders
+/// that came due while the server was down fire on the first run after it
+/// starts, and none fire twice.
+pub fn fire_due(tenant: &TenantEntry, now: DateTime<Utc>) -> Vec<ReminderEvent> {
+    let due = |reminder: &Reminder| reminder.fired_at.is_none() && reminder.at <= now;
+    let ids: Vec<Uuid> = tenant
+        .tasks
+        .iter()
+        .filter(|entry| !entry.completed && entry.reminders.iter().any(due))
+        .map(|entry| *entry.key())
+        .collect();
+
+    let mut events = Vec::new();
+    for id in ids {
+        // Checked again under the lock, as the task may have changed since
+        let Some(mut task) = tenant.tasks.get_mut(&id) else {
+            continue;
+        };
+        if task.completed {
+            continue;
+        }
+        let (title, due_at) = (task.title.clone(), task.due_at);
+        for reminder in task.reminders.iter_mut().filter(|r| due(r)) {
+            reminder.fired_at = Some(now);
+            events.push(ReminderEvent {
+                tenant: tenant.id.clone(),
+                task_id: id,
+                title: title.clone(),
+                due_at,
+                remind_at: reminder.at,
+            });
+        }
+    }
+    events
+}
+
+/// Fires due reminders of every tenant every `interval`, logging each one
+pub async fn run_reminders(
+    tenants: Tenants,
+    interval: Duration,
+    metrics: Metrics,
+    heartbeat: Heartbeat,
+    shutdown: CancellationToken,
+) {
+    let mut ticker = tokio::time::interval(interval);
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = shutdown.cancelled() => return,
+        }
+        heartbeat.beat();
+        let now = Utc::now();
+        for tenant in tenants.entries() {
+            for event in fire_due(&tenant, now) {
+                info!(
+                    tenant = %event.tenant,
+                    task = %event.task_id,
+                    title = %event.title,
+                    due_at = ?event.due_at,
+                    remind_at = %event.remind_at,
+                    "Task reminder"
+                );
+                metrics.increment(
+                    REMINDERS_FIRED_METRIC,
+                    "Task reminders fired",
+                    &[("tenant", &event.tenant)],
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use crate::{
+    audit::{self, FieldChange},
+    config::RevisionsConfig,
+    models::Task,
+};
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, RwLock},
+};
+use uuid::Uuid;
+
+/// Immutable snapshot of a task after one change
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Revision {
+    /// Starts at 1 for the creation and increases with every change
+    pub number: u64,
+    /// Principal that made the change
+    pub author: String,
+    pub created_at: DateTime<Utc>,
+    pub task: Task,
+}
+
+/// Revision metadata as listed by the API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevisionSummary {
+    pub number: u64,
+    pub author: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Field-level difference between two revisions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevisionDiff {
+    pub from: u64,
+    pub to: u64,
+    pub changes: Vec<FieldChange>,
+}
+
+impl RevisionDiff {
+    pub fn between(from: &Revision, to: &Revision) -> Self {
+        let value = |revision: &Revision| serde_json::to_value(&revision.task).ok();
+        Self {
+            from: from.number,
+            to: to.number,
+            changes: audit::field_diff(value(from).as_ref(), value(to).as_ref()),
+        }
+    }
+}
+
+/// Query parameters of the revision diff endpoint
+#[derive(Debug, Deserialize)]
+pub struct DiffQuery {
+    pub from: u64,
+    pub to: u64,
+}
+
+#[derive(Debug, Default)]
+struct History {
+    last: u64,
+    revisions: VecDeque<Revision>,
+}
+
+/// Revision histories of one tenant's tasks.
+///
+/// Retention is shared by every tenant and applied whenever a revision is
+/// added; the latest revision of a task is always kept.
+#[derive(Clone, Default)]
+pub struct Revisions {
+    histories: Arc<DashMap<Uuid, History>>,
+    retention: Arc<RwLock<RevisionsConfig>>,
+}
+
+impl Revisions {
+    pub fn new(retention: Arc<RwLock<RevisionsConfig>>) -> Self {
+        Self {
+            histories: Arc::default(),
+            retention,
+        }
+    }
+
+    /// Adds a revision with the current state of `task`, returning its number
+    pub fn record(&self, task: &Task, author: &str) -> u64 {
+        let retention = self.retention.read().unwrap().clone();
+        let mut history = self.histories.entry(task.id).or_default();
+        history.last += 1;
+        let number = history.last;
+        history.revisions.push_back(Revision {
+            number,
+            author: author.to_string(),
+            created_at: Utc::now(),
+            task: task.clone(),
+        });
+
+        // An age reaching before the earliest representable time drops nothing
+        let cutoff = (retention.max_age_days > 0)
+            .then(|| {
+                i64::try_from(retention.max_age_days)
+                    .ok()
+                    .and_then(Duration::try_days)
+            })
+            .flatten()
+            .and_then(|max_age| Utc::now().checked_sub_signed(max_age));
+        while history.revisions.len() > 1 {
+            let oldest = &history.revisions[0];
+            let too_many = retention.max_count > 0 && history.revisions.len() > retention.max_count;
+            let too_old = cutoff.is_some_and(|cutoff| oldest.created_at < cutoff);
+            if !(too_many || too_old) {
+                break;
+            }
+            history.revisions.pop_front();
+        }
+        number
+    }
+
+    /// Retained revisions of a task, oldest first
+    pub fn list(&self, id: &Uuid) -> Vec<RevisionSummary> {
+        self.histories
+            .get(id)
+            .map(|history| {
+                history
+                    .revisions
+                    .iter()
+                    .map(|r| RevisionSummary {
+                        number: r.number,
+                        author: r.author.clone(),
+                        created_at: r.created_at,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn get(&self, id: &Uuid, number: u64) -> Option<Revision> {
+        self.histories.get(id).and_then(|history| {
+            history
+                .revisions
+                .iter()
+                .find(|r| r.number == number)
+                .cloned()
+        })
+    }
+
+    /// Forgets the history of a task
+    pub fn remove(&self, id: &Uuid) {
+        self.histories.remove(id);
+    }
+}
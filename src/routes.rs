@@ -1,17 +1,170 @@
-use crate::handlers;
-use actix_web::web;
+use crate::{config::LimitsConfig, errors::AppError, handlers, middleware::body_limit::BodyLimit};
+use actix_web::{error::JsonPayloadError, web};
 
 /// Configure all application routes
-pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+pub fn configure_routes(cfg: &mut web::ServiceConfig, limits: &LimitsConfig) {
     cfg.service(
         web::scope("/api/v1")
-            // Task endpoints
-            .route("/tasks", web::get().to(handlers::get_tasks))
-            .route("/tasks", web::post().to(handlers::create_task))
-            .route("/tasks/{id}", web::get().to(handlers::get_task))
-            .route("/tasks/{id}", web::put().to(handlers::update_task))
-            .route("/tasks/{id}", web::delete().to(handlers::delete_task)),
+            // Bulk import streams large NDJSON bodies and gets its own limit
+            .service(
+                web::resource("/tasks/import")
+                    .wrap(BodyLimit::new(limits.import_max_bytes))
+                    .route(web::post().to(handlers::import_tasks)),
+            )
+            // Attachment uploads stream file bodies under their own limit
+            .service(
+                web::resource("/tasks/{id}/attachments")
+                    .wrap(BodyLimit::new(limits.attachment_max_bytes))
+                    .route(web::get().to(handlers::list_attachments))
+                    .route(web::post().to(handlers::upload_attachments)),
+            )
+            .service(
+                web::scope("")
+                    .app_data(json_config(limits.json_max_bytes))
+                    .wrap(BodyLimit::new(limits.json_max_bytes))
+                    // Task endpoints
+                    .route("/tasks", web::get().to(handlers::get_tasks))
+                    .route("/tasks", web::post().to(handlers::create_task))
+                    // Registered before `/tasks/{id}` so "export" is not taken as an id
+                    .route("/tasks/export", web::get().to(handlers::export_tasks))
+                    .route("/tasks/plan", web::get().to(handlers::plan_tasks))
+                    .route("/tasks/search", web::get().to(handlers::search_tasks))
+                    // Registered before `/tasks/{id}` so the action suffix is not taken as part of the id
+                    .route(
+                        "/tasks/{id}:restore",
+                        web::post().to(handlers::restore_task),
+                    )
+                    .route("/tasks/{id}", web::get().to(handlers::get_task))
+                    .route("/tasks/{id}", web::put().to(handlers::update_task))
+                    .route("/tasks/{id}", web::delete().to(handlers::delete_task))
+                    // Subtasks
+                    .route(
+                        "/tasks/{id}/children",
+                        web::get().to(handlers::list_children),
+                    )
+                    .route("/tasks/{id}/subtree", web::get().to(handlers::get_subtree))
+                    .route("/tasks/{id}/parent", web::put().to(handlers::move_task))
+                    // Dependencies
+                    .route(
+                        "/tasks/{id}/dependencies",
+                        web::post().to(handlers::add_dependency),
+                    )
+                    .route(
+                        "/tasks/{id}/dependencies/{dependency}",
+                        web::delete().to(handlers::remove_dependency),
+                    )
+                    // Labels
+                    .route(
+                        "/tasks/{id}/labels",
+                        web::post().to(handlers::change_labels),
+                    )
+                    .route("/labels", web::get().to(handlers::list_labels))
+                    .route("/labels/{name}", web::put().to(handlers::put_label))
+                    .route("/labels/{name}", web::delete().to(handlers::delete_label))
+                    // Comments
+                    .route(
+                        "/tasks/{id}/comments",
+                        web::get().to(handlers::list_comments),
+                    )
+                    .route(
+                        "/tasks/{id}/comments",
+                        web::post().to(handlers::create_comment),
+                    )
+                    .route(
+                        "/tasks/{id}/comments/{comment_id}",
+                        web::get().to(handlers::get_comment),
+                    )
+                    .route(
+                        "/tasks/{id}/comments/{comment_id}",
+                        web::put().to(handlers::update_comment),
+                    )
+                    .route(
+                        "/tasks/{id}/comments/{comment_id}",
+                        web::delete().to(handlers::delete_comment),
+                    )
+                    // Attachments
+                    .route(
+                        "/tasks/{id}/attachments/{attachment_id}",
+                        web::get().to(handlers::get_attachment),
+                    )
+                    .route(
+                        "/tasks/{id}/attachments/{attachment_id}",
+                        web::delete().to(handlers::delete_attachment),
+                    )
+                    .route(
+                        "/tasks/{id}/attachments/{attachment_id}/content",
+                        web::get().to(handlers::download_attachment),
+                    )
+                    // Recurring series of an occurrence
+                    .route("/tasks/{id}/series", web::get().to(handlers::get_series))
+                    .route("/tasks/{id}/series", web::put().to(handlers::update_series))
+                    // Revision history
+                    .route(
+                        "/tasks/{id}/revisions",
+                        web::get().to(handlers::list_revisions),
+                    )
+                    .route(
+                        "/tasks/{id}/revisions/diff",
+                        web::get().to(handlers::diff_revisions),
+                    )
+                    .route(
+                        "/tasks/{id}/revisions/{n:\\d+}",
+                        web::get().to(handlers::get_revision),
+                    )
+                    .route(
+                        "/tasks/{id}/revisions/{n:\\d+}:restore",
+                        web::post().to(handlers::restore_revision),
+                    )
+                    // Trash
+                    .route("/trash", web::get().to(handlers::list_trash))
+                    .route("/trash", web::delete().to(handlers::empty_trash))
+                    .route("/trash/{id}", web::delete().to(handlers::purge_task))
+                    // Tenant administration (admins of the default tenant)
+                    .route("/admin/tenants", web::get().to(handlers::list_tenants))
+                    .route("/admin/tenants", web::post().to(handlers::create_tenant))
+                    .route("/admin/tenants/{id}", web::get().to(handlers::get_tenant))
+                    .route(
+                        "/admin/tenants/{id}",
+                        web::delete().to(handlers::delete_tenant),
+                    )
+                    .route(
+                        "/admin/tenants/{id}/quota",
+                        web::put().to(handlers::update_tenant_quota),
+                    )
+                    .route(
+                        "/admin/tenants/{id}/suspend",
+                        web::post().to(handlers::suspend_tenant),
+                    )
+                    .route(
+                        "/admin/tenants/{id}/resume",
+                        web::post().to(handlers::resume_tenant),
+                    )
+                    .route("/admin/usage", web::get().to(handlers::list_usage))
+                    // Audit log of the caller's tenant (admins)
+                    .route("/audit", web::get().to(handlers::get_audit_log))
+                    // Usage of the caller's tenant
+                    .route("/usage", web::get().to(handlers::get_usage)),
+            ),
     )
-    // Health check (public, no auth required)
-    .route("/health", web::get().to(handlers::health_check));
+    // Health checks (public, no auth required)
+    .route("/health", web::get().to(handlers::health_check))
+    .route("/health/live", web::get().to(handlers::liveness))
+    .route("/health/ready", web::get().to(handlers::readiness))
+    .route("/health/startup", web::get().to(handlers::startup))
+    // Metrics (API key required; restrict further with `network.scopes`)
+    .route("/metrics", web::get().to(handlers::metrics));
+}
+
+/// JSON extractor config that reports oversized bodies as 413
+pub fn json_config(limit: usize) -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(limit)
+        .error_handler(|err, _req| match err {
+            JsonPayloadError::Overflow { .. }
+            | JsonPayloadError::OverflowKnownLength { .. }
+            | JsonPayloadError::Payload(actix_web::error::PayloadError::Overflow) => {
+                AppError::PayloadTooLarge(err.to_string()).into()
+            }
+            _ => AppError::Validation(err.to_string()).into(),
+        })
 }
@@ -0,0 +1,532 @@
+use crate::{errors::AppError, models::Task, tenancy::TaskStore};
+use rust_stemmers::{Algorithm, Stemmer};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::{OnceLock, RwLock};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+use uuid::Uuid;
+
+/// BM25 term frequency saturation
+const K1: f64 = 1.2;
+
+/// BM25 document length normalisation
+const B: f64 = 0.75;
+
+/// Most dictionary words a single prefix or fuzzy term expands to
+const MAX_EXPANSIONS: usize = 64;
+
+/// Words around the first match kept in a description snippet
+const SNIPPET_WORDS: usize = 24;
+
+/// Hits returned when the query does not set `limit`
+pub const DEFAULT_LIMIT: usize = 20;
+
+/// Most hits a single search returns
+pub const MAX_LIMIT: usize = 100;
+
+/// A word of some text, normalised, with its byte range in the text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub word: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Splits text into lowercase words without diacritics.
+///
+/// Text is NFKD-normalised so `Café`, `cafe` and the fullwidth `ｃａｆｅ` all
+/// become `cafe`; anything that is not a letter or digit separates words.
+pub fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let in_word = |c: char| c.is_alphanumeric() || is_combining_mark(c);
+    for (i, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        match (start, in_word(c) && i < text.len()) {
+            (None, true) => start = Some(i),
+            (Some(from), false) => {
+                let word: String = text[from..i]
+                    .nfkd()
+                    .filter(|c| !is_combining_mark(*c))
+                    .flat_map(char::to_lowercase)
+                    .filter(|c| c.is_alphanumeric())
+                    .collect();
+                if !word.is_empty() {
+                    tokens.push(Token {
+                        word,
+                        start: from,
+                        end: i,
+                    });
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    tokens
+}
+
+/// English stem of a normalised word
+pub fn stem(word: &str) -> String {
+    static STEMMER: OnceLock<Stemmer> = OnceLock::new();
+    STEMMER
+        .get_or_init(|| Stemmer::create(Algorithm::English))
+        .stem(word)
+        .into_owned()
+}
+
+/// One part of a search query; every clause has to match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Clause {
+    /// A word, matched by its stem
+    Term(String),
+    /// `word*`: any word starting with the prefix
+    Prefix(String),
+    /// `word~`: the word or one a small edit away
+    Fuzzy(String),
+    /// `"two words"`: the words next to each other, in order
+    Phrase(Vec<String>),
+}
+
+/// Parses a query of words, `prefix*`, `fuzzy~` and `"quoted phrases"`
+pub fn parse(query: &str) -> Result<Vec<Clause>, AppError> {
+    let mut clauses = Vec::new();
+    let mut rest = query;
+    while let Some(at) = rest.find(|c: char| !c.is_whitespace()) {
+        rest = &rest[at..];
+        let (part, quoted) = match rest.strip_prefix('"') {
+            Some(inner) => {
+                let end = inner.find('"').unwrap_or(inner.len());
+                let part = &inner[..end];
+                rest = inner.get(end + 1..).unwrap_or("");
+                (part, true)
+            }
+            None => {
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                let part = &rest[..end];
+                rest = &rest[end..];
+                (part, false)
+            }
+        };
+
+        let mut words: Vec<String> = tokenize(part).into_iter().map(|t| t.word).collect();
+        if quoted || words.len() > 1 && !part.ends_with(['*', '~']) {
+            match words.len() {
+                0 => {}
+                1 => clauses.push(Clause::Term(words.remove(0))),
+                _ => clauses.push(Clause::Phrase(words)),
+            }
+            continue;
+        }
+        let Some(last) = words.pop() else {
+            continue;
+        };
+        clauses.extend(words.into_iter().map(Clause::Term));
+        clauses.push(if part.ends_with('*') {
+            Clause::Prefix(last)
+        } else if part.ends_with('~') {
+            Clause::Fuzzy(last)
+        } else {
+            Clause::Term(last)
+        });
+    }
+
+    if clauses.is_empty() {
+        return Err(AppError::Validation(
+            "Search query has no words".to_string(),
+        ));
+    }
+    Ok(clauses)
+}
+
+/// Query parameters of the search endpoint
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    pub limit: Option<usize>,
+}
+
+/// Documents matching a query, best first, with the stems to highlight
+#[derive(Debug, Default)]
+pub struct Matches {
+    pub scores: Vec<(Uuid, f64)>,
+    pub stems: HashSet<String>,
+}
+
+/// A search result: the task, its score and highlighted text
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    #[serde(flatten)]
+    pub task: Task,
+    pub score: f64,
+    pub highlight: Highlight,
+}
+
+/// Task text with matching words wrapped in `<mark>`; the rest is
+/// HTML-escaped
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Highlight {
+    pub title: String,
+    /// Part of the description around the first match
+    pub description: Option<String>,
+}
+
+impl Highlight {
+    pub fn new(task: &Task, stems: &HashSet<String>) -> Self {
+        Self {
+            title: snippet(&task.title, stems, usize::MAX),
+            description: task
+                .description
+                .as_deref()
+                .map(|description| snippet(description, stems, SNIPPET_WORDS)),
+        }
+    }
+}
+
+/// Search results with the number of readable matches
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchResults {
+    pub total: usize,
+    pub hits: Vec<SearchHit>,
+}
+
+/// Marks words of `text` whose stem is in `stems`, keeping at most `words`
+/// words around the first one
+pub fn snippet(text: &str, stems: &HashSet<String>, words: usize) -> String {
+    let tokens = tokenize(text);
+    let marked: Vec<bool> = tokens
+        .iter()
+        .map(|token| stems.contains(&stem(&token.word)))
+        .collect();
+
+    let (from, to) = if tokens.len() <= words {
+        (0, tokens.len())
+    } else {
+        let first = marked.iter().position(|m| *m).unwrap_or(0);
+        let from = first.saturating_sub(words / 3).min(tokens.len() - words);
+        (from, from + words)
+    };
+    let mut out = String::new();
+    if from > 0 {
+        out.push('…');
+    }
+    let start = if from == 0 { 0 } else { tokens[from].start };
+    let end = if to == tokens.len() {
+        text.len()
+    } else {
+        tokens[to - 1].end
+    };
+    let mut cursor = start;
+    for (token, marked) in tokens[from..to].iter().zip(&marked[from..to]) {
+        if *marked {
+            escape_into(&mut out, &text[cursor..token.start]);
+            out.push_str("<mark>");
+            escape_into(&mut out, &text[token.start..token.end]);
+            out.push_str("</mark>");
+            cursor = token.end;
+        }
+    }
+    escape_into(&mut out, &text[cursor..end]);
+    if to < tokens.len() {
+        out.push('…');
+    }
+    out
+}
+
+fn escape_into(out: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// An indexed task
+struct Doc {
+    /// Hash of the indexed text, to skip changes that do not touch it
+    fingerprint: u64,
+    len: u32,
+    stems: HashSet<String>,
+    words: HashSet<String>,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Positions of every stem, by task
+    postings: HashMap<String, HashMap<Uuid, Vec<u32>>>,
+    /// Every indexed word with the number of tasks using it, for prefix and
+    /// fuzzy expansion
+    words: BTreeMap<String, usize>,
+    docs: HashMap<Uuid, Doc>,
+    total_len: u64,
+}
+
+/// A tenant's full-text index over task titles and descriptions
+#[derive(Default)]
+pub struct SearchIndex {
+    inner: RwLock<Inner>,
+}
+
+impl SearchIndex {
+    /// Replaces the indexed text of a task; `None` removes it
+    pub fn reindex(&self, id: Uuid, task: Option<&Task>) {
+        let fingerprint = task.map(fingerprint);
+        let mut inner = self.inner.write().unwrap();
+        if inner.docs.get(&id).map(|doc| doc.fingerprint) == fingerprint {
+            return;
+        }
+        inner.remove(&id);
+        if let Some(task) = task {
+            inner.add(task);
+        }
+    }
+
+    /// Indexes every task of the store from scratch
+    pub fn rebuild(&self, tasks: &TaskStore) {
+        let mut inner = Inner::default();
+        for task in tasks.iter() {
+            inner.add(task.value());
+        }
+        *self.inner.write().unwrap() = inner;
+    }
+
+    /// Number of indexed tasks
+    pub fn len(&self) -> usize {
+        self.inner.read().unwrap().docs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Tasks matching every clause, ranked by BM25
+    pub fn search(&self, clauses: &[Clause]) -> Matches {
+        let inner = self.inner.read().unwrap();
+        let mut matches = Matches::default();
+        let mut scores: Option<HashMap<Uuid, f64>> = None;
+        for clause in clauses {
+            let (clause_scores, stems) = inner.clause(clause);
+            matches.stems.extend(stems);
+            scores = Some(match scores {
+                None => clause_scores,
+                Some(scores) => scores
+                    .into_iter()
+                    .filter_map(|(id, score)| clause_scores.get(&id).map(|more| (id, score + more)))
+                    .collect(),
+            });
+        }
+
+        matches.scores = scores.unwrap_or_default().into_iter().collect();
+        matches
+            .scores
+            .sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        matches
+    }
+}
+
+fn fingerprint(task: &Task) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (&task.title, &task.description).hash(&mut hasher);
+    hasher.finish()
+}
+
+impl Inner {
+    fn add(&mut self, task: &Task) {
+        // Description positions follow the title's after a gap, so phrases
+        // never run from one into the other
+        let title = tokenize(&task.title);
+        let description = task
+            .description
+            .as_deref()
+            .map(tokenize)
+            .unwrap_or_default();
+        let gap = title.len() as u32 + 1;
+        let positioned = title
+            .iter()
+            .enumerate()
+            .map(|(i, token)| (i as u32, token))
+            .chain(
+                description
+                    .iter()
+                    .enumerate()
+                    .map(|(i, token)| (gap + i as u32, token)),
+            );
+
+        let mut doc = Doc {
+            fingerprint: fingerprint(task),
+            len: (title.len() + description.len()) as u32,
+            stems: HashSet::new(),
+            words: HashSet::new(),
+        };
+        for (position, token) in positioned {
+            let stem = stem(&token.word);
+            self.postings
+                .entry(stem.clone())
+                .or_default()
+                .entry(task.id)
+                .or_default()
+                .push(position);
+            doc.stems.insert(stem);
+            doc.words.insert(token.word.clone());
+        }
+        for word in &doc.words {
+            *self.words.entry(word.clone()).or_default() += 1;
+        }
+        self.total_len += u64::from(doc.len);
+        self.docs.insert(task.id, doc);
+    }
+
+    fn remove(&mut self, id: &Uuid) {
+        let Some(doc) = self.docs.remove(id) else {
+            return;
+        };
+        for stem in &doc.stems {
+            if let Some(postings) = self.postings.get_mut(stem) {
+                postings.remove(id);
+                if postings.is_empty() {
+                    self.postings.remove(stem);
+                }
+            }
+        }
+        for word in &doc.words {
+            if let Some(count) = self.words.get_mut(word) {
+                *count -= 1;
+                if *count == 0 {
+                    self.words.remove(word);
+                }
+            }
+        }
+        self.total_len -= u64::from(doc.len);
+    }
+
+    /// Scores of the tasks matching a clause, with the stems it matched
+    fn clause(&self, clause: &Clause) -> (HashMap<Uuid, f64>, HashSet<String>) {
+        match clause {
+            Clause::Term(word) => {
+                let stem = stem(word);
+                (self.term(&stem), HashSet::from([stem]))
+            }
+            Clause::Prefix(prefix) => {
+                let words = self
+                    .words
+                    .range(prefix.clone()..)
+                    .map(|(word, _)| word)
+                    .take_while(|word| word.starts_with(prefix.as_str()));
+                self.expansion(words)
+            }
+            Clause::Fuzzy(word) => {
+                let distance = match word.chars().count() {
+                    0..=2 => 0,
+                    3..=5 => 1,
+                    _ => 2,
+                };
+                let words = self
+                    .words
+                    .keys()
+                    .filter(|other| within(word, other, distance));
+                self.expansion(words)
+            }
+            Clause::Phrase(words) => {
+                let stems: Vec<String> = words.iter().map(|word| stem(word)).collect();
+                (self.phrase(&stems), stems.into_iter().collect())
+            }
+        }
+    }
+
+    /// Scores for any of the dictionary `words`, keeping each task's best
+    fn expansion<'a>(
+        &self,
+        words: impl Iterator<Item = &'a String>,
+    ) -> (HashMap<Uuid, f64>, HashSet<String>) {
+        let stems: HashSet<String> = words.take(MAX_EXPANSIONS).map(|word| stem(word)).collect();
+        let mut scores: HashMap<Uuid, f64> = HashMap::new();
+        for stem in &stems {
+            for (id, score) in self.term(stem) {
+                let best = scores.entry(id).or_default();
+                *best = best.max(score);
+            }
+        }
+        (scores, stems)
+    }
+
+    fn term(&self, stem: &str) -> HashMap<Uuid, f64> {
+        let Some(postings) = self.postings.get(stem) else {
+            return HashMap::new();
+        };
+        let idf = self.idf(postings.len());
+        postings
+            .iter()
+            .map(|(id, positions)| (*id, self.bm25(id, idf, positions.len())))
+            .collect()
+    }
+
+    fn phrase(&self, stems: &[String]) -> HashMap<Uuid, f64> {
+        let Some(postings) = stems
+            .iter()
+            .map(|stem| self.postings.get(stem))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return HashMap::new();
+        };
+        let idf: f64 = postings.iter().map(|p| self.idf(p.len())).sum();
+
+        let mut scores = HashMap::new();
+        for (id, starts) in postings[0] {
+            let occurrences = starts
+                .iter()
+                .filter(|start| {
+                    postings[1..].iter().enumerate().all(|(i, p)| {
+                        p.get(id)
+                            .is_some_and(|positions| positions.contains(&(**start + i as u32 + 1)))
+                    })
+                })
+                .count();
+            if occurrences > 0 {
+                scores.insert(*id, self.bm25(id, idf, occurrences));
+            }
+        }
+        scores
+    }
+
+    fn idf(&self, frequency: usize) -> f64 {
+        let n = self.docs.len() as f64;
+        let frequency = frequency as f64;
+        (1.0 + (n - frequency + 0.5) / (frequency + 0.5)).ln()
+    }
+
+    fn bm25(&self, id: &Uuid, idf: f64, frequency: usize) -> f64 {
+        let len = self.docs.get(id).map_or(0, |doc| doc.len) as f64;
+        let average = self.total_len as f64 / self.docs.len().max(1) as f64;
+        let frequency = frequency as f64;
+        idf * frequency * (K1 + 1.0) / (frequency + K1 * (1.0 - B + B * len / average.max(1.0)))
+    }
+}
+
+/// Whether two words are at most `distance` single-character edits apart
+fn within(a: &str, b: &str, distance: usize) -> bool {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.len().abs_diff(b.len()) > distance {
+        return false;
+    }
+
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        if current.iter().min().is_some_and(|min| *min > distance) {
+            return false;
+        }
+        previous = current;
+    }
+    previous[b.len()] <= distance
+}
@@ -0,0 +1,170 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use config::ConfigError;
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, fs, path::Path};
+
+/// Prefix identifying the encrypted secrets file format
+const FORMAT_PREFIX: &str = "v1:";
+
+/// Environment variable holding the base64 secrets file key
+pub const SECRETS_KEY_ENV: &str = "SECRETS_KEY";
+
+/// Environment variable naming a file that holds the secrets file key
+pub const SECRETS_KEY_FILE_ENV: &str = "SECRETS_KEY_FILE";
+
+/// A configuration value that must never appear in logs or dumps
+///
+/// `Debug`, `Display` and `Serialize` all print `[redacted]`; the value is
+/// only reachable through [`Secret::expose`].
+#[derive(Clone, Default)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    /// Compares against a candidate in time independent of where they differ
+    pub fn matches(&self, candidate: &str) -> bool {
+        let (a, b) = (self.0.as_bytes(), candidate.as_bytes());
+        a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+    }
+}
+
+impl PartialEq for Secret {
+    fn eq(&self, other: &Self) -> bool {
+        self.matches(&other.0)
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret([redacted])")
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str("[redacted]")
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Secret)
+    }
+}
+
+/// Reads a secret mounted as a file, dropping the trailing newline
+pub fn read_secret_file(path: &Path) -> Result<Secret, ConfigError> {
+    let contents = fs::read_to_string(path).map_err(|e| {
+        ConfigError::Message(format!("Cannot read secret file {}: {}", path.display(), e))
+    })?;
+    Ok(Secret::new(contents.trim_end_matches(['\r', '\n'])))
+}
+
+/// Generates a random key for the encrypted secrets file, base64 encoded
+pub fn generate_key() -> Result<String, ConfigError> {
+    let mut key = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut key)
+        .map_err(|_| ConfigError::Message("Cannot generate a random key".to_string()))?;
+    Ok(STANDARD.encode(key))
+}
+
+/// Loads the secrets file key from `SECRETS_KEY` or `SECRETS_KEY_FILE`
+pub fn key_from_env() -> Result<Secret, ConfigError> {
+    if let Ok(key) = std::env::var(SECRETS_KEY_ENV) {
+        return Ok(Secret::new(key));
+    }
+    match std::env::var(SECRETS_KEY_FILE_ENV) {
+        Ok(path) => read_secret_file(Path::new(&path)),
+        Err(_) => Err(ConfigError::Message(format!(
+            "An encrypted secrets file needs {} or {}",
+            SECRETS_KEY_ENV, SECRETS_KEY_FILE_ENV
+        ))),
+    }
+}
+
+/// Encrypts a TOML document of secrets with AES-256-GCM
+pub fn encrypt(plaintext: &str, key: &Secret) -> Result<String, ConfigError> {
+    let key = aead_key(key)?;
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| ConfigError::Message("Cannot generate a nonce".to_string()))?;
+
+    let mut sealed = plaintext.as_bytes().to_vec();
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::empty(),
+        &mut sealed,
+    )
+    .map_err(|_| ConfigError::Message("Cannot encrypt secrets".to_string()))?;
+
+    let mut out = nonce.to_vec();
+    out.extend_from_slice(&sealed);
+    Ok(format!("{}{}\n", FORMAT_PREFIX, STANDARD.encode(out)))
+}
+
+/// Decrypts a secrets file produced by [`encrypt`]
+pub fn decrypt(contents: &str, key: &Secret) -> Result<String, ConfigError> {
+    let invalid = || ConfigError::Message("Secrets file is not in the expected format".to_string());
+
+    let encoded = contents
+        .trim()
+        .strip_prefix(FORMAT_PREFIX)
+        .ok_or_else(invalid)?;
+    let mut data = STANDARD.decode(encoded).map_err(|_| invalid())?;
+    if data.len() < NONCE_LEN {
+        return Err(invalid());
+    }
+
+    let mut sealed = data.split_off(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(&data).map_err(|_| invalid())?;
+    let plaintext = aead_key(key)?
+        .open_in_place(nonce, Aad::empty(), &mut sealed)
+        .map_err(|_| {
+            ConfigError::Message(
+                "Cannot decrypt secrets file: wrong key or corrupted file".to_string(),
+            )
+        })?;
+
+    String::from_utf8(plaintext.to_vec())
+        .map_err(|_| ConfigError::Message("Decrypted secrets are not valid UTF-8".to_string()))
+}
+
+/// Reads and decrypts a secrets file with the key from the environment
+pub fn load_encrypted_file(path: &Path) -> Result<String, ConfigError> {
+    let contents = fs::read_to_string(path).map_err(|e| {
+        ConfigError::Message(format!(
+            "Cannot read secrets file {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    decrypt(&contents, &key_from_env()?)
+}
+
+fn aead_key(key: &Secret) -> Result<LessSafeKey, ConfigError> {
+    let bytes = STANDARD
+        .decode(key.expose().trim())
+        .map_err(|_| ConfigError::Message("Secrets key is not valid base64".to_string()))?;
+    let key = UnboundKey::new(&AES_256_GCM, &bytes)
+        .map_err(|_| ConfigError::Message("Secrets key must be 32 bytes".to_string()))?;
+    Ok(LessSafeKey::new(key))
+}
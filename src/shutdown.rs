@@ -0,0 +1,142 @@
+use actix_web::dev::Server;
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::time::Instant;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tracing::{info, warn};
+
+/// Coordinates graceful shutdown across the server and background tasks
+///
+/// Cloning is cheap; every clone observes the same draining state.
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    draining: Arc<AtomicBool>,
+    token: CancellationToken,
+    tasks: TaskTracker,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether shutdown has begun; readiness reports 503 from this point on
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Marks the process as draining without stopping anything yet
+    pub fn begin(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+    }
+
+    /// Token cancelled once background tasks should wind down
+    pub fn token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+
+    /// Spawns a background task that shutdown signals and then awaits.
+    ///
+    /// The task is expected to watch [`Shutdown::token`] and return promptly
+    /// once it is cancelled, finishing any pending work (flushes, deliveries)
+    /// first.
+    pub fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.tasks.spawn(task);
+    }
+
+    /// Cancels background tasks and waits for them until `deadline`.
+    ///
+    /// Returns false if some tasks were still running at the deadline.
+    pub async fn stop_tasks(&self, deadline: Instant) -> bool {
+        self.token.cancel();
+        self.tasks.close();
+        tokio::time::timeout_at(deadline, self.tasks.wait())
+            .await
+            .is_ok()
+    }
+}
+
+/// Settings for the shutdown sequence
+#[derive(Debug, Clone, Copy)]
+pub struct DrainSettings {
+    /// Time between failing readiness and closing the listeners, giving load
+    /// balancers a chance to stop routing new requests here
+    pub readiness_delay: Duration,
+    /// Total time allowed for in-flight requests and background tasks
+    pub deadline: Duration,
+}
+
+/// Resolves once SIGTERM or SIGINT is received
+pub async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut term = signal(SignalKind::terminate()).expect("failed to install SIGTERM handler");
+        tokio::select! {
+            _ = term.recv() => info!("Received SIGTERM"),
+            _ = tokio::signal::ctrl_c() => info!("Received SIGINT"),
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        info!("Received Ctrl-C");
+    }
+}
+
+/// Runs the server until a termination signal, then drains it.
+///
+/// The server must have been built with `disable_signals()` and a shutdown
+/// timeout at least as long as `settings.deadline`. Returns true when every
+/// in-flight request and background task finished before the deadline.
+pub async fn run_until_signal(server: Server, shutdown: Shutdown, settings: DrainSettings) -> bool {
+    let handle = server.handle();
+    let mut server = tokio::spawn(server);
+
+    tokio::select! {
+        res = &mut server => {
+            // The server stopped on its own (e.g. a fatal listener error)
+            if let Ok(Err(e)) = res {
+                warn!(error = %e, "Server stopped unexpectedly");
+            }
+            return shutdown.stop_tasks(Instant::now() + settings.deadline).await;
+        }
+        _ = wait_for_signal() => {}
+    }
+
+    info!("Shutting down: readiness now failing");
+    shutdown.begin();
+    tokio::time::sleep(settings.readiness_delay).await;
+
+    let deadline = Instant::now() + settings.deadline;
+    info!(
+        deadline_secs = settings.deadline.as_secs(),
+        "Draining in-flight requests"
+    );
+
+    // Stop accepting connections and wait for in-flight requests
+    let stop = handle.stop(true);
+    let requests_drained = tokio::time::timeout_at(deadline, stop).await.is_ok();
+    if !requests_drained {
+        warn!("In-flight requests did not finish before the shutdown deadline");
+    }
+    let _ = server.await;
+
+    let tasks_drained = shutdown.stop_tasks(deadline).await;
+    if !tasks_drained {
+        warn!("Background tasks did not finish before the shutdown deadline");
+    }
+
+    requests_drained && tasks_drained
+}
@@ -1,20 +1,81 @@
-use crate::models::Task;
-use dashmap::DashMap;
-use std::sync::Arc;
-use uuid::Uuid;
+use crate::{
+    attachments::AttachmentStorage,
+    audit::AuditLog,
+    health::{CheckOutcome, HealthRegistry, Probe, DEFAULT_CHECK_TIMEOUT_MS},
+    metrics::Metrics,
+    shutdown::Shutdown,
+    tenancy::{TaskStore, Tenants, DEFAULT_TENANT},
+};
+use std::time::Duration;
 
 /// Application state shared across all request handlers
 #[derive(Clone)]
 pub struct AppState {
-    /// In-memory task storage (thread-safe)
-    pub tasks: Arc<DashMap<Uuid, Task>>,
+    /// In-memory task storage, partitioned per tenant
+    pub tenants: Tenants,
+    /// Graceful shutdown coordination and background task tracking
+    pub shutdown: Shutdown,
+    /// Named checks backing the liveness, readiness and startup probes
+    pub health: HealthRegistry,
+    /// Counters exposed on `/metrics`
+    pub metrics: Metrics,
+    /// Record of every mutating operation
+    pub audit: AuditLog,
+    /// Blob store and upload rules for task attachments
+    pub attachments: AttachmentStorage,
 }
 
 impl AppState {
+    /// State whose health checks use the default timeout
     pub fn new() -> Self {
-        Self {
-            tasks: Arc::new(DashMap::new()),
-        }
+        Self::with_check_timeout(Duration::from_millis(DEFAULT_CHECK_TIMEOUT_MS))
+    }
+
+    /// State whose health checks time out after `check_timeout` unless
+    /// registered with their own, as configured by `health.check_timeout_ms`
+    pub fn with_check_timeout(check_timeout: Duration) -> Self {
+        let state = Self {
+            tenants: Tenants::new(),
+            shutdown: Shutdown::new(),
+            health: HealthRegistry::new(check_timeout),
+            metrics: Metrics::new(),
+            audit: AuditLog::default(),
+            attachments: AttachmentStorage::default(),
+        };
+        state.register_health_checks();
+        state
+    }
+
+    /// Tasks of the default tenant
+    pub fn default_tasks(&self) -> TaskStore {
+        self.tenants.ensure(DEFAULT_TENANT).tasks.clone()
+    }
+
+    /// Registers the checks every instance has regardless of configuration
+    fn register_health_checks(&self) {
+        let tenants = self.tenants.clone();
+        self.health.register(
+            "storage",
+            &[Probe::Ready, Probe::Startup],
+            true,
+            move || {
+                let count = tenants.task_count();
+                async move { CheckOutcome::pass_with(format!("{} tasks", count)) }
+            },
+        );
+
+        let shutdown = self.shutdown.clone();
+        self.health
+            .register("shutdown", &[Probe::Ready], true, move || {
+                let draining = shutdown.is_draining();
+                async move {
+                    if draining {
+                        CheckOutcome::fail("Server is shutting down")
+                    } else {
+                        CheckOutcome::pass()
+                    }
+                }
+            });
     }
 }
 
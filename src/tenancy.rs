@@ -0,0 +1,517 @@
+use crate::{
+    attachments::Attachments,
+    audit::AuditContext,
+    comments::Comments,
+    config::{Config, RevisionsConfig},
+    dependencies::Dependents,
+    errors::AppError,
+    labels::Labels,
+    models::Task,
+    policy::Principal,
+    recurrence::Series,
+    revisions::Revisions,
+    search::SearchIndex,
+    state::AppState,
+    usage::TenantUsage,
+};
+use actix_web::{dev::Payload, web, FromRequest, HttpMessage, HttpRequest};
+use chrono::{DateTime, Utc};
+use dashmap::{mapref::entry::Entry, DashMap};
+use serde::{Deserialize, Serialize};
+use std::{
+    future::{ready, Ready},
+    ops::Deref,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+/// Tenant of the built-in `admin` principal; its admins manage all tenants
+pub const DEFAULT_TENANT: &str = "default";
+
+/// Counter of requests rejected by per-tenant rate limits and monthly quotas
+pub const RATE_LIMITED_METRIC: &str = "tenant_rate_limited_total";
+
+/// Length of the window `max_requests_per_minute` is counted over
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Tasks belonging to a single tenant
+pub type TaskStore = Arc<DashMap<Uuid, Task>>;
+
+/// Whether a tenant's principals may use the API
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TenantStatus {
+    Active,
+    /// Data is kept but every request is rejected with 403
+    Suspended,
+}
+
+/// Limits applied to one tenant; `None` means unlimited
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TenantQuota {
+    #[serde(default)]
+    pub max_tasks: Option<usize>,
+    #[serde(default)]
+    pub max_requests_per_minute: Option<u32>,
+    /// Total title and description bytes across the tenant's tasks
+    #[serde(default)]
+    pub max_stored_bytes: Option<u64>,
+    #[serde(default)]
+    pub max_requests_per_month: Option<u64>,
+}
+
+/// Snapshot of a tenant as returned by the admin endpoints
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tenant {
+    pub id: String,
+    pub status: TenantStatus,
+    pub quota: TenantQuota,
+    pub task_count: usize,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Request DTO for creating a tenant
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateTenantRequest {
+    pub id: String,
+    #[serde(default)]
+    pub quota: TenantQuota,
+}
+
+/// A tenant's partition of the store
+pub struct TenantEntry {
+    pub id: String,
+    pub tasks: TaskStore,
+    /// Deleted tasks awaiting restore or purge; they still count towards
+    /// `max_stored_bytes`
+    pub trash: TaskStore,
+    pub usage: TenantUsage,
+    pub revisions: Revisions,
+    /// Recurring series, by id
+    pub series: DashMap<Uuid, Series>,
+    /// Serialises parent changes so concurrent moves cannot form a cycle
+    pub hierarchy: Mutex<()>,
+    /// Serialises dependency changes so concurrent edges cannot form a cycle
+    pub dependencies: Mutex<()>,
+    /// Label catalogue and the index of live tasks by label
+    pub labels: Labels,
+    /// Index of live tasks by the tasks they wait on
+    pub dependents: Dependents,
+    /// Full-text index of live tasks
+    pub search: SearchIndex,
+    /// Comment threads, kept while their task is in the trash
+    pub comments: Comments,
+    /// Files attached to tasks; their content is in the shared blob store
+    pub attachments: Attachments,
+    created_at: DateTime<Utc>,
+    /// Task slots reserved by creates that have not stored their task yet
+    reserved_tasks: AtomicUsize,
+    settings: RwLock<(TenantStatus, TenantQuota)>,
+    window: Mutex<(Instant, u32)>,
+}
+
+impl TenantEntry {
+    fn new(id: &str, quota: TenantQuota, retention: Arc<RwLock<RevisionsConfig>>) -> Self {
+        Self {
+            id: id.to_string(),
+            tasks: Arc::new(DashMap::new()),
+            trash: Arc::new(DashMap::new()),
+            usage: TenantUsage::default(),
+            revisions: Revisions::new(retention),
+            series: DashMap::new(),
+            hierarchy: Mutex::new(()),
+            dependencies: Mutex::new(()),
+            labels: Labels::default(),
+            dependents: Dependents::default(),
+            search: SearchIndex::default(),
+            comments: Comments::default(),
+            attachments: Attachments::default(),
+            created_at: Utc::now(),
+            reserved_tasks: AtomicUsize::new(0),
+            settings: RwLock::new((TenantStatus::Active, quota)),
+            window: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    pub fn status(&self) -> TenantStatus {
+        self.settings.read().unwrap().0
+    }
+
+    pub fn quota(&self) -> TenantQuota {
+        self.settings.read().unwrap().1.clone()
+    }
+
+    /// Keeps the label, dependency and search indexes in step with a live
+    /// task changing from `before` to `after`, where `None` means not in the
+    /// live store
+    pub fn reindex(&self, id: Uuid, before: Option<&Task>, after: Option<&Task>) {
+        fn labels(task: Option<&Task>) -> &[String] {
+            task.map_or(&[], |task| &task.labels)
+        }
+        fn blocked_by(task: Option<&Task>) -> &[Uuid] {
+            task.map_or(&[], |task| &task.blocked_by)
+        }
+        self.labels.reindex(id, labels(before), labels(after));
+        self.dependents
+            .reindex(id, blocked_by(before), blocked_by(after));
+        self.search.reindex(id, after);
+    }
+
+    /// Rebuilds the label, dependency and search indexes from the live store
+    pub fn rebuild_indexes(&self) {
+        self.labels.rebuild(&self.tasks);
+        self.dependents.rebuild(&self.tasks);
+        self.search.rebuild(&self.tasks);
+    }
+
+    /// Reserves room for one more live task, failing once the tenant holds
+    /// `max_tasks` tasks.
+    ///
+    /// The slot counts towards the quota until the returned guard is dropped,
+    /// which the caller does once the task is stored or has failed, so
+    /// concurrent creates cannot both take the last slot. Counting reads the
+    /// whole store, so no entry lock may be held.
+    pub fn reserve_task_slot(&self) -> Result<TaskSlot<'_>, AppError> {
+        let max = self.quota().max_tasks;
+        let mut used = 0;
+        self.reserved_tasks
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |reserved| match max {
+                Some(max) => {
+                    used = self.tasks.len() + reserved;
+                    (used < max).then_some(reserved + 1)
+                }
+                None => Some(reserved + 1),
+            })
+            .map_err(|_| AppError::QuotaExceeded {
+                quota: "max_tasks",
+                limit: max.unwrap_or_default() as u64,
+                used: used as u64,
+            })?;
+        Ok(TaskSlot { tenant: self })
+    }
+
+    /// Accounts a task's stored size changing from `before` to `after`
+    /// bytes, refusing growth beyond `max_stored_bytes`
+    pub fn resize(&self, before: u64, after: u64) -> Result<(), AppError> {
+        self.usage.resize(before, after, &self.quota())
+    }
+
+    /// Replaces `task` with `updated` along with its storage accounting, audit
+    /// record and revision; the caller holds the task's entry lock
+    pub fn commit_update(
+        &self,
+        audit: &AuditContext,
+        operation: &str,
+        task: &mut Task,
+        updated: Task,
+    ) -> Result<(), AppError> {
+        self.resize(task.stored_bytes(), updated.stored_bytes())?;
+        if let Err(e) = audit.record(operation, task.id, Some(&*task), Some(&updated)) {
+            self.resize(updated.stored_bytes(), task.stored_bytes())?;
+            return Err(e);
+        }
+        self.revisions.record(&updated, &audit.principal.id);
+        self.reindex(task.id, Some(&*task), Some(&updated));
+        *task = updated;
+        Ok(())
+    }
+
+    pub fn snapshot(&self) -> Tenant {
+        self.snapshot_with(&self.settings.read().unwrap())
+    }
+
+    fn snapshot_with(&self, (status, quota): &(TenantStatus, TenantQuota)) -> Tenant {
+        let (status, quota) = (*status, quota.clone());
+        Tenant {
+            id: self.id.clone(),
+            status,
+            quota,
+            task_count: self.tasks.len(),
+            created_at: self.created_at,
+        }
+    }
+
+    /// Counts a request against the fixed one-minute rate window
+    fn try_acquire(&self, limit: Option<u32>) -> Result<(), AppError> {
+        let Some(limit) = limit else {
+            return Ok(());
+        };
+
+        let mut window = self.window.lock().unwrap();
+        let elapsed = window.0.elapsed();
+        if elapsed >= RATE_WINDOW {
+            *window = (Instant::now(), 0);
+        } else if window.1 >= limit {
+            return Err(AppError::TooManyRequests {
+                message: format!(
+                    "Tenant '{}' exceeded {} requests per minute",
+                    self.id, limit
+                ),
+                retry_after_secs: (RATE_WINDOW - elapsed).as_secs().max(1),
+            });
+        }
+        window.1 += 1;
+        Ok(())
+    }
+}
+
+/// A task slot reserved with `TenantEntry::reserve_task_slot`, released on
+/// drop
+pub struct TaskSlot<'a> {
+    tenant: &'a TenantEntry,
+}
+
+impl Drop for TaskSlot<'_> {
+    fn drop(&mut self) {
+        self.tenant.reserved_tasks.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Registry of tenants and their task partitions
+#[derive(Clone, Default)]
+pub struct Tenants {
+    inner: Arc<DashMap<String, Arc<TenantEntry>>>,
+    revision_retention: Arc<RwLock<RevisionsConfig>>,
+}
+
+impl Tenants {
+    /// Creates a registry holding only the default tenant
+    pub fn new() -> Self {
+        let tenants = Self::default();
+        tenants.ensure(DEFAULT_TENANT);
+        tenants
+    }
+
+    /// Creates a tenant, failing if the id is invalid or taken.
+    ///
+    /// `audit` is called with the new tenant before it becomes visible;
+    /// an error from it cancels the creation. The same holds for the other
+    /// administrative changes below.
+    pub fn create(
+        &self,
+        id: &str,
+        quota: TenantQuota,
+        audit: impl FnOnce(&Tenant) -> Result<(), AppError>,
+    ) -> Result<Tenant, AppError> {
+        validate_tenant_id(id).map_err(AppError::Validation)?;
+        match self.inner.entry(id.to_string()) {
+            Entry::Occupied(_) => Err(AppError::Conflict(format!(
+                "Tenant '{}' already exists",
+                id
+            ))),
+            Entry::Vacant(entry) => {
+                let retention = Arc::clone(&self.revision_retention);
+                let tenant = Arc::new(TenantEntry::new(id, quota, retention));
+                let snapshot = tenant.snapshot();
+                audit(&snapshot)?;
+                entry.insert(tenant);
+                Ok(snapshot)
+            }
+        }
+    }
+
+    /// Creates the tenant with no quota unless it already exists
+    pub fn ensure(&self, id: &str) -> Arc<TenantEntry> {
+        self.inner
+            .entry(id.to_string())
+            .or_insert_with(|| {
+                let retention = Arc::clone(&self.revision_retention);
+                Arc::new(TenantEntry::new(id, TenantQuota::default(), retention))
+            })
+            .clone()
+    }
+
+    /// Sets how much revision history every tenant keeps
+    pub fn set_revision_retention(&self, retention: RevisionsConfig) {
+        *self.revision_retention.write().unwrap() = retention;
+    }
+
+    /// Provisions the tenants of every configured principal
+    pub fn provision(&self, config: &Config) {
+        for principal in config.api.principals.values() {
+            self.ensure(&principal.tenant);
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<TenantEntry>> {
+        self.inner.get(id).map(|entry| Arc::clone(entry.value()))
+    }
+
+    /// Every tenant, in id order
+    pub fn entries(&self) -> Vec<Arc<TenantEntry>> {
+        let mut entries: Vec<_> = self
+            .inner
+            .iter()
+            .map(|entry| Arc::clone(entry.value()))
+            .collect();
+        entries.sort_by(|a, b| a.id.cmp(&b.id));
+        entries
+    }
+
+    pub fn list(&self) -> Vec<Tenant> {
+        let mut tenants: Vec<Tenant> = self.inner.iter().map(|entry| entry.snapshot()).collect();
+        tenants.sort_by(|a, b| a.id.cmp(&b.id));
+        tenants
+    }
+
+    pub fn set_status(
+        &self,
+        id: &str,
+        status: TenantStatus,
+        audit: impl FnOnce(&Tenant, &Tenant) -> Result<(), AppError>,
+    ) -> Result<Tenant, AppError> {
+        if id == DEFAULT_TENANT && status == TenantStatus::Suspended {
+            return Err(AppError::Forbidden(
+                "The default tenant cannot be suspended".to_string(),
+            ));
+        }
+        self.update_settings(id, |settings| settings.0 = status, audit)
+    }
+
+    pub fn set_quota(
+        &self,
+        id: &str,
+        quota: TenantQuota,
+        audit: impl FnOnce(&Tenant, &Tenant) -> Result<(), AppError>,
+    ) -> Result<Tenant, AppError> {
+        self.update_settings(id, |settings| settings.1 = quota, audit)
+    }
+
+    fn update_settings(
+        &self,
+        id: &str,
+        change: impl FnOnce(&mut (TenantStatus, TenantQuota)),
+        audit: impl FnOnce(&Tenant, &Tenant) -> Result<(), AppError>,
+    ) -> Result<Tenant, AppError> {
+        let tenant = self.get(id).ok_or_else(|| tenant_not_found(id))?;
+        let mut settings = tenant.settings.write().unwrap();
+        let mut changed = settings.clone();
+        change(&mut changed);
+
+        let after = tenant.snapshot_with(&changed);
+        audit(&tenant.snapshot_with(&settings), &after)?;
+        *settings = changed;
+        Ok(after)
+    }
+
+    /// Removes a tenant together with all of its tasks
+    pub fn delete(
+        &self,
+        id: &str,
+        audit: impl FnOnce(&Tenant) -> Result<(), AppError>,
+    ) -> Result<(), AppError> {
+        if id == DEFAULT_TENANT {
+            return Err(AppError::Forbidden(
+                "The default tenant cannot be deleted".to_string(),
+            ));
+        }
+        let mut outcome = Err(tenant_not_found(id));
+        self.inner.remove_if(id, |_, tenant| {
+            outcome = audit(&tenant.snapshot());
+            outcome.is_ok()
+        });
+        outcome
+    }
+
+    /// Rebuilds the label and search indexes of every tenant from storage
+    pub fn rebuild_indexes(&self) {
+        for tenant in self.entries() {
+            tenant.rebuild_indexes();
+        }
+    }
+
+    /// Number of tasks across all tenants
+    pub fn task_count(&self) -> usize {
+        self.inner.iter().map(|entry| entry.tasks.len()).sum()
+    }
+
+    /// Checks that the principal's tenant exists, is active and within its
+    /// request quotas, and counts the request towards its usage
+    pub fn admit(&self, principal: &Principal) -> Result<Arc<TenantEntry>, AppError> {
+        let id = &principal.tenant;
+        let tenant = self
+            .get(id)
+            .ok_or_else(|| AppError::Forbidden(format!("Tenant '{}' does not exist", id)))?;
+        let (status, quota) = tenant.settings.read().unwrap().clone();
+        if status == TenantStatus::Suspended {
+            return Err(AppError::Forbidden(format!("Tenant '{}' is suspended", id)));
+        }
+        tenant.try_acquire(quota.max_requests_per_minute)?;
+        tenant.usage.record_request(&principal.id, &quota)?;
+        Ok(tenant)
+    }
+}
+
+fn tenant_not_found(id: &str) -> AppError {
+    AppError::NotFound(format!("Tenant '{}' not found", id))
+}
+
+/// Tenant ids are 1-64 lowercase letters, digits, `-` or `_`
+pub fn validate_tenant_id(id: &str) -> Result<(), String> {
+    let valid = !id.is_empty()
+        && id.len() <= 64
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "Tenant id '{}' must be 1-64 lowercase letters, digits, '-' or '_'",
+            id
+        ))
+    }
+}
+
+/// The caller's tenant, admitted for this request
+///
+/// Task handlers only ever reach tasks through this extractor, so every
+/// lookup and mutation is confined to the caller's partition. Extracting it
+/// counts the request against the tenant's rate limit.
+pub struct CurrentTenant(Arc<TenantEntry>);
+
+impl CurrentTenant {
+    pub fn entry(&self) -> Arc<TenantEntry> {
+        Arc::clone(&self.0)
+    }
+}
+
+impl Deref for CurrentTenant {
+    type Target = TenantEntry;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl FromRequest for CurrentTenant {
+    type Error = AppError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let Some(principal) = req.extensions().get::<Principal>().cloned() else {
+            return ready(Err(AppError::Unauthorized(
+                "Authentication required".to_string(),
+            )));
+        };
+        let Some(state) = req.app_data::<web::Data<AppState>>() else {
+            return ready(Err(AppError::Internal(
+                "Application state is not configured".to_string(),
+            )));
+        };
+
+        let admitted = state.tenants.admit(&principal);
+        if let Err(AppError::TooManyRequests { .. }) = admitted {
+            state.metrics.increment(
+                RATE_LIMITED_METRIC,
+                "Requests rejected by per-tenant rate limits and monthly quotas",
+                &[("tenant", principal.tenant.as_str())],
+            );
+        }
+        ready(admitted.map(CurrentTenant))
+    }
+}
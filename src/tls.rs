@@ -0,0 +1,241 @@
+use crate::{config::TlsConfig, errors::AppError, health::Heartbeat};
+use actix_tls::accept::rustls_0_23::TlsStream;
+use actix_web::{dev::Extensions, rt::net::TcpStream};
+use rustls::{
+    crypto::ring::sign::any_supported_type,
+    pki_types::{CertificateDer, PrivateKeyDer},
+    server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier},
+    sign::CertifiedKey,
+    RootCertStore, ServerConfig,
+};
+use std::{
+    any::Any,
+    fmt, fs,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// Subject of the verified client certificate on a mutual TLS connection
+///
+/// Inserted into connection data by [`on_connect`] and copied into request
+/// extensions by `ApiKeyAuth`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCertificate {
+    pub subject: String,
+}
+
+/// Certificate resolver that can swap in a renewed certificate at runtime
+pub struct ReloadingCertResolver {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+    modified: RwLock<(Option<SystemTime>, Option<SystemTime>)>,
+}
+
+impl ReloadingCertResolver {
+    /// Loads the certificate chain and key, failing if either is unusable
+    pub fn new(
+        cert_path: impl Into<PathBuf>,
+        key_path: impl Into<PathBuf>,
+    ) -> Result<Self, AppError> {
+        let cert_path = cert_path.into();
+        let key_path = key_path.into();
+        let key = load_certified_key(&cert_path, &key_path)?;
+        let modified = (modified_time(&cert_path), modified_time(&key_path));
+
+        Ok(Self {
+            cert_path,
+            key_path,
+            current: RwLock::new(Arc::new(key)),
+            modified: RwLock::new(modified),
+        })
+    }
+
+    /// Returns the certificate currently served to new connections
+    pub fn current(&self) -> Arc<CertifiedKey> {
+        Arc::clone(&self.current.read().unwrap())
+    }
+
+    /// Reloads the certificate if either file changed since the last load.
+    ///
+    /// Returns `Ok(true)` when a new certificate was installed. On error the
+    /// previous certificate stays in place.
+    pub fn reload_if_changed(&self) -> Result<bool, AppError> {
+        let modified = (
+            modified_time(&self.cert_path),
+            modified_time(&self.key_path),
+        );
+        if *self.modified.read().unwrap() == modified {
+            return Ok(false);
+        }
+
+        let key = load_certified_key(&self.cert_path, &self.key_path)?;
+        *self.current.write().unwrap() = Arc::new(key);
+        *self.modified.write().unwrap() = modified;
+        Ok(true)
+    }
+}
+
+impl ResolvesServerCert for ReloadingCertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current())
+    }
+}
+
+impl fmt::Debug for ReloadingCertResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReloadingCertResolver")
+            .field("cert_path", &self.cert_path)
+            .field("key_path", &self.key_path)
+            .finish()
+    }
+}
+
+/// Builds the rustls server configuration described by `TlsConfig`
+pub fn server_config(
+    config: &TlsConfig,
+) -> Result<(ServerConfig, Arc<ReloadingCertResolver>), AppError> {
+    let versions: &[&rustls::SupportedProtocolVersion] = match config.min_version.as_str() {
+        "1.2" => &[&rustls::version::TLS13, &rustls::version::TLS12],
+        "1.3" => &[&rustls::version::TLS13],
+        other => {
+            return Err(AppError::Tls(format!(
+                "Unsupported minimum TLS version '{}', expected 1.2 or 1.3",
+                other
+            )))
+        }
+    };
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+        .with_protocol_versions(versions)
+        .map_err(|e| AppError::Tls(e.to_string()))?;
+
+    let builder = match &config.client_ca_path {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(Path::new(path))? {
+                roots
+                    .add(cert)
+                    .map_err(|e| AppError::Tls(format!("Invalid client CA in {}: {}", path, e)))?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+            let verifier = if config.client_auth_required {
+                verifier.build()
+            } else {
+                verifier.allow_unauthenticated().build()
+            }
+            .map_err(|e| AppError::Tls(e.to_string()))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let resolver = Arc::new(ReloadingCertResolver::new(
+        &config.cert_path,
+        &config.key_path,
+    )?);
+    let server_config = builder.with_cert_resolver(resolver.clone());
+
+    Ok((server_config, resolver))
+}
+
+/// Polls the certificate files and reloads them when they change.
+///
+/// Polling rather than filesystem notifications keeps this working with
+/// Kubernetes secret mounts, which update files by swapping symlinks.
+pub async fn watch_certificates(
+    resolver: Arc<ReloadingCertResolver>,
+    interval: Duration,
+    heartbeat: Heartbeat,
+    shutdown: CancellationToken,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = shutdown.cancelled() => return,
+        }
+        heartbeat.beat();
+        match resolver.reload_if_changed() {
+            Ok(true) => info!(cert = %resolver.cert_path.display(), "Reloaded TLS certificate"),
+            Ok(false) => {}
+            Err(e) => warn!(
+                cert = %resolver.cert_path.display(),
+                error = %e,
+                "Failed to reload TLS certificate, keeping the previous one"
+            ),
+        }
+    }
+}
+
+/// `HttpServer::on_connect` hook recording the client certificate subject
+pub fn on_connect(conn: &dyn Any, data: &mut Extensions) {
+    let Some(stream) = conn.downcast_ref::<TlsStream<TcpStream>>() else {
+        return;
+    };
+
+    let (_, session) = stream.get_ref();
+    if let Some(subject) = session
+        .peer_certificates()
+        .and_then(|chain| chain.first())
+        .and_then(|cert| certificate_subject(cert))
+    {
+        data.insert(ClientCertificate { subject });
+    }
+}
+
+/// Formats the subject distinguished name of a DER-encoded certificate
+pub fn certificate_subject(der: &[u8]) -> Option<String> {
+    x509_parser::parse_x509_certificate(der)
+        .ok()
+        .map(|(_, cert)| cert.subject().to_string())
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, AppError> {
+    let file = fs::File::open(path)
+        .map_err(|e| AppError::Tls(format!("Cannot open {}: {}", path.display(), e)))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| AppError::Tls(format!("Cannot parse {}: {}", path.display(), e)))?;
+
+    if certs.is_empty() {
+        return Err(AppError::Tls(format!(
+            "No certificates found in {}",
+            path.display()
+        )));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>, AppError> {
+    let file = fs::File::open(path)
+        .map_err(|e| AppError::Tls(format!("Cannot open {}: {}", path.display(), e)))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|e| AppError::Tls(format!("Cannot parse {}: {}", path.display(), e)))?
+        .ok_or_else(|| AppError::Tls(format!("No private key found in {}", path.display())))
+}
+
+fn load_certified_key(cert_path: &Path, key_path: &Path) -> Result<CertifiedKey, AppError> {
+    let certs = load_certs(cert_path)?;
+    let key = load_key(key_path)?;
+    let signing_key = any_supported_type(&key)
+        .map_err(|e| AppError::Tls(format!("Unsupported private key: {}", e)))?;
+
+    let certified = CertifiedKey::new(certs, signing_key);
+    certified
+        .keys_match()
+        .map_err(|e| AppError::Tls(format!("Certificate and key do not match: {}", e)))?;
+
+    Ok(certified)
+}
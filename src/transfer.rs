@@ -0,0 +1,386 @@
+use crate::{
+    audit::AuditContext,
+    dependencies,
+    errors::AppError,
+    hierarchy, labels,
+    models::Task,
+    tenancy::{TaskStore, TenantEntry},
+};
+use actix_web::web::{Bytes, BytesMut};
+use futures_util::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+use uuid::Uuid;
+
+/// Content type used for newline-delimited JSON streams
+pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// Number of tasks serialised into a single export chunk
+const EXPORT_BATCH_SIZE: usize = 256;
+
+/// Longest accepted import line; longer lines are rejected and skipped
+pub const MAX_IMPORT_LINE_BYTES: usize = 1024 * 1024;
+
+/// Maximum number of per-line errors echoed back in an import report
+const MAX_REPORTED_ERRORS: usize = 100;
+
+/// Streams the tasks selected by `include` as NDJSON, one chunk per batch.
+///
+/// Only the ids of included tasks and their links are snapshotted up front;
+/// each batch is looked up and serialised when the client pulls it, so memory
+/// stays bounded by the batch size rather than the size of the store. Tasks
+/// deleted while the export is running are skipped.
+///
+/// Parents and dependencies are written before the tasks that reference
+/// them, so the export can be imported again as it is.
+pub fn export_stream(
+    tasks: TaskStore,
+    include: impl Fn(&Task) -> bool,
+) -> impl Stream<Item = Result<Bytes, actix_web::Error>> {
+    let links: HashMap<Uuid, Vec<Uuid>> = tasks
+        .iter()
+        .filter(|entry| include(entry.value()))
+        .map(|entry| {
+            let references = entry
+                .parent_id
+                .iter()
+                .chain(&entry.blocked_by)
+                .copied()
+                .collect();
+            (*entry.key(), references)
+        })
+        .collect();
+    let ids = references_first(&links);
+
+    stream::unfold((tasks, ids, 0usize), |(tasks, ids, offset)| async move {
+        if offset >= ids.len() {
+            return None;
+        }
+
+        let end = (offset + EXPORT_BATCH_SIZE).min(ids.len());
+        let mut buf = Vec::new();
+        for id in &ids[offset..end] {
+            let Some(task) = tasks.get(id).map(|entry| entry.value().clone()) else {
+                continue;
+            };
+            if let Err(e) = serde_json::to_writer(&mut buf, &task) {
+                return Some((
+                    Err(actix_web::error::ErrorInternalServerError(e)),
+                    (tasks, ids, end),
+                ));
+            }
+            buf.push(b'\n');
+        }
+
+        Some((Ok(Bytes::from(buf)), (tasks, ids, end)))
+    })
+}
+
+/// Orders tasks so each comes after the included tasks it references
+fn references_first(links: &HashMap<Uuid, Vec<Uuid>>) -> Vec<Uuid> {
+    let mut roots: Vec<Uuid> = links.keys().copied().collect();
+    roots.sort();
+
+    let mut seen = HashSet::new();
+    let mut ordered = Vec::with_capacity(links.len());
+    for root in roots {
+        if !seen.insert(root) {
+            continue;
+        }
+        // Depth-first, emitting a task once all its references are out
+        let mut stack = vec![(root, 0)];
+        while let Some((id, next)) = stack.last().copied() {
+            match links[&id].get(next) {
+                Some(reference) => {
+                    stack.last_mut().expect("stack is not empty").1 += 1;
+                    if links.contains_key(reference) && seen.insert(*reference) {
+                        stack.push((*reference, 0));
+                    }
+                }
+                None => {
+                    ordered.push(id);
+                    stack.pop();
+                }
+            }
+        }
+    }
+    ordered
+}
+
+/// How imported tasks are reconciled with tasks that already exist
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    /// Replace existing tasks with the imported version
+    Upsert,
+    /// Keep existing tasks and ignore the imported version
+    #[default]
+    SkipExisting,
+    /// Stop the import at the first task whose id already exists
+    FailOnConflict,
+}
+
+/// Query parameters accepted by the import endpoint
+#[derive(Debug, Default, Deserialize)]
+pub struct ImportQuery {
+    #[serde(default)]
+    pub mode: ImportMode,
+}
+
+/// Error attached to a single import line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportLineError {
+    pub line: usize,
+    pub message: String,
+}
+
+/// Summary returned once an import has finished
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub mode: ImportMode,
+    pub lines: usize,
+    pub created: usize,
+    pub updated: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    /// True when a conflict stopped the import before the end of the stream
+    pub aborted: bool,
+    /// First errors encountered; `failed` holds the full count
+    pub errors: Vec<ImportLineError>,
+}
+
+/// Incremental NDJSON importer fed with raw body chunks
+///
+/// Lines that would take the tenant past its task or storage quota fail
+/// individually, like invalid lines. Every created or replaced task is
+/// audited before it is stored.
+pub struct Importer {
+    tenant: Arc<TenantEntry>,
+    audit: AuditContext,
+    report: ImportReport,
+    buffer: BytesMut,
+    /// Leading bytes of `buffer` already searched for a newline
+    scanned: usize,
+    /// Set while skipping the remainder of an over-long line
+    discarding: bool,
+}
+
+impl Importer {
+    pub fn new(tenant: Arc<TenantEntry>, audit: AuditContext, mode: ImportMode) -> Self {
+        Self {
+            tenant,
+            audit,
+            report: ImportReport {
+                mode,
+                ..Default::default()
+            },
+            buffer: BytesMut::new(),
+            scanned: 0,
+            discarding: false,
+        }
+    }
+
+    /// Whether the import has stopped and further input will be ignored
+    pub fn is_aborted(&self) -> bool {
+        self.report.aborted
+    }
+
+    /// Feeds a chunk of the request body, applying every complete line
+    pub fn feed(&mut self, chunk: &[u8]) {
+        self.buffer.extend_from_slice(chunk);
+
+        while !self.report.aborted {
+            let Some(pos) = self.buffer[self.scanned..]
+                .iter()
+                .position(|b| *b == b'\n')
+                .map(|pos| self.scanned + pos)
+            else {
+                self.scanned = self.buffer.len();
+                break;
+            };
+            let line = self.buffer.split_to(pos + 1);
+            self.scanned = 0;
+            if self.discarding {
+                self.discarding = false;
+                continue;
+            }
+            if pos > MAX_IMPORT_LINE_BYTES {
+                self.report.lines += 1;
+                self.fail(format!("Line exceeds {} bytes", MAX_IMPORT_LINE_BYTES));
+                continue;
+            }
+            self.apply_line(&line[..pos]);
+        }
+
+        if self.buffer.len() > MAX_IMPORT_LINE_BYTES {
+            if !self.discarding {
+                self.report.lines += 1;
+                self.fail(format!("Line exceeds {} bytes", MAX_IMPORT_LINE_BYTES));
+                self.discarding = true;
+            }
+            self.buffer.clear();
+            self.scanned = 0;
+        }
+    }
+
+    /// Applies any trailing line without a newline and returns the report
+    pub fn finish(mut self) -> ImportReport {
+        if !self.report.aborted && !self.discarding && !self.buffer.is_empty() {
+            let line = self.buffer.split();
+            self.apply_line(&line);
+        }
+        self.report
+    }
+
+    fn apply_line(&mut self, raw: &[u8]) {
+        self.report.lines += 1;
+
+        let raw = raw.strip_suffix(b"\r").unwrap_or(raw);
+        if raw.iter().all(u8::is_ascii_whitespace) {
+            return;
+        }
+
+        let mut task: Task = match serde_json::from_slice(raw) {
+            Ok(task) => task,
+            Err(e) => return self.fail(format!("Invalid task JSON: {}", e)),
+        };
+        // Imported tasks are always live, even if the line was trashed
+        task.deleted_at = None;
+
+        if task.title.trim().is_empty() {
+            return self.fail("Title cannot be empty".to_string());
+        }
+        task.labels = match labels::normalize_all(&task.labels) {
+            Ok(labels) => labels,
+            Err(e) => return self.fail(e.to_string()),
+        };
+
+        task.blocked_by.sort();
+        task.blocked_by.dedup();
+
+        // Links are checked under the locks moves and new dependencies take,
+        // held until the task is stored
+        let tenant = Arc::clone(&self.tenant);
+        let _hierarchy = tenant.hierarchy.lock().unwrap();
+        let _dependencies = tenant.dependencies.lock().unwrap();
+        if let Err(e) = check_links(&tenant, &task) {
+            return self.fail(e.to_string());
+        }
+
+        let id = task.id;
+        let conflict = match tenant.tasks.get_mut(&id) {
+            // Reserving a slot reads the whole store, so no entry lock may be held
+            None => {
+                let size = task.stored_bytes();
+                let admitted = tenant.reserve_task_slot().and_then(|slot| {
+                    tenant.resize(0, size)?;
+                    self.record("task.create", None, &task, 0)?;
+                    Ok(slot)
+                });
+                let _slot = match admitted {
+                    Ok(slot) => slot,
+                    Err(e) => return self.fail(e.to_string()),
+                };
+                self.report.created += 1;
+                tenant.revisions.record(&task, &self.audit.principal.id);
+                self.store(task);
+                false
+            }
+            Some(mut existing) => match self.report.mode {
+                ImportMode::Upsert => {
+                    let updated =
+                        tenant.commit_update(&self.audit, "task.update", &mut existing, task);
+                    drop(existing);
+                    if let Err(e) = updated {
+                        return self.fail(e.to_string());
+                    }
+                    self.report.updated += 1;
+                    false
+                }
+                ImportMode::SkipExisting => {
+                    self.report.skipped += 1;
+                    false
+                }
+                ImportMode::FailOnConflict => true,
+            },
+        };
+
+        if conflict {
+            self.fail(format!("Task with id {} already exists", id));
+            self.report.aborted = true;
+        }
+    }
+
+    /// Audits a change to `task`, returning the tenant's storage to `previous`
+    /// bytes for it when the record cannot be written
+    fn record(
+        &self,
+        operation: &str,
+        before: Option<&Task>,
+        task: &Task,
+        previous: u64,
+    ) -> Result<(), AppError> {
+        self.audit
+            .record(operation, task.id, before, Some(task))
+            .inspect_err(|_| {
+                let _ = self.tenant.resize(task.stored_bytes(), previous);
+            })
+    }
+
+    /// Inserts a task accounted as new, releasing any concurrent insert it replaces
+    fn store(&self, task: Task) {
+        let replaced = self.tenant.tasks.insert(task.id, task.clone());
+        self.tenant.reindex(task.id, replaced.as_ref(), Some(&task));
+        if let Some(replaced) = replaced {
+            let _ = self.tenant.resize(replaced.stored_bytes(), 0);
+        }
+    }
+
+    fn fail(&mut self, message: String) {
+        self.report.failed += 1;
+        if self.report.errors.len() < MAX_REPORTED_ERRORS {
+            self.report.errors.push(ImportLineError {
+                line: self.report.lines,
+                message,
+            });
+        }
+    }
+}
+
+/// Checks that an imported task's parent and dependencies exist and that
+/// linking to them leaves no cycle, as moving a task and adding a dependency
+/// do
+fn check_links(tenant: &TenantEntry, task: &Task) -> Result<(), AppError> {
+    if let Some(parent) = task.parent_id {
+        if !tenant.tasks.contains_key(&parent) {
+            return Err(AppError::NotFound(format!(
+                "Parent task {} not found",
+                parent
+            )));
+        }
+        if hierarchy::is_ancestor(&tenant.tasks, &task.id, &parent) {
+            return Err(AppError::Conflict(format!(
+                "Moving task {} under {} would create a cycle",
+                task.id, parent
+            )));
+        }
+    }
+    for dependency in &task.blocked_by {
+        if !tenant.tasks.contains_key(dependency) {
+            return Err(AppError::NotFound(format!(
+                "Dependency {} not found",
+                dependency
+            )));
+        }
+        if dependencies::depends_on(tenant, dependency, &task.id) {
+            return Err(AppError::Conflict(format!(
+                "Task {} waiting on {} would create a cycle",
+                task.id, dependency
+            )));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,206 @@
+use crate::{
+    audit::AuditLog,
+    errors::AppError,
+    health::Heartbeat,
+    hierarchy::SubtaskMode,
+    models::Task,
+    tenancy::{TenantEntry, Tenants},
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Query parameters of the task delete endpoint
+#[derive(Debug, Default, Deserialize)]
+pub struct DeleteQuery {
+    /// Remove the task permanently instead of moving it to the trash (admins)
+    #[serde(default)]
+    pub hard: bool,
+    /// What happens to the task's subtasks
+    #[serde(default)]
+    pub subtasks: SubtaskMode,
+}
+
+/// Moves a live task to the trash once `check` (authorisation and
+/// auditing of the trashed state) succeeds
+pub fn trash(
+    tenant: &TenantEntry,
+    id: &Uuid,
+    check: impl FnOnce(&Task, &Task) -> Result<(), AppError>,
+) -> Result<Option<Task>, AppError> {
+    let deleted_at = Some(Utc::now());
+    let mut failed = None;
+    let removed = tenant.tasks.remove_if(id, |_, task| {
+        let trashed = Task {
+            deleted_at,
+            ..task.clone()
+        };
+        failed = check(task, &trashed).err();
+        failed.is_none()
+    });
+    if let Some(e) = failed {
+        return Err(e);
+    }
+
+    Ok(removed.map(|(id, task)| {
+        tenant.reindex(id, Some(&task), None);
+        let trashed = Task { deleted_at, ..task };
+        tenant.trash.insert(id, trashed.clone());
+        trashed
+    }))
+}
+
+/// Moves a trashed task back to the live store once `check` succeeds,
+/// recording the restored task as a revision by `author`.
+///
+/// Fails with a conflict if a live task with the same id was imported
+/// while it was in the trash.
+pub fn restore(
+    tenant: &TenantEntry,
+    id: &Uuid,
+    author: &str,
+    check: impl FnOnce(&Task, &Task) -> Result<(), AppError>,
+) -> Result<Option<Task>, AppError> {
+    if !tenant.trash.contains_key(id) {
+        return Ok(None);
+    }
+    if tenant.tasks.contains_key(id) {
+        return Err(AppError::Conflict(format!(
+            "Task with id {} already exists",
+            id
+        )));
+    }
+    let _slot = tenant.reserve_task_slot()?;
+
+    let mut failed = None;
+    let mut restored = None;
+    tenant.trash.remove_if(id, |_, task| {
+        // A parent that is no longer live leaves the task at the top level
+        let live = Task {
+            deleted_at: None,
+            parent_id: task
+                .parent_id
+                .filter(|parent| tenant.tasks.contains_key(parent)),
+            ..task.clone()
+        };
+        failed = check(task, &live).err();
+        restored = Some(live);
+        failed.is_none()
+    });
+    if let Some(e) = failed {
+        return Err(e);
+    }
+
+    Ok(restored.inspect(|task| {
+        tenant.revisions.record(task, author);
+        tenant.reindex(task.id, None, Some(task));
+        tenant.tasks.insert(task.id, task.clone());
+    }))
+}
+
+/// Permanently removes a trashed task once `check` succeeds, releasing its
+/// storage and revision history
+pub fn purge(
+    tenant: &TenantEntry,
+    id: &Uuid,
+    check: impl FnOnce(&Task) -> Result<(), AppError>,
+) -> Result<Option<Task>, AppError> {
+    let mut failed = None;
+    let removed = tenant.trash.remove_if(id, |_, task| {
+        failed = check(task).err();
+        failed.is_none()
+    });
+    if let Some(e) = failed {
+        return Err(e);
+    }
+
+    let Some((id, task)) = removed else {
+        return Ok(None);
+    };
+    release(tenant, &id, &task)?;
+    Ok(Some(task))
+}
+
+/// Releases the storage and revision history of a permanently removed task.
+///
+/// Blobs of its attachments are left to garbage collection, as other
+/// attachments may share them.
+pub fn release(tenant: &TenantEntry, id: &Uuid, task: &Task) -> Result<(), AppError> {
+    tenant.revisions.remove(id);
+    let comments = tenant.comments.remove(id);
+    let attachments = tenant.attachments.remove(id);
+    tenant.resize(task.stored_bytes() + comments + attachments, 0)
+}
+
+/// Purges a tenant's tasks trashed before `cutoff`, returning how many
+pub fn purge_expired(tenant: &TenantEntry, cutoff: DateTime<Utc>, audit: &AuditLog) -> usize {
+    let expired = |task: &Task| task.deleted_at.is_some_and(|at| at < cutoff);
+    let ids: Vec<Uuid> = tenant
+        .trash
+        .iter()
+        .filter(|entry| expired(entry.value()))
+        .map(|entry| *entry.key())
+        .collect();
+
+    let mut purged = 0;
+    for id in ids {
+        // Checked again under the lock: the task may have been restored
+        // and trashed afresh since it was listed
+        let mut failed = None;
+        let removed = tenant.trash.remove_if(&id, |_, task| {
+            expired(task) && {
+                failed = audit
+                    .record_system(&tenant.id, "task.purge", id, Some(task), None)
+                    .err();
+                failed.is_none()
+            }
+        });
+        let result = match (failed, removed) {
+            (Some(e), _) => Err(e),
+            (None, Some((id, task))) => release(tenant, &id, &task).map(|_| purged += 1),
+            (None, None) => Ok(()),
+        };
+        if let Err(e) = result {
+            warn!(tenant = %tenant.id, task = %id, error = %e, "Failed to purge trashed task");
+        }
+    }
+    purged
+}
+
+/// Purges tasks older than the retention period from every tenant's
+/// trash, at startup and then every `interval`
+pub async fn purge_trash(
+    tenants: Tenants,
+    retention_days: u64,
+    interval: Duration,
+    audit: AuditLog,
+    heartbeat: Heartbeat,
+    shutdown: CancellationToken,
+) {
+    let mut ticker = tokio::time::interval(interval);
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = shutdown.cancelled() => return,
+        }
+        heartbeat.beat();
+        // A retention reaching before the earliest representable time purges nothing
+        let Some(cutoff) = i64::try_from(retention_days)
+            .ok()
+            .and_then(chrono::Duration::try_days)
+            .and_then(|retention| Utc::now().checked_sub_signed(retention))
+        else {
+            continue;
+        };
+        for tenant in tenants.entries() {
+            let purged = purge_expired(&tenant, cutoff, &audit);
+            if purged > 0 {
+                info!(tenant = %tenant.id, purged, "Purged expired tasks from the trash");
+            }
+        }
+    }
+}
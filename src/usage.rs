@@ -0,0 +1,278 @@
+use crate::{
+    errors::AppError,
+    health::Heartbeat,
+    tenancy::{TenantEntry, TenantQuota, Tenants},
+};
+use chrono::{Datelike, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+    time::Duration,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+/// Calendar month counters belong to, as `yyyymm`
+pub fn current_month() -> u32 {
+    let now = Utc::now();
+    now.year() as u32 * 100 + now.month()
+}
+
+fn month_label(month: u32) -> String {
+    format!("{:04}-{:02}", month / 100, month % 100)
+}
+
+/// Seconds until the first day of the next month, when monthly counters reset
+fn secs_until_next_month() -> u64 {
+    let now = Utc::now();
+    let (year, month) = if now.month() == 12 {
+        (now.year() + 1, 1)
+    } else {
+        (now.year(), now.month() + 1)
+    };
+    chrono::NaiveDate::from_ymd_opt(year, month, 1)
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|start| (start.and_utc() - now).num_seconds().max(1) as u64)
+        .unwrap_or(1)
+}
+
+/// Request counter that starts from zero every calendar month
+#[derive(Debug, Default)]
+pub struct MonthlyCounter {
+    month: AtomicU32,
+    count: AtomicU64,
+}
+
+impl MonthlyCounter {
+    /// Requests counted in `month`
+    pub fn get(&self, month: u32) -> u64 {
+        if self.month.load(Ordering::Relaxed) == month {
+            self.count.load(Ordering::Relaxed)
+        } else {
+            0
+        }
+    }
+
+    fn increment(&self, month: u32) {
+        if self.month.swap(month, Ordering::Relaxed) != month {
+            self.count.store(0, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn restore(&self, month: u32, count: u64) {
+        self.month.store(month, Ordering::Relaxed);
+        self.count.store(count, Ordering::Relaxed);
+    }
+}
+
+/// Running usage of one tenant
+///
+/// Updated with atomics on every request and write, so accounting stays off
+/// the lock path of the task store.
+#[derive(Debug, Default)]
+pub struct TenantUsage {
+    stored_bytes: AtomicU64,
+    requests: MonthlyCounter,
+    principals: DashMap<String, MonthlyCounter>,
+}
+
+impl TenantUsage {
+    pub fn stored_bytes(&self) -> u64 {
+        self.stored_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Counts a request by `principal`, unless the monthly quota is used up
+    pub fn record_request(&self, principal: &str, quota: &TenantQuota) -> Result<(), AppError> {
+        let month = current_month();
+        if let Some(max) = quota.max_requests_per_month {
+            let used = self.requests.get(month);
+            if used >= max {
+                return Err(AppError::TooManyRequests {
+                    message: format!("Monthly quota of {} requests exceeded", max),
+                    retry_after_secs: secs_until_next_month(),
+                });
+            }
+        }
+
+        self.requests.increment(month);
+        self.principals
+            .entry(principal.to_string())
+            .or_default()
+            .increment(month);
+        Ok(())
+    }
+
+    /// Accounts a task's content changing from `before` to `after` bytes.
+    ///
+    /// Growth is refused when it would take the tenant past
+    /// `max_stored_bytes`; shrinking always succeeds.
+    pub fn resize(&self, before: u64, after: u64, quota: &TenantQuota) -> Result<(), AppError> {
+        if after <= before {
+            self.stored_bytes
+                .fetch_sub(before - after, Ordering::Relaxed);
+            return Ok(());
+        }
+
+        let grow = after - before;
+        let Some(max) = quota.max_stored_bytes else {
+            self.stored_bytes.fetch_add(grow, Ordering::Relaxed);
+            return Ok(());
+        };
+        self.stored_bytes
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                (used + grow <= max).then_some(used + grow)
+            })
+            .map(|_| ())
+            .map_err(|used| AppError::QuotaExceeded {
+                quota: "max_stored_bytes",
+                limit: max,
+                used,
+            })
+    }
+}
+
+/// Usage of one tenant in the current month, as reported by the API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageReport {
+    pub tenant: String,
+    pub month: String,
+    pub tasks: usize,
+    pub stored_bytes: u64,
+    pub requests: u64,
+    /// Requests per principal (API key) this month
+    pub principals: BTreeMap<String, u64>,
+    pub quota: TenantQuota,
+}
+
+impl UsageReport {
+    pub fn for_tenant(tenant: &TenantEntry) -> Self {
+        let month = current_month();
+        Self {
+            tenant: tenant.id.clone(),
+            month: month_label(month),
+            tasks: tenant.tasks.len(),
+            stored_bytes: tenant.usage.stored_bytes(),
+            requests: tenant.usage.requests.get(month),
+            principals: tenant
+                .usage
+                .principals
+                .iter()
+                .map(|entry| (entry.key().clone(), entry.value().get(month)))
+                .filter(|(_, requests)| *requests > 0)
+                .collect(),
+            quota: tenant.quota(),
+        }
+    }
+}
+
+/// Request counters as saved to `usage.persist_path`
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PersistedUsage {
+    month: u32,
+    tenants: BTreeMap<String, PersistedTenant>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PersistedTenant {
+    requests: u64,
+    principals: BTreeMap<String, u64>,
+}
+
+/// Writes the current month's request counters to `path`
+pub fn save(tenants: &Tenants, path: &Path) -> io::Result<()> {
+    let month = current_month();
+    let persisted = PersistedUsage {
+        month,
+        tenants: tenants
+            .entries()
+            .into_iter()
+            .map(|tenant| {
+                let usage = &tenant.usage;
+                let principals = usage
+                    .principals
+                    .iter()
+                    .map(|entry| (entry.key().clone(), entry.value().get(month)))
+                    .collect();
+                let requests = usage.requests.get(month);
+                (
+                    tenant.id.clone(),
+                    PersistedTenant {
+                        requests,
+                        principals,
+                    },
+                )
+            })
+            .collect(),
+    };
+
+    // Write then rename so a crash never leaves a truncated file behind
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(&persisted)?)?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Restores request counters saved earlier in the current month.
+///
+/// Counters of tenants that no longer exist, or from a previous month, are
+/// dropped. A missing file is not an error.
+pub fn restore(tenants: &Tenants, path: &Path) -> io::Result<()> {
+    let raw = match std::fs::read(path) {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let persisted: PersistedUsage = serde_json::from_slice(&raw)?;
+    if persisted.month != current_month() {
+        return Ok(());
+    }
+
+    for (id, saved) in persisted.tenants {
+        let Some(tenant) = tenants.get(&id) else {
+            continue;
+        };
+        tenant
+            .usage
+            .requests
+            .restore(persisted.month, saved.requests);
+        for (principal, requests) in saved.principals {
+            tenant
+                .usage
+                .principals
+                .entry(principal)
+                .or_default()
+                .restore(persisted.month, requests);
+        }
+    }
+    Ok(())
+}
+
+/// Saves usage counters every `interval` and once more on shutdown
+pub async fn persist_usage(
+    tenants: Tenants,
+    path: PathBuf,
+    interval: Duration,
+    heartbeat: Heartbeat,
+    shutdown: CancellationToken,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+
+    loop {
+        let stopping = tokio::select! {
+            _ = ticker.tick() => false,
+            _ = shutdown.cancelled() => true,
+        };
+        heartbeat.beat();
+        if let Err(e) = save(&tenants, &path) {
+            warn!(path = %path.display(), error = %e, "Failed to save usage counters");
+        }
+        if stopping {
+            return;
+        }
+    }
+}
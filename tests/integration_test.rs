@@ -1,19 +1,61 @@
-use actix_web::{test, web, App};
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::{from_fn, Next},
+    test, web, App, HttpMessage, ResponseError,
+};
 use rust_high_performance_api_server::{
-    create_app,
-    models::{CreateTaskRequest, Task},
+    attachments::Attachment,
+    audit::{AuditLog, AuditQuery, AuditRecord},
+    comments::{Comment, CommentPage},
+    config::{
+        AuditConfig, CompressionConfig, Config, ConfigSources, IpAccessRule, PrincipalConfig,
+        Profile, RevisionsConfig, DEFAULT_API_KEY, MAX_RETENTION_DAYS,
+    },
+    dependencies::{Plan, TaskView},
+    health::{CheckOutcome, CheckStatus, HealthReport, Probe},
+    hierarchy::TaskNode,
+    labels::{Label, LabelSummary},
+    metrics::Metrics,
+    middleware::{
+        auth::ApiKeyAuth,
+        body_limit::BodyLimit,
+        compression::ResponseCompression,
+        cors::Cors,
+        ip_filter::{IpFilter, DENIED_METRIC},
+        logging::RequestLogging,
+        security_headers::SecurityHeaders,
+    },
+    models::{CreateTaskRequest, Priority, Task},
+    network,
+    policy::{self, Action, Principal, Role},
+    recurrence::{RRule, SeriesView},
+    reload::{
+        ConfigHandle, LAST_RELOAD_FAILURE_METRIC, LAST_RELOAD_SUCCESS_METRIC,
+        RELOAD_FAILURES_METRIC,
+    },
+    reminders,
+    revisions::{Revision, RevisionDiff, RevisionSummary},
+    routes::{configure_routes, json_config},
+    search::{self, SearchResults},
+    secrets::{self, Secret},
     state::AppState,
+    tenancy::{Tenant, TenantQuota, DEFAULT_TENANT},
+    tls::{self, ReloadingCertResolver},
+    transfer::ImportReport,
+    trash,
+    usage::{self, UsageReport},
 };
 use serde_json::json;
+use std::io::{Read, Write};
 
 #[actix_web::test]
 async fn test_health_check() {
     let app_state = AppState::new();
-    let app = test::init_service(
-        App::new()
-            .app_data(web::Data::new(app_state))
-            .route("/health", web::get().to(rust_high_performance_api_server::handlers::health_check)),
-    )
+    let app = test::init_service(App::new().app_data(web::Data::new(app_state)).route(
+        "/health",
+        web::get().to(rust_high_performance_api_server::handlers::health_check),
+    ))
     .await;
 
     let req = test::TestRequest::get().uri("/health").to_request();
@@ -22,19 +64,34 @@ async fn test_health_check() {
     assert!(resp.status().is_success());
 }
 
+/// Stands in for `ApiKeyAuth` in tests that call task handlers directly
+async fn as_admin(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    req.extensions_mut()
+        .insert(Principal::new("admin", Role::Admin));
+    next.call(req).await
+}
+
 #[actix_web::test]
 async fn test_create_task() {
     let app_state = AppState::new();
     let app = test::init_service(
         App::new()
+            .wrap(from_fn(as_admin))
             .app_data(web::Data::new(app_state.clone()))
-            .route("/api/v1/tasks", web::post().to(rust_high_performance_api_server::handlers::create_task)),
+            .route(
+                "/api/v1/tasks",
+                web::post().to(rust_high_performance_api_server::handlers::create_task),
+            ),
     )
     .await;
 
     let create_req = CreateTaskRequest {
         title: "Test Task".to_string(),
         description: Some("Test Description".to_string()),
+        ..Default::default()
     };
 
     let req = test::TestRequest::post()
@@ -54,16 +111,20 @@ async fn test_create_task() {
 #[actix_web::test]
 async fn test_get_tasks() {
     let app_state = AppState::new();
-    
+
     // Create a test task
     let task = Task::new("Test Task".to_string(), None);
     let task_id = task.id;
-    app_state.tasks.insert(task_id, task);
+    app_state.default_tasks().insert(task_id, task);
 
     let app = test::init_service(
         App::new()
+            .wrap(from_fn(as_admin))
             .app_data(web::Data::new(app_state))
-            .route("/api/v1/tasks", web::get().to(rust_high_performance_api_server::handlers::get_tasks)),
+            .route(
+                "/api/v1/tasks",
+                web::get().to(rust_high_performance_api_server::handlers::get_tasks),
+            ),
     )
     .await;
 
@@ -79,15 +140,19 @@ async fn test_get_tasks() {
 #[actix_web::test]
 async fn test_get_task_by_id() {
     let app_state = AppState::new();
-    
+
     let task = Task::new("Test Task".to_string(), None);
     let task_id = task.id;
-    app_state.tasks.insert(task_id, task);
+    app_state.default_tasks().insert(task_id, task);
 
     let app = test::init_service(
         App::new()
+            .wrap(from_fn(as_admin))
             .app_data(web::Data::new(app_state))
-            .route("/api/v1/tasks/{id}", web::get().to(rust_high_performance_api_server::handlers::get_task)),
+            .route(
+                "/api/v1/tasks/{id}",
+                web::get().to(rust_high_performance_api_server::handlers::get_task),
+            ),
     )
     .await;
 
@@ -104,15 +169,19 @@ async fn test_get_task_by_id() {
 #[actix_web::test]
 async fn test_update_task() {
     let app_state = AppState::new();
-    
+
     let task = Task::new("Original Title".to_string(), None);
     let task_id = task.id;
-    app_state.tasks.insert(task_id, task);
+    app_state.default_tasks().insert(task_id, task);
 
     let app = test::init_service(
         App::new()
+            .wrap(from_fn(as_admin))
             .app_data(web::Data::new(app_state))
-            .route("/api/v1/tasks/{id}", web::put().to(rust_high_performance_api_server::handlers::update_task)),
+            .route(
+                "/api/v1/tasks/{id}",
+                web::put().to(rust_high_performance_api_server::handlers::update_task),
+            ),
     )
     .await;
 
@@ -137,15 +206,19 @@ async fn test_update_task() {
 #[actix_web::test]
 async fn test_delete_task() {
     let app_state = AppState::new();
-    
+
     let task = Task::new("Test Task".to_string(), None);
     let task_id = task.id;
-    app_state.tasks.insert(task_id, task);
+    app_state.default_tasks().insert(task_id, task);
 
     let app = test::init_service(
         App::new()
+            .wrap(from_fn(as_admin))
             .app_data(web::Data::new(app_state))
-            .route("/api/v1/tasks/{id}", web::delete().to(rust_high_performance_api_server::handlers::delete_task)),
+            .route(
+                "/api/v1/tasks/{id}",
+                web::delete().to(rust_high_performance_api_server::handlers::delete_task),
+            ),
     )
     .await;
 
@@ -162,14 +235,19 @@ async fn test_create_task_validation() {
     let app_state = AppState::new();
     let app = test::init_service(
         App::new()
+            .wrap(from_fn(as_admin))
             .app_data(web::Data::new(app_state))
-            .route("/api/v1/tasks", web::post().to(rust_high_performance_api_server::handlers::create_task)),
+            .route(
+                "/api/v1/tasks",
+                web::post().to(rust_high_performance_api_server::handlers::create_task),
+            ),
     )
     .await;
 
     let create_req = CreateTaskRequest {
         title: "   ".to_string(), // Empty after trim
         description: None,
+        ..Default::default()
     };
 
     let req = test::TestRequest::post()
@@ -180,3 +258,3572 @@ async fn test_create_task_validation() {
     let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), 400);
 }
+
+#[actix_web::test]
+async fn test_export_tasks_ndjson() {
+    let app_state = AppState::new();
+    for i in 0..300 {
+        let task = Task::new(format!("Task {}", i), None);
+        app_state.default_tasks().insert(task.id, task);
+    }
+
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(as_admin))
+            .app_data(web::Data::new(app_state))
+            .route(
+                "/api/v1/tasks/export",
+                web::get().to(rust_high_performance_api_server::handlers::export_tasks),
+            ),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/tasks/export")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert!(resp.status().is_success());
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "application/x-ndjson"
+    );
+
+    let body = test::read_body(resp).await;
+    let tasks: Vec<Task> = body
+        .split(|b| *b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_slice(line).unwrap())
+        .collect();
+    assert_eq!(tasks.len(), 300);
+}
+
+#[actix_web::test]
+async fn test_import_tasks_modes() {
+    let app_state = AppState::new();
+
+    let existing = Task::new("Existing".to_string(), None);
+    let existing_id = existing.id;
+    app_state
+        .default_tasks()
+        .insert(existing_id, existing.clone());
+
+    let mut renamed = existing.clone();
+    renamed.title = "Renamed".to_string();
+    let fresh = Task::new("Fresh".to_string(), None);
+
+    let body = format!(
+        "{}\n{}\nnot json\n",
+        serde_json::to_string(&renamed).unwrap(),
+        serde_json::to_string(&fresh).unwrap(),
+    );
+
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(as_admin))
+            .app_data(web::Data::new(app_state.clone()))
+            .route(
+                "/api/v1/tasks/import",
+                web::post().to(rust_high_performance_api_server::handlers::import_tasks),
+            ),
+    )
+    .await;
+
+    // Default mode keeps the existing task
+    let req = test::TestRequest::post()
+        .uri("/api/v1/tasks/import")
+        .set_payload(body.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let report: ImportReport = test::read_body_json(resp).await;
+    assert_eq!(report.lines, 3);
+    assert_eq!(report.created, 1);
+    assert_eq!(report.skipped, 1);
+    assert_eq!(report.failed, 1);
+    assert_eq!(report.errors[0].line, 3);
+    assert_eq!(
+        app_state.default_tasks().get(&existing_id).unwrap().title,
+        "Existing"
+    );
+
+    // Upsert overwrites it
+    let req = test::TestRequest::post()
+        .uri("/api/v1/tasks/import?mode=upsert")
+        .set_payload(body.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let report: ImportReport = test::read_body_json(resp).await;
+    assert_eq!(report.updated, 2);
+    assert_eq!(
+        app_state.default_tasks().get(&existing_id).unwrap().title,
+        "Renamed"
+    );
+
+    // Fail-on-conflict stops at the first existing id
+    let req = test::TestRequest::post()
+        .uri("/api/v1/tasks/import?mode=fail_on_conflict")
+        .set_payload(body)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+    let report: ImportReport = test::read_body_json(resp).await;
+    assert!(report.aborted);
+    assert_eq!(report.lines, 1);
+}
+
+#[actix_web::test]
+async fn test_import_checks_task_links() {
+    let app_state = AppState::new();
+
+    let parent = Task::new("Parent".to_string(), None);
+    let mut child = Task::new("Child".to_string(), None);
+    child.parent_id = Some(parent.id);
+    let mut waiting = Task::new("Waiting".to_string(), None);
+    waiting.blocked_by = vec![uuid::Uuid::new_v4()];
+    let mut reparented = parent.clone();
+    reparented.parent_id = Some(child.id);
+    let mut looped = Task::new("Looped".to_string(), None);
+    looped.blocked_by = vec![looped.id];
+
+    let body = [&child, &parent, &child, &waiting, &reparented, &looped]
+        .iter()
+        .map(|task| serde_json::to_string(task).unwrap() + "\n")
+        .collect::<String>();
+
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(as_admin))
+            .app_data(web::Data::new(app_state.clone()))
+            .route(
+                "/api/v1/tasks/import",
+                web::post().to(rust_high_performance_api_server::handlers::import_tasks),
+            ),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/tasks/import?mode=upsert")
+        .set_payload(body)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let report: ImportReport = test::read_body_json(resp).await;
+
+    // The child only imports once its parent exists, and no cycle gets in
+    assert_eq!(report.created, 2);
+    assert_eq!(report.updated, 0);
+    assert_eq!(report.failed, 4);
+    let failed: Vec<usize> = report.errors.iter().map(|e| e.line).collect();
+    assert_eq!(failed, vec![1, 4, 5, 6]);
+    assert!(report.errors[0].message.contains("Parent task"));
+    assert!(report.errors[2].message.contains("cycle"));
+    assert_eq!(
+        app_state.default_tasks().get(&parent.id).unwrap().parent_id,
+        None
+    );
+    assert!(app_state.default_tasks().get(&looped.id).is_none());
+}
+
+#[actix_web::test]
+async fn test_response_compression_negotiation() {
+    let app_state = AppState::new();
+    for i in 0..50 {
+        let task = Task::new(
+            format!("Task {}", i),
+            Some("A reasonably long description".to_string()),
+        );
+        app_state.default_tasks().insert(task.id, task);
+    }
+
+    let compression = CompressionConfig {
+        enabled: true,
+        encodings: "zstd,gzip".to_string(),
+        min_size: 1024,
+    };
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(as_admin))
+            .app_data(web::Data::new(app_state))
+            .wrap(ResponseCompression::new(&compression))
+            .route(
+                "/api/v1/tasks",
+                web::get().to(rust_high_performance_api_server::handlers::get_tasks),
+            )
+            .route(
+                "/health",
+                web::get().to(rust_high_performance_api_server::handlers::health_check),
+            ),
+    )
+    .await;
+
+    // Brotli is not configured, so the client's second choice is used
+    let req = test::TestRequest::get()
+        .uri("/api/v1/tasks")
+        .insert_header(("Accept-Encoding", "br, gzip;q=0.8"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.headers().get("content-encoding").unwrap(), "gzip");
+
+    let body = test::read_body(resp).await;
+    let mut decoded = Vec::new();
+    flate2::read::GzDecoder::new(&body[..])
+        .read_to_end(&mut decoded)
+        .unwrap();
+    let tasks: Vec<Task> = serde_json::from_slice(&decoded).unwrap();
+    assert_eq!(tasks.len(), 50);
+
+    // Small responses stay uncompressed
+    let req = test::TestRequest::get()
+        .uri("/health")
+        .insert_header(("Accept-Encoding", "gzip"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.headers().get("content-encoding").is_none());
+}
+
+#[actix_web::test]
+async fn test_compressed_request_body_limits() {
+    let app_state = AppState::new();
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(as_admin))
+            .app_data(web::Data::new(app_state.clone()))
+            .app_data(json_config(1024))
+            .wrap(BodyLimit::new(1024))
+            .route(
+                "/api/v1/tasks",
+                web::post().to(rust_high_performance_api_server::handlers::create_task),
+            ),
+    )
+    .await;
+
+    let gzip = |data: &[u8]| {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    };
+
+    // A gzip body within the limit is decoded transparently
+    let req = test::TestRequest::post()
+        .uri("/api/v1/tasks")
+        .insert_header(("Content-Type", "application/json"))
+        .insert_header(("Content-Encoding", "gzip"))
+        .set_payload(gzip(br#"{"title": "Compressed"}"#))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+
+    // A tiny body that inflates past the limit is rejected
+    let padding = " ".repeat(1024 * 1024);
+    let bomb = gzip(format!(r#"{{"title": "Bomb"{}}}"#, padding).as_bytes());
+    assert!(bomb.len() < 4096);
+    let req = test::TestRequest::post()
+        .uri("/api/v1/tasks")
+        .insert_header(("Content-Type", "application/json"))
+        .insert_header(("Content-Encoding", "gzip"))
+        .set_payload(bomb)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 413);
+    assert_eq!(app_state.default_tasks().len(), 1);
+
+    // Plain bodies over the limit are rejected from Content-Length alone
+    let req = test::TestRequest::post()
+        .uri("/api/v1/tasks")
+        .insert_header(("Content-Type", "application/json"))
+        .set_payload(format!(r#"{{"title": "{}"}}"#, "x".repeat(2048)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 413);
+}
+
+fn self_signed(common_name: &str) -> (String, String, Vec<u8>) {
+    let mut params = rcgen::CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+    params
+        .distinguished_name
+        .push(rcgen::DnType::CommonName, common_name);
+    let key_pair = rcgen::KeyPair::generate().unwrap();
+    let cert = params.self_signed(&key_pair).unwrap();
+    (cert.pem(), key_pair.serialize_pem(), cert.der().to_vec())
+}
+
+fn write_with_mtime(path: &std::path::Path, contents: impl AsRef<[u8]>, secs: u64) {
+    std::fs::write(path, contents).unwrap();
+    let mtime = std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+    std::fs::File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(mtime)
+        .unwrap();
+}
+
+#[actix_web::test]
+async fn test_tls_certificate_reload() {
+    let dir = std::env::temp_dir().join(format!("tls-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let cert_path = dir.join("server.crt");
+    let key_path = dir.join("server.key");
+
+    let (cert_pem, key_pem, first_der) = self_signed("first");
+    write_with_mtime(&cert_path, cert_pem, 1_000);
+    write_with_mtime(&key_path, key_pem, 1_000);
+
+    let resolver = ReloadingCertResolver::new(&cert_path, &key_path).unwrap();
+    assert_eq!(resolver.current().cert[0].as_ref(), &first_der[..]);
+    assert!(!resolver.reload_if_changed().unwrap());
+
+    // A broken renewal keeps serving the previous certificate
+    write_with_mtime(&cert_path, "not a certificate", 2_000);
+    assert!(resolver.reload_if_changed().is_err());
+    assert_eq!(resolver.current().cert[0].as_ref(), &first_der[..]);
+
+    let (cert_pem, key_pem, second_der) = self_signed("second");
+    write_with_mtime(&key_path, key_pem, 3_000);
+    write_with_mtime(&cert_path, cert_pem, 3_000);
+    assert!(resolver.reload_if_changed().unwrap());
+    assert_eq!(resolver.current().cert[0].as_ref(), &second_der[..]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[actix_web::test]
+async fn test_tls_client_certificate_subject() {
+    let (_, _, der) = self_signed("client-42");
+    let subject = tls::certificate_subject(&der).unwrap();
+    assert!(subject.contains("CN=client-42"));
+}
+
+/// Issues a certificate for `name` signed by `ca`, returning its PEM, key PEM
+/// and DER
+fn issue(
+    name: &str,
+    usage: rcgen::ExtendedKeyUsagePurpose,
+    ca: &(rcgen::Certificate, rcgen::KeyPair),
+) -> (String, String, Vec<u8>, Vec<u8>) {
+    let mut params = rcgen::CertificateParams::new(vec![name.to_string()]).unwrap();
+    params
+        .distinguished_name
+        .push(rcgen::DnType::CommonName, name);
+    params.extended_key_usages = vec![usage];
+    let key_pair = rcgen::KeyPair::generate().unwrap();
+    let cert = params.signed_by(&key_pair, &ca.0, &ca.1).unwrap();
+    (
+        cert.pem(),
+        key_pair.serialize_pem(),
+        cert.der().to_vec(),
+        key_pair.serialize_der(),
+    )
+}
+
+/// Completes a TLS handshake with `addr`, trusting `ca_der`, offering `alpn`
+/// and presenting `identity` as the client certificate and key if given
+fn tls_connect(
+    addr: std::net::SocketAddr,
+    ca_der: &[u8],
+    identity: Option<(Vec<u8>, Vec<u8>)>,
+    alpn: &[&[u8]],
+) -> rustls::StreamOwned<rustls::ClientConnection, std::net::TcpStream> {
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(CertificateDer::from(ca_der.to_vec())).unwrap();
+    let builder = rustls::ClientConfig::builder_with_provider(std::sync::Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .unwrap()
+    .with_root_certificates(roots);
+    let mut config = match identity {
+        Some((cert, key)) => builder
+            .with_client_auth_cert(
+                vec![CertificateDer::from(cert)],
+                PrivateKeyDer::Pkcs8(key.into()),
+            )
+            .unwrap(),
+        None => builder.with_no_client_auth(),
+    };
+    config.alpn_protocols = alpn.iter().map(|protocol| protocol.to_vec()).collect();
+
+    let connection = rustls::ClientConnection::new(
+        std::sync::Arc::new(config),
+        ServerName::try_from("localhost").unwrap(),
+    )
+    .unwrap();
+    let mut stream =
+        rustls::StreamOwned::new(connection, std::net::TcpStream::connect(addr).unwrap());
+    while stream.conn.is_handshaking() {
+        stream.conn.complete_io(&mut stream.sock).unwrap();
+    }
+    stream
+}
+
+/// Sends a plain HTTP/1.1 request and returns whatever arrives before the
+/// connection closes, or the error that closed it
+fn tls_get(
+    stream: &mut rustls::StreamOwned<rustls::ClientConnection, std::net::TcpStream>,
+    path: &str,
+) -> std::io::Result<String> {
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nX-API-Key: admin-key\r\nConnection: close\r\n\r\n",
+        path
+    )?;
+    let mut response = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => response.extend_from_slice(&buf[..n]),
+            // Servers often close without a TLS close_notify
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof && !response.is_empty() => {
+                break
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(String::from_utf8_lossy(&response).into_owned())
+}
+
+#[actix_web::test]
+async fn test_tls_mutual_handshake() {
+    let dir = std::env::temp_dir().join(format!("mtls-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let mut ca_params = rcgen::CertificateParams::new(Vec::new()).unwrap();
+    ca_params
+        .distinguished_name
+        .push(rcgen::DnType::CommonName, "test-ca");
+    ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+    let ca_key = rcgen::KeyPair::generate().unwrap();
+    let ca = (ca_params.self_signed(&ca_key).unwrap(), ca_key);
+    let ca_der = ca.0.der().to_vec();
+    let (server_pem, server_key_pem, _, _) =
+        issue("localhost", rcgen::ExtendedKeyUsagePurpose::ServerAuth, &ca);
+    let (_, _, client_der, client_key_der) =
+        issue("client-42", rcgen::ExtendedKeyUsagePurpose::ClientAuth, &ca);
+    for (file, contents) in [
+        ("ca.crt", ca.0.pem()),
+        ("server.crt", server_pem),
+        ("server.key", server_key_pem),
+    ] {
+        std::fs::write(dir.join(file), contents).unwrap();
+    }
+
+    let mut config = Config::from_env().unwrap();
+    config.api.api_key = Secret::new("admin-key");
+    let mut tls_config = config.server.tls.clone();
+    tls_config.cert_path = dir.join("server.crt").to_string_lossy().into_owned();
+    tls_config.key_path = dir.join("server.key").to_string_lossy().into_owned();
+    tls_config.client_ca_path = Some(dir.join("ca.crt").to_string_lossy().into_owned());
+    tls_config.client_auth_required = true;
+    let (rustls_config, _) = tls::server_config(&tls_config).unwrap();
+
+    // Echoes the subject ApiKeyAuth copied into the request extensions
+    let config_handle = ConfigHandle::new(config, ConfigSources::default());
+    let server = actix_web::HttpServer::new(move || {
+        App::new()
+            .wrap(ApiKeyAuth::new(config_handle.clone()))
+            .route(
+                "/subject",
+                web::get().to(|req: actix_web::HttpRequest| async move {
+                    req.extensions()
+                        .get::<tls::ClientCertificate>()
+                        .map(|cert| cert.subject.clone())
+                        .unwrap_or_default()
+                }),
+            )
+    })
+    .workers(1)
+    .on_connect(tls::on_connect)
+    .bind_rustls_0_23(("127.0.0.1", 0), rustls_config)
+    .unwrap();
+    let addr = server.addrs()[0];
+    let server = server.disable_signals().run();
+    let handle = server.handle();
+    actix_web::rt::spawn(server);
+
+    let (alpn, with_cert, without_cert) = actix_web::rt::task::spawn_blocking(move || {
+        let identity = Some((client_der, client_key_der));
+        let alpn = tls_connect(addr, &ca_der, identity.clone(), &[b"h2", b"http/1.1"])
+            .conn
+            .alpn_protocol()
+            .map(<[u8]>::to_vec);
+        let with_cert = tls_get(
+            &mut tls_connect(addr, &ca_der, identity, &[b"http/1.1"]),
+            "/subject",
+        );
+        // TLS 1.3 clients only learn of the rejection from the next record
+        let without_cert = tls_connect(addr, &ca_der, None, &[b"http/1.1"]).read(&mut [0u8; 1]);
+        (alpn, with_cert, without_cert)
+    })
+    .await
+    .unwrap();
+    handle.stop(false).await;
+
+    assert_eq!(alpn.as_deref(), Some(&b"h2"[..]));
+    let response = with_cert.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.ends_with("CN=client-42"), "{}", response);
+    let rejection = without_cert.unwrap_err().to_string();
+    assert!(rejection.contains("CertificateRequired"), "{}", rejection);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[actix_web::test]
+async fn test_readiness_fails_when_draining() {
+    let app_state = AppState::new();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(app_state.clone()))
+            .route(
+                "/health/ready",
+                web::get().to(rust_high_performance_api_server::handlers::readiness),
+            ),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/health/ready").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    app_state.shutdown.begin();
+
+    let req = test::TestRequest::get().uri("/health/ready").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 503);
+}
+
+#[actix_web::test]
+async fn test_shutdown_awaits_background_tasks() {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+    use std::time::Duration;
+
+    // A task that flushes on cancellation finishes within the deadline
+    let shutdown = rust_high_performance_api_server::shutdown::Shutdown::new();
+    let flushed = Arc::new(AtomicBool::new(false));
+    let token = shutdown.token();
+    let flag = flushed.clone();
+    shutdown.spawn(async move {
+        token.cancelled().await;
+        flag.store(true, Ordering::SeqCst);
+    });
+
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(1);
+    assert!(shutdown.stop_tasks(deadline).await);
+    assert!(flushed.load(Ordering::SeqCst));
+
+    // A task that ignores cancellation makes the drain unclean
+    let shutdown = rust_high_performance_api_server::shutdown::Shutdown::new();
+    shutdown.spawn(tokio::time::sleep(Duration::from_secs(60)));
+
+    let deadline = tokio::time::Instant::now() + Duration::from_millis(50);
+    assert!(!shutdown.stop_tasks(deadline).await);
+}
+
+#[actix_web::test]
+async fn test_health_probes_report_checks() {
+    use std::time::Duration;
+
+    let app_state = AppState::new();
+    app_state
+        .health
+        .register("cache", &[Probe::Ready], false, || async {
+            CheckOutcome::fail("cache offline")
+        });
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(app_state.clone()))
+            .wrap(ApiKeyAuth::new(config_with_api_key("secret")))
+            .route(
+                "/health/live",
+                web::get().to(rust_high_performance_api_server::handlers::liveness),
+            )
+            .route(
+                "/health/ready",
+                web::get().to(rust_high_performance_api_server::handlers::readiness),
+            )
+            .route(
+                "/health/startup",
+                web::get().to(rust_high_performance_api_server::handlers::startup),
+            ),
+    )
+    .await;
+
+    // Probes bypass authentication; a non-critical failure only degrades readiness
+    let req = test::TestRequest::get().uri("/health/ready").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let report: HealthReport = test::read_body_json(resp).await;
+    assert_eq!(report.status, CheckStatus::Warn);
+    let storage = report.checks.iter().find(|c| c.name == "storage").unwrap();
+    assert_eq!(storage.status, CheckStatus::Pass);
+
+    // Startup fails until initialisation is marked complete
+    let req = test::TestRequest::get().uri("/health/startup").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 503);
+    app_state.health.mark_started();
+    let req = test::TestRequest::get().uri("/health/startup").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    // A critical check that hangs past its timeout fails the probe
+    app_state.health.register_with_timeout(
+        "slow",
+        &[Probe::Live],
+        true,
+        Duration::from_millis(20),
+        || async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            CheckOutcome::pass()
+        },
+    );
+    let req = test::TestRequest::get().uri("/health/live").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 503);
+    let report: HealthReport = test::read_body_json(resp).await;
+    assert_eq!(report.status, CheckStatus::Fail);
+    assert!(report.checks[0]
+        .message
+        .as_deref()
+        .unwrap()
+        .contains("Timed out"));
+
+    // Checks registered without their own timeout follow the configured one
+    let configured = AppState::with_check_timeout(Duration::from_millis(20));
+    configured
+        .health
+        .register("slow", &[Probe::Live], true, || async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            CheckOutcome::pass()
+        });
+    let report = configured.health.report(Probe::Live).await;
+    assert_eq!(report.status, CheckStatus::Fail);
+}
+
+#[actix_web::test]
+async fn test_worker_heartbeat_liveness() {
+    use std::time::Duration;
+
+    let app_state = AppState::new();
+    let heartbeat = app_state
+        .health
+        .worker("flusher", Duration::from_millis(30));
+
+    let report = app_state.health.report(Probe::Live).await;
+    assert_eq!(report.status, CheckStatus::Pass);
+
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    let report = app_state.health.report(Probe::Live).await;
+    assert_eq!(report.status, CheckStatus::Fail);
+
+    heartbeat.beat();
+    let report = app_state.health.report(Probe::Live).await;
+    assert_eq!(report.status, CheckStatus::Pass);
+}
+
+#[actix_web::test]
+async fn test_config_validation_reports_all_problems() {
+    let mut config = Config::from_env().unwrap();
+    assert!(config.validate().is_ok());
+
+    config.server.port = 0;
+    config.server.workers = 0;
+    config.api.api_key = Secret::new("");
+    let problems = config.validate().unwrap_err();
+    assert_eq!(problems.len(), 3);
+    assert!(problems.iter().any(|p| p.contains("server.port")));
+    assert!(problems.iter().any(|p| p.contains("server.workers")));
+    assert!(problems.iter().any(|p| p.contains("api.api_key")));
+
+    let mut config = Config::from_env().unwrap();
+    config.audit.retention_days = u64::MAX;
+    config.revisions.max_age_days = u64::MAX;
+    config.trash.retention_days = MAX_RETENTION_DAYS + 1;
+    let problems = config.validate().unwrap_err();
+    assert_eq!(problems.len(), 3);
+    assert!(problems[0].contains("audit.retention_days"));
+    assert!(problems[1].contains("revisions.max_age_days"));
+    assert!(problems[2].contains("trash.retention_days"));
+
+    let mut config = Config::from_env().unwrap();
+    config.profile = Profile::Production;
+    config.api.api_key = Secret::new(DEFAULT_API_KEY);
+    let problems = config.validate().unwrap_err();
+    assert!(problems[0].contains("production"));
+}
+
+#[actix_web::test]
+async fn test_config_file_layers_and_redaction() {
+    let dir = std::env::temp_dir().join(format!("config-layers-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.toml");
+    std::fs::write(
+        &path,
+        "profile = \"production\"\n[server]\nport = 9000\nworkers = 3\n[api]\napi_key = \"file-key\"\n",
+    )
+    .unwrap();
+
+    let config = Config::load(&ConfigSources {
+        file: Some(path),
+        secrets_file: None,
+        overrides: vec![("server.port".to_string(), "9100".to_string())],
+    })
+    .unwrap();
+    assert_eq!(config.profile, Profile::Production);
+    assert_eq!(config.server.port, 9100);
+    assert_eq!(config.server.workers, 3);
+    assert_eq!(config.api.api_key.expose(), "file-key");
+    assert!(config.validate().is_ok());
+
+    // Secrets never appear in dumps
+    assert!(!toml::to_string_pretty(&config)
+        .unwrap()
+        .contains("file-key"));
+    assert!(!format!("{:?}", config).contains("file-key"));
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+fn config_with_api_key(api_key: &str) -> ConfigHandle {
+    let mut config = Config::from_env().unwrap();
+    config.api.api_key = Secret::new(api_key);
+    ConfigHandle::new(config, ConfigSources::default())
+}
+
+#[actix_web::test]
+async fn test_config_reload_rotates_api_key() {
+    let dir = std::env::temp_dir().join(format!("config-reload-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.toml");
+    std::fs::write(&path, "[api]\napi_key = \"old-key\"\n").unwrap();
+
+    let sources = ConfigSources {
+        file: Some(path.clone()),
+        secrets_file: None,
+        overrides: Vec::new(),
+    };
+    let handle = ConfigHandle::new(Config::load(&sources).unwrap(), sources);
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState::new()))
+            .wrap(ApiKeyAuth::new(handle.clone()))
+            .route(
+                "/api/v1/tasks",
+                web::get().to(rust_high_performance_api_server::handlers::get_tasks),
+            ),
+    )
+    .await;
+
+    let call = |key: &'static str| {
+        test::TestRequest::get()
+            .uri("/api/v1/tasks")
+            .insert_header(("X-API-Key", key))
+            .to_request()
+    };
+    assert_eq!(
+        test::call_service(&app, call("old-key")).await.status(),
+        200
+    );
+
+    std::fs::write(&path, "[api]\napi_key = \"new-key\"\n").unwrap();
+    assert_eq!(handle.reload(), Ok(true));
+    assert_eq!(
+        test::call_service(&app, call("old-key")).await.status(),
+        401
+    );
+    assert_eq!(
+        test::call_service(&app, call("new-key")).await.status(),
+        200
+    );
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[actix_web::test]
+async fn test_config_reload_failure_keeps_previous() {
+    let dir = std::env::temp_dir().join(format!("config-reload-fail-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.toml");
+    std::fs::write(&path, "[api]\napi_key = \"first-key\"\n").unwrap();
+
+    let sources = ConfigSources {
+        file: Some(path.clone()),
+        secrets_file: None,
+        overrides: Vec::new(),
+    };
+    let handle = ConfigHandle::new(Config::load(&sources).unwrap(), sources);
+    let metrics = Metrics::new();
+    handle.register_metrics(&metrics);
+
+    // Invalid values are rejected as a whole
+    std::fs::write(&path, "[api]\napi_key = \"\"\n").unwrap();
+    let problems = handle.reload().unwrap_err();
+    assert!(problems[0].contains("api.api_key"));
+    assert_eq!(handle.current().api.api_key.expose(), "first-key");
+    let stats = handle.stats();
+    assert_eq!(stats.failures, 1);
+    assert!(stats.last_error.is_some());
+    assert_eq!(metrics.counter(RELOAD_FAILURES_METRIC, &[]), 1);
+    assert!(metrics.counter(LAST_RELOAD_FAILURE_METRIC, &[]) > 0);
+    assert_eq!(metrics.counter(LAST_RELOAD_SUCCESS_METRIC, &[]), 0);
+    assert!(metrics
+        .render()
+        .contains("# TYPE config_last_reload_failure_timestamp_seconds gauge"));
+
+    // Static sections keep their startup values until a restart
+    let port = handle.current().server.port;
+    std::fs::write(
+        &path,
+        "[server]\nport = 1\n[api]\napi_key = \"second-key\"\n",
+    )
+    .unwrap();
+    assert_eq!(handle.reload(), Ok(true));
+    assert_eq!(handle.current().api.api_key.expose(), "second-key");
+    assert_eq!(handle.current().server.port, port);
+    assert_eq!(handle.stats().last_error, None);
+    assert!(metrics.counter(LAST_RELOAD_SUCCESS_METRIC, &[]) > 0);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[actix_web::test]
+async fn test_secret_file_indirection() {
+    let dir = std::env::temp_dir().join(format!("secret-file-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let secret_path = dir.join("api_key");
+    std::fs::write(&secret_path, "mounted-key\n").unwrap();
+    let alice_path = dir.join("alice_key");
+    std::fs::write(&alice_path, "alice-mounted\n").unwrap();
+    let bob_env = format!("TEST_BOB_KEY_{}", std::process::id());
+    std::env::set_var(&bob_env, "bob-from-env");
+    let path = dir.join("config.toml");
+    std::fs::write(
+        &path,
+        format!(
+            "[api]\napi_key = \"plain-key\"\napi_key_file = \"{}\"\n\
+             [api.principals.alice]\nrole = \"member\"\nkey_file = \"{}\"\n\
+             [api.principals.bob]\nrole = \"viewer\"\nkey = \"plain-bob\"\nkey_env = \"{}\"\n",
+            secret_path.display(),
+            alice_path.display(),
+            bob_env,
+        ),
+    )
+    .unwrap();
+
+    let config = Config::load(&ConfigSources {
+        file: Some(path.clone()),
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(config.api.api_key.expose(), "mounted-key");
+    assert_eq!(format!("{:?}", config.api.api_key), "Secret([redacted])");
+    // Principal keys resolve the same way
+    assert_eq!(config.api.principals["alice"].key.expose(), "alice-mounted");
+    assert_eq!(config.api.principals["bob"].key.expose(), "bob-from-env");
+    assert!(config.validate().is_ok());
+
+    // A variable that is not set fails the load rather than leaving a key empty
+    std::env::remove_var(&bob_env);
+    assert!(Config::load(&ConfigSources {
+        file: Some(path),
+        ..Default::default()
+    })
+    .is_err());
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[actix_web::test]
+async fn test_encrypted_secrets_file() {
+    let key = Secret::new(secrets::generate_key().unwrap());
+    let sealed = secrets::encrypt("[api]\napi_key = \"sealed-key\"\n", &key).unwrap();
+    assert!(!sealed.contains("sealed-key"));
+
+    // A different key cannot decrypt it
+    let other = Secret::new(secrets::generate_key().unwrap());
+    assert!(secrets::decrypt(&sealed, &other).is_err());
+
+    let dir = std::env::temp_dir().join(format!("secrets-enc-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("secrets.enc");
+    std::fs::write(&path, &sealed).unwrap();
+    let key_path = dir.join("secrets.key");
+    std::fs::write(&key_path, key.expose()).unwrap();
+    std::env::set_var(secrets::SECRETS_KEY_FILE_ENV, &key_path);
+
+    let config = Config::load(&ConfigSources {
+        secrets_file: Some(path),
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(config.api.api_key.expose(), "sealed-key");
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+fn config_with_cors(origins: &str, credentials: bool) -> ConfigHandle {
+    let mut config = Config::from_env().unwrap();
+    config.api.api_key = Secret::new("secret");
+    config.cors.allowed_origins = origins.to_string();
+    config.cors.allow_credentials = credentials;
+    ConfigHandle::new(config, ConfigSources::default())
+}
+
+#[actix_web::test]
+async fn test_cors_preflight_policy() {
+    let handle = config_with_cors("https://app.example.com,https://*.example.org", false);
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState::new()))
+            .wrap(ApiKeyAuth::new(handle.clone()))
+            .wrap(Cors::new(handle.clone()))
+            .route(
+                "/api/v1/tasks",
+                web::get().to(rust_high_performance_api_server::handlers::get_tasks),
+            ),
+    )
+    .await;
+
+    let preflight = |origin: &str, method: &str| {
+        test::TestRequest::default()
+            .method(actix_web::http::Method::OPTIONS)
+            .uri("/api/v1/tasks")
+            .insert_header(("Origin", origin))
+            .insert_header(("Access-Control-Request-Method", method))
+            .insert_header(("Access-Control-Request-Headers", "X-API-Key"))
+            .to_request()
+    };
+
+    // Preflights are answered before authentication
+    let resp = test::call_service(&app, preflight("https://app.example.com", "GET")).await;
+    assert_eq!(resp.status(), 204);
+    assert_eq!(
+        resp.headers().get("access-control-allow-origin").unwrap(),
+        "https://app.example.com"
+    );
+    assert_eq!(
+        resp.headers().get("access-control-max-age").unwrap(),
+        "3600"
+    );
+
+    let resp = test::call_service(&app, preflight("https://a.b.example.org", "DELETE")).await;
+    assert_eq!(resp.status(), 204);
+
+    for (origin, method) in [
+        ("https://example.org", "GET"),
+        ("https://evil.com", "GET"),
+        ("https://app.example.com", "PATCH"),
+    ] {
+        let resp = test::call_service(&app, preflight(origin, method)).await;
+        assert_eq!(resp.status(), 403, "{} {}", origin, method);
+        assert!(resp.headers().get("access-control-allow-origin").is_none());
+    }
+}
+
+#[actix_web::test]
+async fn test_cors_actual_request_headers() {
+    let handle = config_with_cors("*", false);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(as_admin))
+            .app_data(web::Data::new(AppState::new()))
+            .wrap(Cors::new(handle.clone()))
+            .route(
+                "/api/v1/tasks",
+                web::get().to(rust_high_performance_api_server::handlers::get_tasks),
+            ),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/tasks")
+        .insert_header(("Origin", "https://anywhere.test"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(
+        resp.headers().get("access-control-allow-origin").unwrap(),
+        "*"
+    );
+    assert_eq!(resp.headers().get("vary").unwrap(), "Origin");
+
+    // Credentials cannot be combined with the wildcard origin
+    let mut config = (*handle.current()).clone();
+    config.cors.allow_credentials = true;
+    let problems = config.validate().unwrap_err();
+    assert!(problems
+        .iter()
+        .any(|p| p.contains("cors.allow_credentials")));
+}
+
+#[actix_web::test]
+async fn test_security_headers_defaults() {
+    let handle = config_with_api_key("secret");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState::new()))
+            .wrap(ApiKeyAuth::new(handle.clone()))
+            .wrap(SecurityHeaders::new(handle.clone()))
+            .route(
+                "/api/v1/identified",
+                web::get().to(|| async {
+                    actix_web::HttpResponse::Ok()
+                        .insert_header(("Server", "actix-web/4"))
+                        .insert_header(("X-Powered-By", "Rust"))
+                        .finish()
+                }),
+            ),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/identified")
+        .insert_header(("X-API-Key", "secret"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let headers = resp.headers();
+    assert_eq!(headers.get("x-content-type-options").unwrap(), "nosniff");
+    assert_eq!(headers.get("x-frame-options").unwrap(), "DENY");
+    assert_eq!(headers.get("referrer-policy").unwrap(), "no-referrer");
+    assert!(headers.get("strict-transport-security").is_some());
+    assert!(headers.get("content-security-policy").is_some());
+    assert_eq!(headers.get("cache-control").unwrap(), "no-store");
+    assert!(headers.get("server").is_none());
+    assert!(headers.get("x-powered-by").is_none());
+
+    // Rejected requests are hardened too, but are not marked no-store
+    let req = test::TestRequest::get()
+        .uri("/api/v1/identified")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401);
+    assert_eq!(resp.headers().get("x-frame-options").unwrap(), "DENY");
+    assert!(resp.headers().get("cache-control").is_none());
+}
+
+#[actix_web::test]
+async fn test_security_headers_scope_overrides() {
+    let mut config = Config::from_env().unwrap();
+    config.security_headers.hsts = String::new();
+    let handle = ConfigHandle::new(config, ConfigSources::default());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState::new()))
+            .wrap(SecurityHeaders::new(handle.clone()))
+            .route(
+                "/health",
+                web::get().to(rust_high_performance_api_server::handlers::health_check),
+            )
+            .route("/docs/index.html", web::get().to(|| async { "docs" })),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/health").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.headers().get("cache-control").unwrap(), "no-store");
+    assert!(resp.headers().get("strict-transport-security").is_none());
+
+    let req = test::TestRequest::get()
+        .uri("/docs/index.html")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.headers().get("x-frame-options").unwrap(), "SAMEORIGIN");
+    let csp = resp
+        .headers()
+        .get("content-security-policy")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert!(csp.contains("script-src 'self'"));
+}
+
+#[actix_web::test]
+async fn test_trusted_proxy_client_ip() {
+    use actix_web::http::header::HeaderMap;
+    use std::net::IpAddr;
+
+    let trusted = network::parse_cidrs("10.0.0.0/8, 192.168.1.1").unwrap();
+    let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+    let headers = |name: &'static str, value: &'static str| {
+        let mut map = HeaderMap::new();
+        map.insert(
+            actix_web::http::header::HeaderName::from_static(name),
+            actix_web::http::header::HeaderValue::from_static(value),
+        );
+        map
+    };
+
+    // Untrusted peers cannot spoof their address
+    let xff = headers("x-forwarded-for", "1.1.1.1");
+    assert_eq!(
+        network::resolve_client_ip(ip("8.8.8.8"), &xff, &trusted),
+        ip("8.8.8.8")
+    );
+
+    // The first untrusted hop from the right is the client
+    let xff = headers("x-forwarded-for", "1.1.1.1, 203.0.113.9, 10.1.2.3");
+    assert_eq!(
+        network::resolve_client_ip(ip("192.168.1.1"), &xff, &trusted),
+        ip("203.0.113.9")
+    );
+
+    let fwd = headers(
+        "forwarded",
+        "for=\"[2001:db8::17]:4711\";proto=https, for=10.0.0.5",
+    );
+    assert_eq!(
+        network::resolve_client_ip(ip("10.0.0.1"), &fwd, &trusted),
+        ip("2001:db8::17")
+    );
+}
+
+#[actix_web::test]
+async fn test_ip_filter_scopes_and_metrics() {
+    let mut config = Config::from_env().unwrap();
+    config.network.trusted_proxies = "10.0.0.1".parse().unwrap();
+    config.network.deny = "198.51.100.0/24".parse().unwrap();
+    config.network.scopes.insert(
+        "/admin".to_string(),
+        IpAccessRule {
+            allow: "10.0.0.0/8".parse().unwrap(),
+            deny: Default::default(),
+        },
+    );
+    let handle = ConfigHandle::new(config, ConfigSources::default());
+    let state = AppState::new();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .wrap(IpFilter::new(handle.clone(), state.metrics.clone()))
+            .route("/admin/stats", web::get().to(|| async { "ok" }))
+            .route("/public", web::get().to(|| async { "ok" })),
+    )
+    .await;
+
+    let call = |path: &str, peer: &str, forwarded_for: Option<&str>| {
+        let mut req = test::TestRequest::get()
+            .uri(path)
+            .peer_addr(peer.parse().unwrap());
+        if let Some(xff) = forwarded_for {
+            req = req.insert_header(("X-Forwarded-For", xff.to_string()));
+        }
+        req.to_request()
+    };
+
+    let resp = test::call_service(&app, call("/admin/stats", "10.2.3.4:5000", None)).await;
+    assert_eq!(resp.status(), 200);
+    let resp = test::call_service(&app, call("/admin/stats", "203.0.113.5:5000", None)).await;
+    assert_eq!(resp.status(), 403);
+    let resp = test::call_service(&app, call("/public", "203.0.113.5:5000", None)).await;
+    assert_eq!(resp.status(), 200);
+
+    // The load balancer's own address does not unlock the admin scope
+    let resp = test::call_service(
+        &app,
+        call("/admin/stats", "10.0.0.1:5000", Some("203.0.113.5")),
+    )
+    .await;
+    assert_eq!(resp.status(), 403);
+    let resp =
+        test::call_service(&app, call("/public", "10.0.0.1:5000", Some("198.51.100.7"))).await;
+    assert_eq!(resp.status(), 403);
+
+    assert_eq!(
+        state.metrics.counter(DENIED_METRIC, &[("scope", "/admin")]),
+        2
+    );
+    assert_eq!(
+        state.metrics.counter(DENIED_METRIC, &[("scope", "global")]),
+        1
+    );
+    assert!(state
+        .metrics
+        .render()
+        .contains("ip_filter_denied_total{scope=\"/admin\"} 2"));
+
+    // A malformed entry fails the load instead of being skipped
+    let sources = ConfigSources {
+        overrides: vec![(
+            "network.deny".to_string(),
+            "198.51.100.0/24,not-an-ip".to_string(),
+        )],
+        ..Default::default()
+    };
+    let err = Config::load(&sources).unwrap_err().to_string();
+    assert!(
+        err.contains("'not-an-ip' is not an IP address or CIDR"),
+        "{}",
+        err
+    );
+}
+
+#[actix_web::test]
+async fn test_ip_filter_scopes_match_decoded_paths() {
+    let mut config = Config::from_env().unwrap();
+    config.network.scopes.insert(
+        "/admin".to_string(),
+        IpAccessRule {
+            allow: "10.0.0.0/8".parse().unwrap(),
+            deny: Default::default(),
+        },
+    );
+    let handle = ConfigHandle::new(config, ConfigSources::default());
+    let state = AppState::new();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .wrap(IpFilter::new(handle, state.metrics.clone()))
+            .route("/admin/stats", web::get().to(|| async { "ok" })),
+    )
+    .await;
+
+    let call = |path: &str, peer: &str| {
+        test::TestRequest::get()
+            .uri(path)
+            .peer_addr(peer.parse().unwrap())
+            .to_request()
+    };
+
+    // The encoded path routes to the admin handler, so it is in the admin scope
+    let resp = test::call_service(&app, call("/%61dmin/stats", "10.2.3.4:5000")).await;
+    assert_eq!(resp.status(), 200);
+    let resp = test::call_service(&app, call("/%61dmin/stats", "203.0.113.5:5000")).await;
+    assert_eq!(resp.status(), 403);
+    let resp = test::call_service(&app, call("/admin/%73tats", "203.0.113.5:5000")).await;
+    assert_eq!(resp.status(), 403);
+    assert_eq!(
+        state.metrics.counter(DENIED_METRIC, &[("scope", "/admin")]),
+        2
+    );
+}
+
+#[actix_web::test]
+async fn test_policy_decisions() {
+    let alice = Principal::new("alice", Role::Member);
+    let viewer = Principal::new("audit", Role::Viewer);
+    let admin = Principal::new("admin", Role::Admin);
+
+    let mut own = Task::new("Alice's".to_string(), None);
+    own.owner_id = Some("alice".to_string());
+    let mut other = Task::new("Bob's".to_string(), None);
+    other.owner_id = Some("bob".to_string());
+
+    for action in [Action::Read, Action::Update, Action::Delete] {
+        assert!(policy::can(&alice, action, Some(&own)));
+        assert!(!policy::can(&alice, action, Some(&other)));
+        assert!(policy::can(&admin, action, Some(&other)));
+    }
+    assert!(policy::can(&alice, Action::Create, None));
+    assert!(!policy::can(&alice, Action::Import, None));
+    assert!(policy::can(&viewer, Action::Read, Some(&other)));
+    assert!(!policy::can(&viewer, Action::Update, Some(&other)));
+    assert!(!policy::can(&viewer, Action::Create, None));
+
+    // Tasks a member cannot see are reported as missing, not forbidden
+    assert_eq!(
+        policy::authorize(&alice, Action::Update, Some(&other))
+            .unwrap_err()
+            .error_response()
+            .status(),
+        404
+    );
+    assert_eq!(
+        policy::authorize(&viewer, Action::Update, Some(&other))
+            .unwrap_err()
+            .error_response()
+            .status(),
+        403
+    );
+}
+
+#[actix_web::test]
+async fn test_rbac_enforced_by_handlers() {
+    let mut config = Config::from_env().unwrap();
+    config.api.api_key = Secret::new("admin-key");
+    for (id, key, role) in [
+        ("alice", "alice-key", Role::Member),
+        ("audit", "audit-key", Role::Viewer),
+    ] {
+        config.api.principals.insert(
+            id.to_string(),
+            PrincipalConfig {
+                key: Secret::new(key),
+                role,
+                tenant: DEFAULT_TENANT.to_string(),
+            },
+        );
+    }
+    assert!(config.validate().is_ok());
+
+    let limits = config.limits.clone();
+    let state = AppState::new();
+    let mut foreign = Task::new("Someone else's".to_string(), None);
+    foreign.owner_id = Some("bob".to_string());
+    let foreign_id = foreign.id;
+    state.default_tasks().insert(foreign_id, foreign);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .wrap(ApiKeyAuth::new(ConfigHandle::new(
+                config,
+                ConfigSources::default(),
+            )))
+            .configure(|cfg| configure_routes(cfg, &limits)),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/tasks")
+        .insert_header(("X-API-Key", "alice-key"))
+        .set_json(json!({"title": "Mine"}))
+        .to_request();
+    let created: Task = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(created.owner_id.as_deref(), Some("alice"));
+
+    // Members only see and touch their own tasks
+    let req = test::TestRequest::get()
+        .uri("/api/v1/tasks")
+        .insert_header(("X-API-Key", "alice-key"))
+        .to_request();
+    let tasks: Vec<Task> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].id, created.id);
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/tasks/{}", foreign_id))
+        .insert_header(("X-API-Key", "alice-key"))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+    // Viewers read everything but change nothing
+    let req = test::TestRequest::get()
+        .uri("/api/v1/tasks")
+        .insert_header(("X-API-Key", "audit-key"))
+        .to_request();
+    let tasks: Vec<Task> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(tasks.len(), 2);
+
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/v1/tasks/{}", created.id))
+        .insert_header(("X-API-Key", "audit-key"))
+        .set_json(json!({"completed": true}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/tasks/{}", foreign_id))
+        .insert_header(("X-API-Key", "admin-key"))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 204);
+
+    // Orphaning subtasks on delete needs access to each of them
+    let mut subtask = Task::new("Someone else's subtask".to_string(), None);
+    subtask.owner_id = Some("bob".to_string());
+    subtask.parent_id = Some(created.id);
+    state.default_tasks().insert(subtask.id, subtask.clone());
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/tasks/{}", created.id))
+        .insert_header(("X-API-Key", "alice-key"))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+    assert!(state.default_tasks().contains_key(&created.id));
+    assert_eq!(
+        state.default_tasks().get(&subtask.id).unwrap().parent_id,
+        Some(created.id)
+    );
+}
+
+fn tenant_principal(id: &str, key: &str, role: Role, tenant: &str) -> (String, PrincipalConfig) {
+    let principal = PrincipalConfig {
+        key: Secret::new(key),
+        role,
+        tenant: tenant.to_string(),
+    };
+    (id.to_string(), principal)
+}
+
+#[actix_web::test]
+async fn test_tenant_isolation() {
+    let mut config = Config::from_env().unwrap();
+    config.api.api_key = Secret::new("operator-key");
+    config.api.principals.extend([
+        tenant_principal("red-admin", "red-key", Role::Admin, "red"),
+        tenant_principal("blue-admin", "blue-key", Role::Admin, "blue"),
+    ]);
+    assert!(config.validate().is_ok());
+
+    let limits = config.limits.clone();
+    let state = AppState::new();
+    state.tenants.provision(&config);
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .wrap(ApiKeyAuth::new(ConfigHandle::new(
+                config,
+                ConfigSources::default(),
+            )))
+            .configure(|cfg| configure_routes(cfg, &limits)),
+    )
+    .await;
+    let request = |method: test::TestRequest, uri: &str, key: &str| {
+        method
+            .uri(uri)
+            .insert_header(("X-API-Key", key.to_string()))
+    };
+
+    let req = request(test::TestRequest::post(), "/api/v1/tasks", "blue-key")
+        .set_json(json!({"title": "Blue secret"}))
+        .to_request();
+    let blue_task: Task = test::call_and_read_body_json(&app, req).await;
+
+    // Even an admin of another tenant cannot see, guess or change it
+    let req = request(test::TestRequest::get(), "/api/v1/tasks", "red-key").to_request();
+    let tasks: Vec<Task> = test::call_and_read_body_json(&app, req).await;
+    assert!(tasks.is_empty());
+    for method in [test::TestRequest::get(), test::TestRequest::delete()] {
+        let uri = format!("/api/v1/tasks/{}", blue_task.id);
+        let resp = test::call_service(&app, request(method, &uri, "red-key").to_request()).await;
+        assert_eq!(resp.status(), 404);
+    }
+    assert_eq!(state.tenants.get("blue").unwrap().tasks.len(), 1);
+
+    // Only admins of the default tenant manage tenants
+    let req = request(
+        test::TestRequest::post(),
+        "/api/v1/admin/tenants/blue/suspend",
+        "red-key",
+    )
+    .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+
+    let req = request(
+        test::TestRequest::post(),
+        "/api/v1/admin/tenants/blue/suspend",
+        "operator-key",
+    )
+    .to_request();
+    let tenant: Tenant = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(tenant.task_count, 1);
+    let req = request(test::TestRequest::get(), "/api/v1/tasks", "blue-key").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+
+    let req = request(
+        test::TestRequest::delete(),
+        "/api/v1/admin/tenants/blue",
+        "operator-key",
+    )
+    .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 204);
+    assert!(state.tenants.get("blue").is_none());
+}
+
+#[actix_web::test]
+async fn test_tenant_quotas() {
+    let mut config = Config::from_env().unwrap();
+    config.api.api_key = Secret::new("operator-key");
+    config.api.principals.extend([tenant_principal(
+        "green-admin",
+        "green-key",
+        Role::Admin,
+        "green",
+    )]);
+
+    let limits = config.limits.clone();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState::new()))
+            .wrap(ApiKeyAuth::new(ConfigHandle::new(
+                config,
+                ConfigSources::default(),
+            )))
+            .configure(|cfg| configure_routes(cfg, &limits)),
+    )
+    .await;
+
+    let quota = TenantQuota {
+        max_tasks: Some(1),
+        max_requests_per_minute: Some(3),
+        ..Default::default()
+    };
+    let req = test::TestRequest::post()
+        .uri("/api/v1/admin/tenants")
+        .insert_header(("X-API-Key", "operator-key"))
+        .set_json(json!({"id": "green", "quota": quota}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 201);
+
+    let create = || {
+        test::TestRequest::post()
+            .uri("/api/v1/tasks")
+            .insert_header(("X-API-Key", "green-key"))
+            .set_json(json!({"title": "Green"}))
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, create()).await.status(), 201);
+    assert_eq!(test::call_service(&app, create()).await.status(), 403);
+    assert_eq!(test::call_service(&app, create()).await.status(), 403);
+
+    let resp = test::call_service(&app, create()).await;
+    assert_eq!(resp.status(), 429);
+    assert!(resp.headers().contains_key("retry-after"));
+}
+
+#[actix_web::test]
+async fn test_task_slots_are_reserved_atomically() {
+    let state = AppState::new();
+    let tenant = state.tenants.ensure("slots");
+    let quota = TenantQuota {
+        max_tasks: Some(2),
+        ..Default::default()
+    };
+    state
+        .tenants
+        .set_quota("slots", quota, |_, _| Ok(()))
+        .unwrap();
+    let task = Task::new("Stored".to_string(), None);
+    tenant.tasks.insert(task.id, task);
+
+    // The last slot stays taken until the reservation is released
+    let slot = tenant.reserve_task_slot().unwrap();
+    assert!(tenant.reserve_task_slot().is_err());
+    drop(slot);
+    assert!(tenant.reserve_task_slot().is_ok());
+}
+
+#[actix_web::test]
+async fn test_usage_quotas_and_reporting() {
+    let mut config = Config::from_env().unwrap();
+    config.api.api_key = Secret::new("operator-key");
+    config.api.principals.extend([
+        tenant_principal("owner", "owner-key", Role::Admin, "acme"),
+        tenant_principal("intern", "intern-key", Role::Member, "acme"),
+    ]);
+
+    let limits = config.limits.clone();
+    let state = AppState::new();
+    state.tenants.provision(&config);
+    let quota = TenantQuota {
+        max_stored_bytes: Some(10),
+        max_requests_per_month: Some(5),
+        ..Default::default()
+    };
+    state
+        .tenants
+        .set_quota("acme", quota, |_, _| Ok(()))
+        .unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .wrap(ApiKeyAuth::new(ConfigHandle::new(
+                config,
+                ConfigSources::default(),
+            )))
+            .configure(|cfg| configure_routes(cfg, &limits)),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/tasks")
+        .insert_header(("X-API-Key", "intern-key"))
+        .set_json(json!({"title": "Eight by"}))
+        .to_request();
+    let task: Task = test::call_and_read_body_json(&app, req).await;
+
+    // Growing the title past the storage quota is refused with the numbers
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/v1/tasks/{}", task.id))
+        .insert_header(("X-API-Key", "intern-key"))
+        .set_json(json!({"title": "Far too long"}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 403);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["quota"], "max_stored_bytes");
+    assert_eq!(body["limit"], 10);
+    assert_eq!(body["used"], 8);
+
+    let usage = |key: &'static str| {
+        test::TestRequest::get()
+            .uri("/api/v1/usage")
+            .insert_header(("X-API-Key", key))
+            .to_request()
+    };
+    let report: UsageReport = test::call_and_read_body_json(&app, usage("intern-key")).await;
+    assert_eq!(
+        (report.tasks, report.stored_bytes, report.requests),
+        (1, 8, 3)
+    );
+    assert_eq!(report.principals.keys().collect::<Vec<_>>(), ["intern"]);
+
+    let report: UsageReport = test::call_and_read_body_json(&app, usage("owner-key")).await;
+    assert_eq!(report.principals.get("intern"), Some(&3));
+    assert_eq!(report.principals.get("owner"), Some(&1));
+
+    // The fifth request uses up the month
+    assert_eq!(
+        test::call_service(&app, usage("owner-key")).await.status(),
+        200
+    );
+    let resp = test::call_service(&app, usage("owner-key")).await;
+    assert_eq!(resp.status(), 429);
+    assert!(resp.headers().contains_key("retry-after"));
+
+    // Counters survive a restart through the persisted file
+    let path = std::env::temp_dir().join(format!("usage-{}.json", std::process::id()));
+    usage::save(&state.tenants, &path).unwrap();
+    let restarted = AppState::new();
+    restarted.tenants.ensure("acme");
+    usage::restore(&restarted.tenants, &path).unwrap();
+    let report = UsageReport::for_tenant(&restarted.tenants.get("acme").unwrap());
+    assert_eq!(report.requests, 5);
+    std::fs::remove_file(&path).ok();
+}
+
+#[actix_web::test]
+async fn test_audit_log_records_mutations() {
+    let mut config = Config::from_env().unwrap();
+    config.api.api_key = Secret::new("admin-key");
+    config.api.principals.extend([tenant_principal(
+        "alice",
+        "alice-key",
+        Role::Member,
+        DEFAULT_TENANT,
+    )]);
+
+    let limits = config.limits.clone();
+    let handle = ConfigHandle::new(config, ConfigSources::default());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState::new()))
+            .wrap(ApiKeyAuth::new(handle.clone()))
+            .wrap(RequestLogging::new(handle))
+            .configure(|cfg| configure_routes(cfg, &limits)),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/tasks")
+        .insert_header(("X-API-Key", "alice-key"))
+        .set_json(json!({"title": "Draft"}))
+        .to_request();
+    let task: Task = test::call_and_read_body_json(&app, req).await;
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/v1/tasks/{}", task.id))
+        .insert_header(("X-API-Key", "alice-key"))
+        .set_json(json!({"title": "Final"}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/tasks/{}", task.id))
+        .insert_header(("X-API-Key", "admin-key"))
+        .peer_addr("192.0.2.7:40000".parse().unwrap())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 204);
+
+    let query = |params: String, key: &'static str| {
+        test::TestRequest::get()
+            .uri(&format!("/api/v1/audit?{}", params))
+            .insert_header(("X-API-Key", key))
+            .to_request()
+    };
+    let records: Vec<AuditRecord> =
+        test::call_and_read_body_json(&app, query(format!("task_id={}", task.id), "admin-key"))
+            .await;
+    let operations: Vec<_> = records.iter().map(|r| r.operation.as_str()).collect();
+    assert_eq!(operations, ["task.create", "task.update", "task.trash"]);
+    assert_eq!(records[2].principal, "admin");
+    assert!(records.iter().all(|r| r.request_id.is_some()));
+    assert_eq!(records[2].client_ip, Some("192.0.2.7".parse().unwrap()));
+    let title = records[1]
+        .changes
+        .iter()
+        .find(|c| c.field == "title")
+        .unwrap();
+    assert_eq!(
+        (title.before.as_str(), title.after.as_str()),
+        (Some("Draft"), Some("Final"))
+    );
+
+    let records: Vec<AuditRecord> =
+        test::call_and_read_body_json(&app, query("principal=alice".to_string(), "admin-key"))
+            .await;
+    assert_eq!(records.len(), 2);
+    let records: Vec<AuditRecord> = test::call_and_read_body_json(
+        &app,
+        query("since=2999-01-01T00:00:00Z".to_string(), "admin-key"),
+    )
+    .await;
+    assert!(records.is_empty());
+
+    // Only admins read the audit log
+    let resp = test::call_service(&app, query(String::new(), "alice-key")).await;
+    assert_eq!(resp.status(), 403);
+}
+
+#[actix_web::test]
+async fn test_audit_log_file_and_retention() {
+    let dir = std::env::temp_dir().join(format!("audit-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("audit.ndjson");
+    let config = AuditConfig {
+        path: Some(path.to_string_lossy().into_owned()),
+        retention_days: 30,
+        max_records: 2,
+    };
+
+    // Key rotations are audited without revealing either key
+    let log = AuditLog::default();
+    log.open(&config).unwrap();
+    let mut old = Config::from_env().unwrap().api;
+    for key in ["first-key", "second-key", "third-key"] {
+        let mut new = old.clone();
+        new.api_key = Secret::new(key);
+        log.record_api_changes(&old, &new).unwrap();
+        old = new;
+    }
+    log.sync().unwrap();
+    let contents = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(contents.lines().count(), 3);
+    assert!(!contents.contains("second-key"));
+
+    // Reopening keeps only what retention allows
+    let reopened = AuditLog::default();
+    reopened.open(&config).unwrap();
+    let records = reopened.query(DEFAULT_TENANT, &AuditQuery::default());
+    assert_eq!(records.iter().map(|r| r.seq).collect::<Vec<_>>(), [2, 3]);
+    assert_eq!(records[0].operation, "config.api_keys");
+    assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[actix_web::test]
+async fn test_task_revisions_diff_and_restore() {
+    let mut config = Config::from_env().unwrap();
+    config.api.api_key = Secret::new("admin-key");
+    config.api.principals.extend([tenant_principal(
+        "viewer",
+        "viewer-key",
+        Role::Viewer,
+        DEFAULT_TENANT,
+    )]);
+
+    let limits = config.limits.clone();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState::new()))
+            .wrap(ApiKeyAuth::new(ConfigHandle::new(
+                config,
+                ConfigSources::default(),
+            )))
+            .configure(|cfg| configure_routes(cfg, &limits)),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/tasks")
+        .insert_header(("X-API-Key", "admin-key"))
+        .set_json(json!({"title": "Draft", "description": "first"}))
+        .to_request();
+    let task: Task = test::call_and_read_body_json(&app, req).await;
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/v1/tasks/{}", task.id))
+        .insert_header(("X-API-Key", "admin-key"))
+        .set_json(json!({"title": "Final", "completed": true}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    let get = |path: String, key: &'static str| {
+        test::TestRequest::get()
+            .uri(&format!("/api/v1/tasks/{}/revisions{}", task.id, path))
+            .insert_header(("X-API-Key", key))
+            .to_request()
+    };
+    let revisions: Vec<RevisionSummary> =
+        test::call_and_read_body_json(&app, get(String::new(), "viewer-key")).await;
+    assert_eq!(
+        revisions.iter().map(|r| r.number).collect::<Vec<_>>(),
+        [1, 2]
+    );
+    assert_eq!(revisions[0].author, "admin");
+    let first: Revision =
+        test::call_and_read_body_json(&app, get("/1".to_string(), "viewer-key")).await;
+    assert_eq!(first.task.title, "Draft");
+    let resp = test::call_service(&app, get("/9".to_string(), "viewer-key")).await;
+    assert_eq!(resp.status(), 404);
+
+    let diff: RevisionDiff =
+        test::call_and_read_body_json(&app, get("/diff?from=1&to=2".to_string(), "viewer-key"))
+            .await;
+    let fields: Vec<_> = diff.changes.iter().map(|c| c.field.as_str()).collect();
+    assert!(fields.contains(&"title") && fields.contains(&"completed"));
+    assert!(!fields.contains(&"description"));
+
+    // Restoring needs update rights and produces a new revision
+    let restore = |key: &'static str| {
+        test::TestRequest::post()
+            .uri(&format!("/api/v1/tasks/{}/revisions/1:restore", task.id))
+            .insert_header(("X-API-Key", key))
+            .to_request()
+    };
+    assert_eq!(
+        test::call_service(&app, restore("viewer-key"))
+            .await
+            .status(),
+        403
+    );
+    let restored: Task = test::call_and_read_body_json(&app, restore("admin-key")).await;
+    assert_eq!(
+        (restored.title.as_str(), restored.completed),
+        ("Draft", false)
+    );
+    let revisions: Vec<RevisionSummary> =
+        test::call_and_read_body_json(&app, get(String::new(), "admin-key")).await;
+    assert_eq!(revisions.len(), 3);
+
+    // Deleting a task drops its history
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/tasks/{}", task.id))
+        .insert_header(("X-API-Key", "admin-key"))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 204);
+    assert_eq!(
+        test::call_service(&app, get(String::new(), "admin-key"))
+            .await
+            .status(),
+        404
+    );
+}
+
+#[actix_web::test]
+async fn test_revision_retention() {
+    let state = AppState::new();
+    state.tenants.set_revision_retention(RevisionsConfig {
+        max_count: 2,
+        max_age_days: 0,
+    });
+    let tenant = state.tenants.get(DEFAULT_TENANT).unwrap();
+
+    let mut task = Task::new("v1".to_string(), None);
+    for title in ["v1", "v2", "v3"] {
+        task.update(Some(title.to_string()), None, None);
+        tenant.revisions.record(&task, "admin");
+    }
+    let numbers: Vec<_> = tenant
+        .revisions
+        .list(&task.id)
+        .iter()
+        .map(|r| r.number)
+        .collect();
+    assert_eq!(numbers, [2, 3]);
+    assert!(tenant.revisions.get(&task.id, 1).is_none());
+    assert_eq!(tenant.revisions.get(&task.id, 3).unwrap().task.title, "v3");
+}
+
+#[actix_web::test]
+async fn test_trash_restore_and_purge() {
+    let mut config = Config::from_env().unwrap();
+    config.api.api_key = Secret::new("admin-key");
+    config.api.principals.extend([tenant_principal(
+        "alice",
+        "alice-key",
+        Role::Member,
+        DEFAULT_TENANT,
+    )]);
+
+    let limits = config.limits.clone();
+    let state = AppState::new();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .wrap(ApiKeyAuth::new(ConfigHandle::new(
+                config,
+                ConfigSources::default(),
+            )))
+            .configure(|cfg| configure_routes(cfg, &limits)),
+    )
+    .await;
+
+    let call = |method: test::TestRequest, uri: String, key: &'static str| {
+        method
+            .uri(&uri)
+            .insert_header(("X-API-Key", key))
+            .to_request()
+    };
+    let req = test::TestRequest::post()
+        .uri("/api/v1/tasks")
+        .insert_header(("X-API-Key", "alice-key"))
+        .set_json(json!({"title": "Old"}))
+        .to_request();
+    let task: Task = test::call_and_read_body_json(&app, req).await;
+    let uri = format!("/api/v1/tasks/{}", task.id);
+
+    // Deleting moves the task to the trash, out of normal lookups
+    let resp = test::call_service(
+        &app,
+        call(test::TestRequest::delete(), uri.clone(), "alice-key"),
+    )
+    .await;
+    assert_eq!(resp.status(), 204);
+    let resp = test::call_service(
+        &app,
+        call(test::TestRequest::get(), uri.clone(), "alice-key"),
+    )
+    .await;
+    assert_eq!(resp.status(), 404);
+    let trashed: Vec<Task> = test::call_and_read_body_json(
+        &app,
+        call(
+            test::TestRequest::get(),
+            "/api/v1/trash".to_string(),
+            "alice-key",
+        ),
+    )
+    .await;
+    assert_eq!(trashed.len(), 1);
+    assert!(trashed[0].deleted_at.is_some());
+
+    let restore = format!("{}:restore", uri);
+    let restored: TaskView = test::call_and_read_body_json(
+        &app,
+        call(test::TestRequest::post(), restore.clone(), "alice-key"),
+    )
+    .await;
+    assert!(restored.task.deleted_at.is_none() && !restored.blocked);
+    let revisions: Vec<RevisionSummary> = test::call_and_read_body_json(
+        &app,
+        call(
+            test::TestRequest::get(),
+            format!("{}/revisions", uri),
+            "alice-key",
+        ),
+    )
+    .await;
+    assert_eq!(
+        revisions.iter().map(|r| r.number).collect::<Vec<_>>(),
+        [1, 2]
+    );
+    assert_eq!(revisions[1].author, "alice");
+    let resp = test::call_service(
+        &app,
+        call(test::TestRequest::get(), uri.clone(), "alice-key"),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let resp =
+        test::call_service(&app, call(test::TestRequest::post(), restore, "alice-key")).await;
+    assert_eq!(resp.status(), 404);
+
+    // Hard deletes and purges are for admins only
+    let hard = format!("{}?hard=true", uri);
+    let resp = test::call_service(
+        &app,
+        call(test::TestRequest::delete(), hard.clone(), "alice-key"),
+    )
+    .await;
+    assert_eq!(resp.status(), 403);
+    let resp = test::call_service(
+        &app,
+        call(test::TestRequest::delete(), uri.clone(), "alice-key"),
+    )
+    .await;
+    assert_eq!(resp.status(), 204);
+    let purge = format!("/api/v1/trash/{}", task.id);
+    let resp = test::call_service(
+        &app,
+        call(test::TestRequest::delete(), purge.clone(), "alice-key"),
+    )
+    .await;
+    assert_eq!(resp.status(), 403);
+    let resp =
+        test::call_service(&app, call(test::TestRequest::delete(), purge, "admin-key")).await;
+    assert_eq!(resp.status(), 204);
+
+    let tenant = state.tenants.get(DEFAULT_TENANT).unwrap();
+    assert!(tenant.trash.is_empty());
+    assert_eq!(tenant.usage.stored_bytes(), 0);
+    assert!(tenant.revisions.list(&task.id).is_empty());
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/tasks")
+        .insert_header(("X-API-Key", "admin-key"))
+        .set_json(json!({"title": "Gone"}))
+        .to_request();
+    let task: Task = test::call_and_read_body_json(&app, req).await;
+    let resp = test::call_service(
+        &app,
+        call(
+            test::TestRequest::delete(),
+            format!("/api/v1/tasks/{}?hard=true", task.id),
+            "admin-key",
+        ),
+    )
+    .await;
+    assert_eq!(resp.status(), 204);
+    assert!(tenant.tasks.is_empty() && tenant.trash.is_empty());
+}
+
+#[actix_web::test]
+async fn test_trash_expires_after_retention() {
+    let state = AppState::new();
+    let tenant = state.tenants.get(DEFAULT_TENANT).unwrap();
+    let mut old = Task::new("old".to_string(), None);
+    old.deleted_at = Some(chrono::Utc::now() - chrono::Duration::days(40));
+    let mut recent = Task::new("recent".to_string(), None);
+    recent.deleted_at = Some(chrono::Utc::now());
+    tenant.trash.insert(old.id, old.clone());
+    tenant.trash.insert(recent.id, recent.clone());
+
+    let cutoff = chrono::Utc::now() - chrono::Duration::days(30);
+    assert_eq!(trash::purge_expired(&tenant, cutoff, &state.audit), 1);
+    assert!(!tenant.trash.contains_key(&old.id));
+    assert!(tenant.trash.contains_key(&recent.id));
+
+    let records = state.audit.query(DEFAULT_TENANT, &AuditQuery::default());
+    assert_eq!(records.len(), 1);
+    assert_eq!(
+        (records[0].operation.as_str(), records[0].principal.as_str()),
+        ("task.purge", "system")
+    );
+}
+
+#[actix_web::test]
+async fn test_due_dates_priorities_and_listing_filters() {
+    let app_state = AppState::new();
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(as_admin))
+            .app_data(web::Data::new(app_state))
+            .route(
+                "/api/v1/tasks",
+                web::get().to(rust_high_performance_api_server::handlers::get_tasks),
+            )
+            .route(
+                "/api/v1/tasks",
+                web::post().to(rust_high_performance_api_server::handlers::create_task),
+            )
+            .route(
+                "/api/v1/tasks/{id}",
+                web::put().to(rust_high_performance_api_server::handlers::update_task),
+            ),
+    )
+    .await;
+
+    let now = chrono::Utc::now();
+    let create = |body: serde_json::Value| {
+        test::TestRequest::post()
+            .uri("/api/v1/tasks")
+            .set_json(body)
+            .to_request()
+    };
+    let late: Task = test::call_and_read_body_json(
+        &app,
+        create(json!({"title": "Late", "due_at": now - chrono::Duration::days(1), "priority": "urgent"})),
+    )
+    .await;
+    let soon: Task = test::call_and_read_body_json(
+        &app,
+        create(json!({
+            "title": "Soon",
+            "due_at": now + chrono::Duration::days(1),
+            "priority": "low",
+            "reminders": [now + chrono::Duration::hours(2), now + chrono::Duration::hours(1)],
+        })),
+    )
+    .await;
+    test::call_service(&app, create(json!({"title": "Someday"}))).await;
+    assert_eq!(late.priority, Priority::Urgent);
+    assert!(soon.reminders[0].at < soon.reminders[1].at);
+
+    // Invalid scheduling input is rejected
+    let resp =
+        test::call_service(&app, create(json!({"title": "x", "priority": "critical"}))).await;
+    assert_eq!(resp.status(), 400);
+    let resp = test::call_service(
+        &app,
+        create(json!({"title": "x", "reminders": [now - chrono::Duration::hours(1)]})),
+    )
+    .await;
+    assert_eq!(resp.status(), 400);
+
+    let list = |query: &str| {
+        test::TestRequest::get()
+            .uri(&format!("/api/v1/tasks?{}", query))
+            .to_request()
+    };
+    let titles = |tasks: Vec<Task>| tasks.into_iter().map(|t| t.title).collect::<Vec<_>>();
+    let overdue: Vec<Task> = test::call_and_read_body_json(&app, list("overdue=true")).await;
+    assert_eq!(titles(overdue), ["Late"]);
+    let by_due: Vec<Task> = test::call_and_read_body_json(&app, list("sort=due_at")).await;
+    assert_eq!(titles(by_due), ["Late", "Soon", "Someday"]);
+    let by_priority: Vec<Task> =
+        test::call_and_read_body_json(&app, list("sort=priority&order=desc")).await;
+    assert_eq!(titles(by_priority), ["Late", "Someday", "Soon"]);
+    let low: Vec<Task> = test::call_and_read_body_json(&app, list("priority=low")).await;
+    assert_eq!(titles(low), ["Soon"]);
+
+    // Completing or clearing the due date ends the overdue state
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/v1/tasks/{}", late.id))
+        .set_json(json!({"due_at": null}))
+        .to_request();
+    let updated: Task = test::call_and_read_body_json(&app, req).await;
+    assert!(updated.due_at.is_none());
+    assert_eq!(updated.priority, Priority::Urgent);
+    let overdue: Vec<Task> = test::call_and_read_body_json(&app, list("overdue=true")).await;
+    assert!(overdue.is_empty());
+}
+
+#[actix_web::test]
+async fn test_reminders_fire_once_including_missed_ones() {
+    let state = AppState::new();
+    let tenant = state.tenants.get(DEFAULT_TENANT).unwrap();
+    let now = chrono::Utc::now();
+
+    // A reminder that came due while the server was down, and one still pending
+    let mut task = Task::new("Call back".to_string(), None);
+    task.set_reminders(vec![
+        now - chrono::Duration::hours(3),
+        now + chrono::Duration::hours(1),
+    ]);
+    let mut done = Task::new("Done".to_string(), None);
+    done.completed = true;
+    done.set_reminders(vec![now - chrono::Duration::hours(1)]);
+    tenant.tasks.insert(task.id, task.clone());
+    tenant.tasks.insert(done.id, done);
+
+    let events = reminders::fire_due(&tenant, now);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].task_id, task.id);
+    assert!(reminders::fire_due(&tenant, now).is_empty());
+
+    let stored = tenant.tasks.get(&task.id).unwrap().clone();
+    assert!(stored.reminders[0].fired_at.is_some());
+    assert!(stored.reminders[1].fired_at.is_none());
+    assert_eq!(
+        reminders::fire_due(&tenant, now + chrono::Duration::hours(2)).len(),
+        1
+    );
+}
+
+#[actix_web::test]
+async fn test_rrule_occurrences_across_dst() {
+    let at = |s: &str| s.parse::<chrono::DateTime<chrono::Utc>>().unwrap();
+    let occurrences = |rule: &str, start: &str, tz: chrono_tz::Tz| {
+        let rule: RRule = rule.parse().unwrap();
+        rule.occurrences(at(start), tz).take(3).collect::<Vec<_>>()
+    };
+    let new_york = chrono_tz::America::New_York;
+
+    // 09:00 local stays 09:00 when DST starts on 8 March 2026
+    assert_eq!(
+        occurrences("FREQ=DAILY;COUNT=5", "2026-03-07T14:00:00Z", new_york),
+        [
+            at("2026-03-07T14:00:00Z"),
+            at("2026-03-08T13:00:00Z"),
+            at("2026-03-09T13:00:00Z")
+        ]
+    );
+    // 02:30 does not exist on 8 March and moves forward to 03:30
+    assert_eq!(
+        occurrences("FREQ=DAILY", "2026-03-07T07:30:00Z", new_york),
+        [
+            at("2026-03-07T07:30:00Z"),
+            at("2026-03-08T07:30:00Z"),
+            at("2026-03-09T06:30:00Z")
+        ]
+    );
+    // 01:30 happens twice on 1 November; the first one is used
+    assert_eq!(
+        occurrences("FREQ=DAILY", "2026-10-31T05:30:00Z", new_york),
+        [
+            at("2026-10-31T05:30:00Z"),
+            at("2026-11-01T05:30:00Z"),
+            at("2026-11-02T06:30:00Z")
+        ]
+    );
+
+    assert_eq!(
+        occurrences(
+            "FREQ=MONTHLY;BYDAY=-1FR",
+            "2026-01-30T12:00:00Z",
+            chrono_tz::UTC
+        ),
+        [
+            at("2026-01-30T12:00:00Z"),
+            at("2026-02-27T12:00:00Z"),
+            at("2026-03-27T12:00:00Z")
+        ]
+    );
+    assert_eq!(
+        occurrences(
+            "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,WE",
+            "2026-01-07T08:00:00Z",
+            chrono_tz::UTC
+        ),
+        [
+            at("2026-01-07T08:00:00Z"),
+            at("2026-01-19T08:00:00Z"),
+            at("2026-01-21T08:00:00Z")
+        ]
+    );
+    assert_eq!(
+        occurrences("FREQ=DAILY;COUNT=2", "2026-01-01T00:00:00Z", chrono_tz::UTC).len(),
+        2
+    );
+    assert_eq!(
+        occurrences(
+            "FREQ=DAILY;UNTIL=20260102",
+            "2026-01-01T09:00:00Z",
+            chrono_tz::UTC
+        )
+        .len(),
+        2
+    );
+
+    let rule: RRule = "freq=weekly;byday=MO,WE;interval=2".parse().unwrap();
+    assert_eq!(rule.to_string(), "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,WE");
+    assert!("FREQ=HOURLY".parse::<RRule>().is_err());
+    assert!("FREQ=DAILY;BYSETPOS=1".parse::<RRule>().is_err());
+    assert!("FREQ=WEEKLY;BYDAY=1MO".parse::<RRule>().is_err());
+}
+
+#[actix_web::test]
+async fn test_recurring_tasks_and_series_edits() {
+    let mut config = Config::from_env().unwrap();
+    config.api.api_key = Secret::new("admin-key");
+    let limits = config.limits.clone();
+    let state = AppState::new();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .wrap(ApiKeyAuth::new(ConfigHandle::new(
+                config,
+                ConfigSources::default(),
+            )))
+            .configure(|cfg| configure_routes(cfg, &limits)),
+    )
+    .await;
+    let send = |req: test::TestRequest| req.insert_header(("X-API-Key", "admin-key")).to_request();
+    let complete = |id: uuid::Uuid| {
+        send(
+            test::TestRequest::put()
+                .uri(&format!("/api/v1/tasks/{}", id))
+                .set_json(json!({"completed": true})),
+        )
+    };
+    let open_occurrence = || {
+        state
+            .default_tasks()
+            .iter()
+            .filter(|entry| !entry.completed)
+            .map(|entry| entry.value().clone())
+            .collect::<Vec<_>>()
+    };
+
+    // 09:00 in Berlin, the day before summer time starts on 31 March 2030
+    let req = send(
+        test::TestRequest::post()
+            .uri("/api/v1/tasks")
+            .set_json(json!({
+                "title": "Standup",
+                "due_at": "2030-03-30T08:00:00Z",
+                "reminders": ["2030-03-30T07:45:00Z"],
+                "recurrence": {"rule": "FREQ=DAILY;COUNT=3", "timezone": "Europe/Berlin"},
+            })),
+    );
+    let first: Task = test::call_and_read_body_json(&app, req).await;
+    let series_id = first.series_id.unwrap();
+    let resp = test::call_service(
+        &app,
+        send(
+            test::TestRequest::post()
+                .uri("/api/v1/tasks")
+                .set_json(json!({
+                    "title": "No date",
+                    "recurrence": {"rule": "FREQ=DAILY", "timezone": "Europe/Berlin"},
+                })),
+        ),
+    )
+    .await;
+    assert_eq!(resp.status(), 400);
+
+    // Completing an occurrence creates the next one at 09:00 summer time
+    assert_eq!(
+        test::call_service(&app, complete(first.id)).await.status(),
+        200
+    );
+    let open = open_occurrence();
+    assert_eq!(open.len(), 1);
+    let second = open[0].clone();
+    assert_eq!(
+        (second.series_id, second.due_at),
+        (
+            Some(series_id),
+            Some("2030-03-31T07:00:00Z".parse().unwrap())
+        )
+    );
+    assert_eq!(
+        second.reminders[0].at,
+        "2030-03-31T06:45:00Z"
+            .parse::<chrono::DateTime<chrono::Utc>>()
+            .unwrap()
+    );
+
+    // Editing this occurrence leaves the series alone; editing the series
+    // updates it and the open occurrences from here on
+    let req = send(
+        test::TestRequest::put()
+            .uri(&format!("/api/v1/tasks/{}", second.id))
+            .set_json(json!({"title": "Standup (moved)"})),
+    );
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    let view: SeriesView = test::call_and_read_body_json(
+        &app,
+        send(test::TestRequest::get().uri(&format!("/api/v1/tasks/{}/series", second.id))),
+    )
+    .await;
+    assert_eq!(view.series.title, "Standup");
+    let req = send(
+        test::TestRequest::put()
+            .uri(&format!("/api/v1/tasks/{}/series", second.id))
+            .set_json(json!({"title": "Daily sync", "priority": "high"})),
+    );
+    let view: SeriesView = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(view.series.title, "Daily sync");
+    assert_eq!(open_occurrence()[0].title, "Daily sync");
+
+    assert_eq!(
+        test::call_service(&app, complete(second.id)).await.status(),
+        200
+    );
+    let third = open_occurrence()[0].clone();
+    assert_eq!(
+        (third.title.as_str(), third.priority),
+        ("Daily sync", Priority::High)
+    );
+
+    // COUNT=3 ends the series
+    assert_eq!(
+        test::call_service(&app, complete(third.id)).await.status(),
+        200
+    );
+    assert!(open_occurrence().is_empty());
+    let resp = test::call_service(
+        &app,
+        send(
+            test::TestRequest::get().uri(&format!("/api/v1/tasks/{}/series", uuid::Uuid::new_v4())),
+        ),
+    )
+    .await;
+    assert_eq!(resp.status(), 404);
+}
+
+#[actix_web::test]
+async fn test_subtasks_progress_and_moves() {
+    let state = AppState::new();
+    let limits = Config::from_env().unwrap().limits;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .wrap(from_fn(as_admin))
+            .configure(|cfg| configure_routes(cfg, &limits)),
+    )
+    .await;
+
+    let create = |body: serde_json::Value| {
+        test::TestRequest::post()
+            .uri("/api/v1/tasks")
+            .set_json(body)
+            .to_request()
+    };
+    let root: Task = test::call_and_read_body_json(&app, create(json!({"title": "Release"}))).await;
+    let docs: Task =
+        test::call_and_read_body_json(&app, create(json!({"title": "Docs", "parent_id": root.id})))
+            .await;
+    let code: Task =
+        test::call_and_read_body_json(&app, create(json!({"title": "Code", "parent_id": root.id})))
+            .await;
+    let tests: Task = test::call_and_read_body_json(
+        &app,
+        create(json!({"title": "Tests", "parent_id": code.id})),
+    )
+    .await;
+    assert_eq!(tests.parent_id, Some(code.id));
+    let resp = test::call_service(
+        &app,
+        create(json!({"title": "Stray", "parent_id": uuid::Uuid::new_v4()})),
+    )
+    .await;
+    assert_eq!(resp.status(), 404);
+
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/v1/tasks/{}", docs.id))
+        .set_json(json!({"completed": true}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/tasks/{}/children", root.id))
+        .to_request();
+    let children: Vec<Task> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        children.iter().map(|t| t.id).collect::<Vec<_>>(),
+        vec![docs.id, code.id]
+    );
+
+    // One of three descendants is done
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/tasks/{}/subtree", root.id))
+        .to_request();
+    let tree: TaskNode = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(tree.progress, Some(33));
+    assert_eq!(tree.children[0].progress, None);
+    assert_eq!(tree.children[1].progress, Some(0));
+    assert_eq!(tree.children[1].children[0].task.id, tests.id);
+
+    let parent = |id: uuid::Uuid, parent: serde_json::Value| {
+        test::TestRequest::put()
+            .uri(&format!("/api/v1/tasks/{}/parent", id))
+            .set_json(json!({"parent_id": parent}))
+            .to_request()
+    };
+    // A task cannot move under itself or one of its descendants
+    assert_eq!(
+        test::call_service(&app, parent(root.id, json!(root.id)))
+            .await
+            .status(),
+        409
+    );
+    assert_eq!(
+        test::call_service(&app, parent(root.id, json!(tests.id)))
+            .await
+            .status(),
+        409
+    );
+
+    let moved: Task = test::call_and_read_body_json(&app, parent(tests.id, json!(docs.id))).await;
+    assert_eq!(moved.parent_id, Some(docs.id));
+    let moved: Task = test::call_and_read_body_json(&app, parent(code.id, json!(null))).await;
+    assert_eq!(moved.parent_id, None);
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/tasks/{}/subtree", root.id))
+        .to_request();
+    let tree: TaskNode = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(tree.children.len(), 1);
+    assert_eq!(tree.progress, Some(50));
+}
+
+#[actix_web::test]
+async fn test_subtask_delete_modes() {
+    let state = AppState::new();
+    let limits = Config::from_env().unwrap().limits;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .wrap(from_fn(as_admin))
+            .configure(|cfg| configure_routes(cfg, &limits)),
+    )
+    .await;
+
+    let create = |body: serde_json::Value| {
+        test::TestRequest::post()
+            .uri("/api/v1/tasks")
+            .set_json(body)
+            .to_request()
+    };
+    let delete = |id: uuid::Uuid, query: &str| {
+        test::TestRequest::delete()
+            .uri(&format!("/api/v1/tasks/{}{}", id, query))
+            .to_request()
+    };
+    let root: Task = test::call_and_read_body_json(&app, create(json!({"title": "Root"}))).await;
+    let child: Task = test::call_and_read_body_json(
+        &app,
+        create(json!({"title": "Child", "parent_id": root.id})),
+    )
+    .await;
+    let grandchild: Task = test::call_and_read_body_json(
+        &app,
+        create(json!({"title": "Grandchild", "parent_id": child.id})),
+    )
+    .await;
+    let tenant = state.tenants.get(DEFAULT_TENANT).unwrap();
+
+    assert_eq!(
+        test::call_service(&app, delete(root.id, "?subtasks=forbid"))
+            .await
+            .status(),
+        409
+    );
+    assert!(tenant.tasks.contains_key(&root.id));
+
+    // Orphaning moves the direct subtasks to the top level
+    assert_eq!(
+        test::call_service(&app, delete(root.id, "")).await.status(),
+        204
+    );
+    assert_eq!(tenant.tasks.get(&child.id).unwrap().parent_id, None);
+    assert_eq!(
+        tenant.tasks.get(&grandchild.id).unwrap().parent_id,
+        Some(child.id)
+    );
+
+    // Cascading trashes every descendant, and restoring keeps the links
+    assert_eq!(
+        test::call_service(&app, delete(child.id, "?subtasks=cascade"))
+            .await
+            .status(),
+        204
+    );
+    assert!(tenant.tasks.is_empty());
+    assert_eq!(tenant.trash.len(), 3);
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/v1/tasks/{}:restore", grandchild.id))
+        .to_request();
+    let restored: Task = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(restored.parent_id, None);
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/v1/tasks/{}:restore", child.id))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    assert_eq!(
+        test::call_service(&app, delete(child.id, "?subtasks=cascade&hard=true"))
+            .await
+            .status(),
+        204
+    );
+    assert!(tenant.tasks.contains_key(&grandchild.id));
+    assert!(!tenant.trash.contains_key(&child.id));
+}
+
+#[actix_web::test]
+async fn test_task_dependencies_block_completion() {
+    let state = AppState::new();
+    let limits = Config::from_env().unwrap().limits;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .wrap(from_fn(as_admin))
+            .configure(|cfg| configure_routes(cfg, &limits)),
+    )
+    .await;
+
+    let create = |body: serde_json::Value| {
+        test::TestRequest::post()
+            .uri("/api/v1/tasks")
+            .set_json(body)
+            .to_request()
+    };
+    let depend = |id: uuid::Uuid, on: uuid::Uuid| {
+        test::TestRequest::post()
+            .uri(&format!("/api/v1/tasks/{}/dependencies", id))
+            .set_json(json!({"task_id": on}))
+            .to_request()
+    };
+    let complete = |id: uuid::Uuid| {
+        test::TestRequest::put()
+            .uri(&format!("/api/v1/tasks/{}", id))
+            .set_json(json!({"completed": true}))
+            .to_request()
+    };
+    let design: TaskView =
+        test::call_and_read_body_json(&app, create(json!({"title": "Design"}))).await;
+    let build: TaskView = test::call_and_read_body_json(
+        &app,
+        create(json!({"title": "Build", "blocked_by": [design.task.id]})),
+    )
+    .await;
+    assert!(build.blocked);
+    let ship: TaskView =
+        test::call_and_read_body_json(&app, create(json!({"title": "Ship"}))).await;
+    let ship: TaskView =
+        test::call_and_read_body_json(&app, depend(ship.task.id, build.task.id)).await;
+    assert_eq!(ship.task.blocked_by, vec![build.task.id]);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/tasks/{}", build.task.id))
+        .to_request();
+    let build: TaskView = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(build.blocking, vec![ship.task.id]);
+
+    // Edges closing a loop are rejected, however long the loop
+    assert_eq!(
+        test::call_service(&app, depend(design.task.id, ship.task.id))
+            .await
+            .status(),
+        409
+    );
+    assert_eq!(
+        test::call_service(&app, depend(design.task.id, design.task.id))
+            .await
+            .status(),
+        409
+    );
+    assert_eq!(
+        test::call_service(&app, depend(design.task.id, uuid::Uuid::new_v4()))
+            .await
+            .status(),
+        404
+    );
+
+    let resp = test::call_service(&app, complete(build.task.id)).await;
+    assert_eq!(resp.status(), 409);
+    let done: TaskView = test::call_and_read_body_json(&app, complete(design.task.id)).await;
+    assert_eq!(done.blocking, vec![build.task.id]);
+    let built: TaskView = test::call_and_read_body_json(&app, complete(build.task.id)).await;
+    assert!(!built.blocked && built.task.completed);
+
+    let undepend = |id: uuid::Uuid, on: uuid::Uuid| {
+        test::TestRequest::delete()
+            .uri(&format!("/api/v1/tasks/{}/dependencies/{}", id, on))
+            .to_request()
+    };
+    let ship: TaskView =
+        test::call_and_read_body_json(&app, undepend(ship.task.id, build.task.id)).await;
+    assert!(ship.task.blocked_by.is_empty());
+    assert_eq!(
+        test::call_service(&app, undepend(ship.task.id, build.task.id))
+            .await
+            .status(),
+        404
+    );
+
+    // Trashed tasks leave the reverse edges until they are restored
+    let get = |id: uuid::Uuid| {
+        test::TestRequest::get()
+            .uri(&format!("/api/v1/tasks/{}", id))
+            .to_request()
+    };
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/tasks/{}", build.task.id))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 204);
+    let design: TaskView = test::call_and_read_body_json(&app, get(design.task.id)).await;
+    assert!(design.blocking.is_empty());
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/v1/tasks/{}:restore", build.task.id))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    let design: TaskView = test::call_and_read_body_json(&app, get(design.task.id)).await;
+    assert_eq!(design.blocking, vec![build.task.id]);
+
+    // Restoring a completed revision is checked like completing the task
+    let reopen = |id: uuid::Uuid| {
+        test::TestRequest::put()
+            .uri(&format!("/api/v1/tasks/{}", id))
+            .set_json(json!({"completed": false}))
+            .to_request()
+    };
+    test::call_service(&app, reopen(design.task.id)).await;
+    test::call_service(&app, reopen(build.task.id)).await;
+    let restore = || {
+        test::TestRequest::post()
+            .uri(&format!(
+                "/api/v1/tasks/{}/revisions/2:restore",
+                build.task.id
+            ))
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, restore()).await.status(), 409);
+    test::call_service(&app, complete(design.task.id)).await;
+    let restored: TaskView = test::call_and_read_body_json(&app, restore()).await;
+    assert!(restored.task.completed && !restored.blocked);
+}
+
+#[actix_web::test]
+async fn test_dependency_plan_and_critical_path() {
+    let state = AppState::new();
+    let limits = Config::from_env().unwrap().limits;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .wrap(from_fn(as_admin))
+            .configure(|cfg| configure_routes(cfg, &limits)),
+    )
+    .await;
+
+    let create = |body: serde_json::Value| {
+        test::TestRequest::post()
+            .uri("/api/v1/tasks")
+            .set_json(body)
+            .to_request()
+    };
+    let a: TaskView = test::call_and_read_body_json(&app, create(json!({"title": "A"}))).await;
+    let b: TaskView = test::call_and_read_body_json(
+        &app,
+        create(json!({"title": "B", "blocked_by": [a.task.id]})),
+    )
+    .await;
+    let c: TaskView = test::call_and_read_body_json(
+        &app,
+        create(json!({"title": "C", "blocked_by": [b.task.id]})),
+    )
+    .await;
+    let d: TaskView = test::call_and_read_body_json(
+        &app,
+        create(json!({"title": "D", "blocked_by": [a.task.id]})),
+    )
+    .await;
+    let (a, b, c, d) = (a.task, b.task, c.task, d.task);
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/tasks/plan")
+        .to_request();
+    let plan: Plan = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(plan.order, vec![a.id, b.id, c.id, d.id]);
+    assert_eq!(plan.critical_path, vec![a.id, b.id, c.id]);
+
+    // Only edges inside the selection count
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/tasks/plan?ids={},{}", d.id, c.id))
+        .to_request();
+    let plan: Plan = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(plan.order, vec![c.id, d.id]);
+    assert_eq!(plan.critical_path, vec![c.id]);
+    let req = test::TestRequest::get()
+        .uri("/api/v1/tasks/plan?ids=nope")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+
+    // Imported tasks can carry a cycle the API would have refused
+    let mut looped = vec![a.clone(), b.clone()];
+    looped[0].blocked_by = vec![b.id];
+    assert!(Plan::new(&looped).is_err());
+}
+
+#[actix_web::test]
+async fn test_labels_and_tag_queries() {
+    let state = AppState::new();
+    let limits = Config::from_env().unwrap().limits;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .wrap(from_fn(as_admin))
+            .configure(|cfg| configure_routes(cfg, &limits)),
+    )
+    .await;
+
+    let create = |body: serde_json::Value| {
+        test::TestRequest::post()
+            .uri("/api/v1/tasks")
+            .set_json(body)
+            .to_request()
+    };
+    let login: Task = test::call_and_read_body_json(
+        &app,
+        create(json!({"title": "Login", "labels": ["Team  Backend", "api", "API"]})),
+    )
+    .await;
+    assert_eq!(login.labels, vec!["api", "team-backend"]);
+    let theme: Task = test::call_and_read_body_json(
+        &app,
+        create(json!({"title": "Theme", "labels": ["team-frontend", "ui"]})),
+    )
+    .await;
+    let docs: Task =
+        test::call_and_read_body_json(&app, create(json!({"title": "Docs", "labels": ["api"]})))
+            .await;
+    let resp = test::call_service(&app, create(json!({"title": "Bad", "labels": ["no;pe"]}))).await;
+    assert_eq!(resp.status(), 400);
+
+    let titles = |uri: &str| {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        let app = &app;
+        async move {
+            let tasks: Vec<Task> = test::call_and_read_body_json(app, req).await;
+            let mut titles: Vec<String> = tasks.into_iter().map(|task| task.title).collect();
+            titles.sort();
+            titles
+        }
+    };
+    assert_eq!(
+        titles("/api/v1/tasks?labels_all=api,team-backend").await,
+        vec!["Login"]
+    );
+    assert_eq!(
+        titles("/api/v1/tasks?labels_any=ui,TEAM-BACKEND").await,
+        vec!["Login", "Theme"]
+    );
+    assert_eq!(titles("/api/v1/tasks?labels_none=api").await, vec!["Theme"]);
+    assert_eq!(
+        titles("/api/v1/tasks?labels_any=api&labels_none=team-backend").await,
+        vec!["Docs"]
+    );
+    assert!(titles("/api/v1/tasks?labels_all=api,missing")
+        .await
+        .is_empty());
+
+    // Label operations change only the labels, and the index follows
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/v1/tasks/{}/labels", theme.id))
+        .set_json(json!({"add": ["api", "Design"], "remove": ["ui"]}))
+        .to_request();
+    let changed: Task = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(changed.labels, vec!["api", "design", "team-frontend"]);
+    assert_eq!(changed.title, "Theme");
+    assert_eq!(
+        titles("/api/v1/tasks?labels_all=api").await,
+        vec!["Docs", "Login", "Theme"]
+    );
+    assert!(titles("/api/v1/tasks?labels_any=ui").await.is_empty());
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/tasks/{}", docs.id))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 204);
+    assert_eq!(
+        titles("/api/v1/tasks?labels_all=api").await,
+        vec!["Login", "Theme"]
+    );
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/v1/tasks/{}:restore", docs.id))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    assert_eq!(
+        titles("/api/v1/tasks?labels_all=api").await,
+        vec!["Docs", "Login", "Theme"]
+    );
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/tasks?labels_all=a;b")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+}
+
+#[actix_web::test]
+async fn test_label_catalogue() {
+    let mut config = Config::from_env().unwrap();
+    config.api.api_key = Secret::new("admin-key");
+    config.api.principals.extend([tenant_principal(
+        "alice",
+        "alice-key",
+        Role::Member,
+        DEFAULT_TENANT,
+    )]);
+    let limits = config.limits.clone();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState::new()))
+            .wrap(ApiKeyAuth::new(ConfigHandle::new(
+                config,
+                ConfigSources::default(),
+            )))
+            .configure(|cfg| configure_routes(cfg, &limits)),
+    )
+    .await;
+
+    let put = |name: &str, body: serde_json::Value, key: &'static str| {
+        test::TestRequest::put()
+            .uri(&format!("/api/v1/labels/{}", name))
+            .insert_header(("X-API-Key", key))
+            .set_json(body)
+            .to_request()
+    };
+    let resp = test::call_service(
+        &app,
+        put("backend", json!({"color": "#1f6feb"}), "alice-key"),
+    )
+    .await;
+    assert_eq!(resp.status(), 403);
+    let resp = test::call_service(
+        &app,
+        put("Backend", json!({"color": "#1f6feb"}), "admin-key"),
+    )
+    .await;
+    assert_eq!(resp.status(), 201);
+    let label: Label = test::read_body_json(resp).await;
+    assert_eq!(label.name, "backend");
+    let resp = test::call_service(
+        &app,
+        put(
+            "backend",
+            json!({"description": "Server side"}),
+            "admin-key",
+        ),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let resp =
+        test::call_service(&app, put("backend", json!({"color": "blue"}), "admin-key")).await;
+    assert_eq!(resp.status(), 400);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/tasks")
+        .insert_header(("X-API-Key", "alice-key"))
+        .set_json(json!({"title": "Tagged", "labels": ["frontend"]}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 201);
+
+    // Labels used on tasks are listed even without a catalogue entry
+    let req = test::TestRequest::get()
+        .uri("/api/v1/labels")
+        .insert_header(("X-API-Key", "alice-key"))
+        .to_request();
+    let labels: Vec<LabelSummary> = test::call_and_read_body_json(&app, req).await;
+    let names: Vec<(&str, bool)> = labels
+        .iter()
+        .map(|label| (label.name.as_str(), label.catalogued))
+        .collect();
+    assert_eq!(names, vec![("backend", true), ("frontend", false)]);
+    assert_eq!(labels[0].description.as_deref(), Some("Server side"));
+
+    let delete = || {
+        test::TestRequest::delete()
+            .uri("/api/v1/labels/backend")
+            .insert_header(("X-API-Key", "admin-key"))
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, delete()).await.status(), 204);
+    assert_eq!(test::call_service(&app, delete()).await.status(), 404);
+}
+
+#[actix_web::test]
+async fn test_full_text_search() {
+    let state = AppState::new();
+    let limits = Config::from_env().unwrap().limits;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .wrap(from_fn(as_admin))
+            .configure(|cfg| configure_routes(cfg, &limits)),
+    )
+    .await;
+
+    let create = |title: &str, description: Option<&str>| {
+        test::TestRequest::post()
+            .uri("/api/v1/tasks")
+            .set_json(json!({"title": title, "description": description}))
+            .to_request()
+    };
+    let fix: Task = test::call_and_read_body_json(
+        &app,
+        create(
+            "Fix database migration",
+            Some("The migration script fails on large tables"),
+        ),
+    )
+    .await;
+    test::call_service(&app, create("Migrating users to the new database", None)).await;
+    test::call_service(
+        &app,
+        create("Café opening", Some("Prepare the <menu> for the CAFE")),
+    )
+    .await;
+    let notes: Task =
+        test::call_and_read_body_json(&app, create("Write release notes", None)).await;
+
+    let search = |q: &str| {
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/v1/tasks/search?q={}", urlencoding(q)))
+            .to_request();
+        let app = &app;
+        async move {
+            let results: SearchResults = test::call_and_read_body_json(app, req).await;
+            results
+        }
+    };
+
+    // Stemming matches other forms; more occurrences rank higher
+    let results = search("migrations").await;
+    assert_eq!(results.total, 2);
+    assert_eq!(results.hits[0].task.id, fix.id);
+    assert_eq!(
+        results.hits[0].highlight.title,
+        "Fix database <mark>migration</mark>"
+    );
+    assert!(results.hits[0].score > results.hits[1].score);
+
+    let results = search("cafe").await;
+    assert_eq!(results.hits[0].highlight.title, "<mark>Café</mark> opening");
+    assert_eq!(
+        results.hits[0].highlight.description.as_deref(),
+        Some("Prepare the &lt;menu&gt; for the <mark>CAFE</mark>")
+    );
+
+    assert_eq!(search("\"database migration\"").await.total, 1);
+    assert_eq!(search("database migration").await.total, 2);
+    assert_eq!(search("rele*").await.hits[0].task.id, notes.id);
+    assert_eq!(search("relaese~").await.hits[0].task.id, notes.id);
+    assert_eq!(search("relaese").await.total, 0);
+
+    // The index follows updates and deletes
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/v1/tasks/{}", notes.id))
+        .set_json(json!({"title": "Publish changelog"}))
+        .to_request();
+    test::call_service(&app, req).await;
+    assert_eq!(search("release").await.total, 0);
+    assert_eq!(search("changelog").await.total, 1);
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/tasks/{}", fix.id))
+        .to_request();
+    test::call_service(&app, req).await;
+    assert_eq!(search("migration").await.total, 1);
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/tasks/search?q=%20*%20")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+}
+
+fn urlencoding(q: &str) -> String {
+    q.bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' | b'_' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[actix_web::test]
+async fn test_search_index_rebuilds_from_storage() {
+    let words: Vec<String> = search::tokenize("Ｃａｆé-Déjà vu, naïve!")
+        .into_iter()
+        .map(|t| t.word)
+        .collect();
+    assert_eq!(words, vec!["cafe", "deja", "vu", "naive"]);
+    assert_eq!(
+        search::parse("fix \"load balancer\" deploy* timout~").unwrap(),
+        vec![
+            search::Clause::Term("fix".to_string()),
+            search::Clause::Phrase(vec!["load".to_string(), "balancer".to_string()]),
+            search::Clause::Prefix("deploy".to_string()),
+            search::Clause::Fuzzy("timout".to_string()),
+        ]
+    );
+
+    // Tasks already in storage are searchable once the indexes are rebuilt
+    let state = AppState::new();
+    let tenant = state.tenants.get(DEFAULT_TENANT).unwrap();
+    let mut task = Task::new("Rotate certificates".to_string(), None);
+    task.labels = vec!["ops".to_string()];
+    tenant.tasks.insert(task.id, task.clone());
+    assert!(tenant.search.is_empty());
+    state.tenants.rebuild_indexes();
+    let matches = tenant.search.search(&search::parse("certificate").unwrap());
+    assert_eq!(matches.scores.len(), 1);
+    assert_eq!(matches.scores[0].0, task.id);
+    assert!(tenant
+        .labels
+        .select(&["ops".to_string()], &[])
+        .unwrap()
+        .contains(&task.id));
+}
+
+fn comments_app_config() -> Config {
+    let mut config = Config::from_env().unwrap();
+    config.api.api_key = Secret::new("admin-key");
+    config.api.principals.extend([
+        tenant_principal("alice", "alice-key", Role::Member, DEFAULT_TENANT),
+        tenant_principal("bob", "bob-key", Role::Member, DEFAULT_TENANT),
+    ]);
+    config
+}
+
+#[actix_web::test]
+async fn test_task_comment_threads() {
+    let config = comments_app_config();
+    let limits = config.limits.clone();
+    let state = AppState::new();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .wrap(ApiKeyAuth::new(ConfigHandle::new(
+                config,
+                ConfigSources::default(),
+            )))
+            .configure(|cfg| configure_routes(cfg, &limits)),
+    )
+    .await;
+
+    let call = |method: test::TestRequest, uri: &str, key: &'static str| {
+        method
+            .uri(uri)
+            .insert_header(("X-API-Key", key))
+            .to_request()
+    };
+    let task: Task = test::call_and_read_body_json(
+        &app,
+        call(
+            test::TestRequest::post().set_json(json!({"title": "Discuss"})),
+            "/api/v1/tasks",
+            "alice-key",
+        ),
+    )
+    .await;
+    let uri = format!("/api/v1/tasks/{}/comments", task.id);
+
+    let mut ids = Vec::new();
+    for body in ["First **draft**", "Second", "Third"] {
+        let resp = test::call_service(
+            &app,
+            call(
+                test::TestRequest::post().set_json(json!({ "body": body })),
+                &uri,
+                "alice-key",
+            ),
+        )
+        .await;
+        assert_eq!(resp.status(), 201);
+        let comment: Comment = test::read_body_json(resp).await;
+        assert_eq!(comment.author_id, "alice");
+        ids.push(comment.id);
+    }
+    let resp = test::call_service(
+        &app,
+        call(
+            test::TestRequest::post().set_json(json!({"body": "  "})),
+            &uri,
+            "alice-key",
+        ),
+    )
+    .await;
+    assert_eq!(resp.status(), 400);
+
+    // Other members cannot see the task, so neither its comments
+    let resp = test::call_service(&app, call(test::TestRequest::get(), &uri, "bob-key")).await;
+    assert_eq!(resp.status(), 404);
+
+    // Pages follow the `next` cursor, oldest first
+    let page: CommentPage = test::call_and_read_body_json(
+        &app,
+        call(
+            test::TestRequest::get(),
+            &format!("{}?limit=2", uri),
+            "alice-key",
+        ),
+    )
+    .await;
+    assert_eq!(
+        page.comments.iter().map(|c| c.id).collect::<Vec<_>>(),
+        ids[..2]
+    );
+    assert_eq!(page.next, Some(ids[1]));
+    let page: CommentPage = test::call_and_read_body_json(
+        &app,
+        call(
+            test::TestRequest::get(),
+            &format!("{}?limit=2&after={}", uri, ids[1]),
+            "alice-key",
+        ),
+    )
+    .await;
+    assert_eq!(page.comments.len(), 1);
+    assert_eq!(page.next, None);
+
+    // Edits keep the previous body
+    let first = format!("{}/{}", uri, ids[0]);
+    let edited: Comment = test::call_and_read_body_json(
+        &app,
+        call(
+            test::TestRequest::put().set_json(json!({"body": "First, final"})),
+            &first,
+            "alice-key",
+        ),
+    )
+    .await;
+    assert_eq!(edited.body, "First, final");
+    assert_eq!(edited.edits.len(), 1);
+    assert_eq!(edited.edits[0].body, "First **draft**");
+
+    // Admins may comment on any task and moderate, but not edit as the author
+    let resp = test::call_service(
+        &app,
+        call(
+            test::TestRequest::post().set_json(json!({"body": "Ping"})),
+            &uri,
+            "admin-key",
+        ),
+    )
+    .await;
+    assert_eq!(resp.status(), 201);
+    let admin_comment: Comment = test::read_body_json(resp).await;
+    let resp = test::call_service(
+        &app,
+        call(
+            test::TestRequest::put().set_json(json!({"body": "Pong"})),
+            &format!("{}/{}", uri, admin_comment.id),
+            "alice-key",
+        ),
+    )
+    .await;
+    assert_eq!(resp.status(), 403);
+
+    // Soft-deleted comments disappear from reads
+    let resp =
+        test::call_service(&app, call(test::TestRequest::delete(), &first, "admin-key")).await;
+    assert_eq!(resp.status(), 204);
+    let resp = test::call_service(&app, call(test::TestRequest::get(), &first, "alice-key")).await;
+    assert_eq!(resp.status(), 404);
+    let page: CommentPage =
+        test::call_and_read_body_json(&app, call(test::TestRequest::get(), &uri, "alice-key"))
+            .await;
+    assert_eq!(page.comments.len(), 3);
+    assert!(page.comments.iter().all(|c| c.id != ids[0]));
+
+    let operations: Vec<String> = state
+        .audit
+        .query(DEFAULT_TENANT, &AuditQuery::default())
+        .into_iter()
+        .map(|record| record.operation)
+        .filter(|operation| operation.starts_with("comment."))
+        .collect();
+    assert!(operations.contains(&"comment.update".to_string()));
+    assert!(operations.contains(&"comment.delete".to_string()));
+}
+
+#[actix_web::test]
+async fn test_comments_follow_task_deletion() {
+    let config = comments_app_config();
+    let limits = config.limits.clone();
+    let state = AppState::new();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .wrap(ApiKeyAuth::new(ConfigHandle::new(
+                config,
+                ConfigSources::default(),
+            )))
+            .configure(|cfg| configure_routes(cfg, &limits)),
+    )
+    .await;
+
+    let call = |method: test::TestRequest, uri: &str| {
+        method
+            .uri(uri)
+            .insert_header(("X-API-Key", "admin-key"))
+            .to_request()
+    };
+    let task: Task = test::call_and_read_body_json(
+        &app,
+        call(
+            test::TestRequest::post().set_json(json!({"title": "Keep"})),
+            "/api/v1/tasks",
+        ),
+    )
+    .await;
+    let uri = format!("/api/v1/tasks/{}", task.id);
+    let comments = format!("{}/comments", uri);
+    let resp = test::call_service(
+        &app,
+        call(
+            test::TestRequest::post().set_json(json!({"body": "Noted"})),
+            &comments,
+        ),
+    )
+    .await;
+    assert_eq!(resp.status(), 201);
+
+    // Trashing hides the thread; restoring brings it back
+    let resp = test::call_service(&app, call(test::TestRequest::delete(), &uri)).await;
+    assert_eq!(resp.status(), 204);
+    let resp = test::call_service(&app, call(test::TestRequest::get(), &comments)).await;
+    assert_eq!(resp.status(), 404);
+    let resp = test::call_service(
+        &app,
+        call(test::TestRequest::post(), &format!("{}:restore", uri)),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let page: CommentPage =
+        test::call_and_read_body_json(&app, call(test::TestRequest::get(), &comments)).await;
+    assert_eq!(page.comments.len(), 1);
+
+    // A hard delete removes the comments and their stored bytes
+    let tenant = state.tenants.get(DEFAULT_TENANT).unwrap();
+    assert!(tenant.usage.stored_bytes() > task.stored_bytes());
+    let resp = test::call_service(
+        &app,
+        call(test::TestRequest::delete(), &format!("{}?hard=true", uri)),
+    )
+    .await;
+    assert_eq!(resp.status(), 204);
+    assert_eq!(tenant.usage.stored_bytes(), 0);
+    let page = tenant.comments.page(&task.id, &Default::default()).unwrap();
+    assert!(page.comments.is_empty());
+}
+
+fn attachments_config(name: &str) -> Config {
+    let mut config = comments_app_config();
+    let dir = std::env::temp_dir().join(format!("{}-{}", name, uuid::Uuid::new_v4()));
+    config.attachments.path = dir.to_string_lossy().into_owned();
+    config.attachments.allowed_types = "image/*,text/plain".to_string();
+    config.limits.attachment_max_bytes = 1024;
+    config
+}
+
+fn multipart_body(files: &[(&str, &str, &str)]) -> (String, Vec<u8>) {
+    let boundary = "attachment-boundary";
+    let mut body = Vec::new();
+    for (filename, content_type, content) in files {
+        write!(
+            body,
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n{}\r\n",
+            boundary, filename, content_type, content
+        )
+        .unwrap();
+    }
+    write!(body, "--{}--\r\n", boundary).unwrap();
+    (format!("multipart/form-data; boundary={}", boundary), body)
+}
+
+#[actix_web::test]
+async fn test_task_attachments_upload_and_download() {
+    let config = attachments_config("attachments");
+    let limits = config.limits.clone();
+    let state = AppState::new();
+    state.attachments.configure(&config.attachments).unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .wrap(ApiKeyAuth::new(ConfigHandle::new(
+                config.clone(),
+                ConfigSources::default(),
+            )))
+            .configure(|cfg| configure_routes(cfg, &limits)),
+    )
+    .await;
+
+    let call = |method: test::TestRequest, uri: &str, key: &'static str| {
+        method
+            .uri(uri)
+            .insert_header(("X-API-Key", key))
+            .to_request()
+    };
+    let task: Task = test::call_and_read_body_json(
+        &app,
+        call(
+            test::TestRequest::post().set_json(json!({"title": "Crash"})),
+            "/api/v1/tasks",
+            "alice-key",
+        ),
+    )
+    .await;
+    let uri = format!("/api/v1/tasks/{}/attachments", task.id);
+
+    // Multipart uploads attach one file per part
+    let (content_type, body) = multipart_body(&[
+        ("crash.log", "text/plain", "panic at line 42"),
+        ("../screenshot.png", "image/png", "not really a png"),
+    ]);
+    let resp = test::call_service(
+        &app,
+        call(
+            test::TestRequest::post()
+                .insert_header(("Content-Type", content_type))
+                .set_payload(body),
+            &uri,
+            "alice-key",
+        ),
+    )
+    .await;
+    assert_eq!(resp.status(), 201);
+    let uploaded: Vec<Attachment> = test::read_body_json(resp).await;
+    assert_eq!(uploaded.len(), 2);
+    assert_eq!(uploaded[0].size, 16);
+    assert_eq!(uploaded[1].filename, "screenshot.png");
+    assert_eq!(uploaded[1].content_type, "image/png");
+
+    // A raw upload of the same content shares its blob
+    let raw = format!("{}?filename=r%C3%A9sum%C3%A9.txt", uri);
+    let resp = test::call_service(
+        &app,
+        call(
+            test::TestRequest::post()
+                .insert_header(("Content-Type", "text/plain"))
+                .set_payload("panic at line 42"),
+            &raw,
+            "alice-key",
+        ),
+    )
+    .await;
+    assert_eq!(resp.status(), 201);
+    let same: Vec<Attachment> = test::read_body_json(resp).await;
+    assert_eq!(same[0].sha256, uploaded[0].sha256);
+    assert_eq!(state.attachments.store().list().unwrap().len(), 2);
+    let listed: Vec<Attachment> =
+        test::call_and_read_body_json(&app, call(test::TestRequest::get(), &uri, "alice-key"))
+            .await;
+    assert_eq!(listed.len(), 3);
+
+    // Types outside the allowlist and bodies over the limit are refused
+    let upload = |content_type: &'static str, body: Vec<u8>| {
+        call(
+            test::TestRequest::post()
+                .insert_header(("Content-Type", content_type))
+                .set_payload(body),
+            &raw,
+            "alice-key",
+        )
+    };
+    let resp = test::call_service(&app, upload("application/x-msdownload", b"MZ".to_vec())).await;
+    assert_eq!(resp.status(), 415);
+    let resp = test::call_service(&app, upload("text/plain", vec![b'x'; 2048])).await;
+    assert_eq!(resp.status(), 413);
+    let resp = test::call_service(
+        &app,
+        call(test::TestRequest::post().set_payload("x"), &uri, "bob-key"),
+    )
+    .await;
+    assert_eq!(resp.status(), 404);
+
+    // Downloads name the file and honour ranges
+    let content = format!("{}/{}/content", uri, same[0].id);
+    let resp =
+        test::call_service(&app, call(test::TestRequest::get(), &content, "alice-key")).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+        resp.headers().get("content-disposition").unwrap(),
+        "attachment; filename=\"r_sum_.txt\"; filename*=UTF-8''r%C3%A9sum%C3%A9.txt"
+    );
+    assert_eq!(resp.headers().get("accept-ranges").unwrap(), "bytes");
+    assert_eq!(test::read_body(resp).await, "panic at line 42");
+
+    let ranged = |range: &'static str| {
+        call(
+            test::TestRequest::get().insert_header(("Range", range)),
+            &content,
+            "alice-key",
+        )
+    };
+    let resp = test::call_service(&app, ranged("bytes=6-7")).await;
+    assert_eq!(resp.status(), 206);
+    assert_eq!(resp.headers().get("content-range").unwrap(), "bytes 6-7/16");
+    assert_eq!(test::read_body(resp).await, "at");
+    let resp = test::call_service(&app, ranged("bytes=-2")).await;
+    assert_eq!(test::read_body(resp).await, "42");
+    let resp = test::call_service(&app, ranged("bytes=100-")).await;
+    assert_eq!(resp.status(), 416);
+    assert_eq!(resp.headers().get("content-range").unwrap(), "bytes */16");
+
+    let tenant = state.tenants.get(DEFAULT_TENANT).unwrap();
+    assert_eq!(
+        tenant.usage.stored_bytes(),
+        task.stored_bytes() + 16 + 16 + 16
+    );
+    std::fs::remove_dir_all(&config.attachments.path).unwrap();
+}
+
+#[actix_web::test]
+async fn test_unreferenced_blobs_are_collected() {
+    let config = attachments_config("attachments-gc");
+    let limits = config.limits.clone();
+    let state = AppState::new();
+    state.attachments.configure(&config.attachments).unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .wrap(ApiKeyAuth::new(ConfigHandle::new(
+                config.clone(),
+                ConfigSources::default(),
+            )))
+            .configure(|cfg| configure_routes(cfg, &limits)),
+    )
+    .await;
+
+    let call = |method: test::TestRequest, uri: &str| {
+        method
+            .uri(uri)
+            .insert_header(("X-API-Key", "admin-key"))
+            .to_request()
+    };
+    let mut tasks = Vec::new();
+    for title in ["First", "Second"] {
+        let task: Task = test::call_and_read_body_json(
+            &app,
+            call(
+                test::TestRequest::post().set_json(json!({ "title": title })),
+                "/api/v1/tasks",
+            ),
+        )
+        .await;
+        let uri = format!("/api/v1/tasks/{}/attachments?filename=trace.txt", task.id);
+        let resp = test::call_service(
+            &app,
+            call(
+                test::TestRequest::post()
+                    .insert_header(("Content-Type", "text/plain"))
+                    .set_payload("shared"),
+                &uri,
+            ),
+        )
+        .await;
+        assert_eq!(resp.status(), 201);
+        tasks.push(task);
+    }
+    let unique = format!(
+        "/api/v1/tasks/{}/attachments?filename=other.txt",
+        tasks[0].id
+    );
+    let resp = test::call_service(
+        &app,
+        call(
+            test::TestRequest::post()
+                .insert_header(("Content-Type", "text/plain"))
+                .set_payload("unique"),
+            &unique,
+        ),
+    )
+    .await;
+    let unique: Vec<Attachment> = test::read_body_json(resp).await;
+    let store = state.attachments.store();
+    assert_eq!(store.list().unwrap().len(), 2);
+
+    // Blobs of trashed tasks are kept for a restore
+    let first = format!("/api/v1/tasks/{}", tasks[0].id);
+    let resp = test::call_service(&app, call(test::TestRequest::delete(), &first)).await;
+    assert_eq!(resp.status(), 204);
+    assert_eq!(
+        state.attachments.collect_garbage(&state.tenants).unwrap(),
+        0
+    );
+
+    // Once purged, only content no other task uses goes
+    let resp = test::call_service(
+        &app,
+        call(
+            test::TestRequest::delete(),
+            &format!("/api/v1/trash/{}", tasks[0].id),
+        ),
+    )
+    .await;
+    assert_eq!(resp.status(), 204);
+    assert_eq!(
+        state.attachments.collect_garbage(&state.tenants).unwrap(),
+        1
+    );
+    let remaining = store.list().unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_ne!(remaining[0], unique[0].sha256);
+
+    // Deleting the last attachment using a blob frees it too
+    let second = format!("/api/v1/tasks/{}/attachments", tasks[1].id);
+    let listed: Vec<Attachment> =
+        test::call_and_read_body_json(&app, call(test::TestRequest::get(), &second)).await;
+    let resp = test::call_service(
+        &app,
+        call(
+            test::TestRequest::delete(),
+            &format!("{}/{}", second, listed[0].id),
+        ),
+    )
+    .await;
+    assert_eq!(resp.status(), 204);
+    assert_eq!(
+        state.attachments.collect_garbage(&state.tenants).unwrap(),
+        1
+    );
+    assert!(store.list().unwrap().is_empty());
+
+    let tenant = state.tenants.get(DEFAULT_TENANT).unwrap();
+    assert_eq!(tenant.usage.stored_bytes(), tasks[1].stored_bytes());
+    std::fs::remove_dir_all(&config.attachments.path).unwrap();
+}